@@ -0,0 +1,285 @@
+//! Opening books in the Polyglot format. A book is a sorted list of 16 byte
+//! entries, each a position's key, a move and a weight, all big-endian.
+//!
+//! Polyglot keys are Zobrist hashes over a fixed table of 781 random numbers
+//! from the Polyglot specification. The table isn't bundled with the crate,
+//! so it is loaded alongside the book, as the big-endian bytes of the 781
+//! numbers in their usual order.
+
+use std::cell::RefCell;
+
+use chess::{
+    BitBoard, Board, ChessMove, Color, File, MoveGen, Piece, Rank, Square, ALL_SQUARES, EMPTY,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::input::{board_from_fen, InputError};
+use crate::notation::format_coordinate_move;
+use crate::{get_best_move_minimax_alpha_beta, splitmix64};
+
+/// How many random numbers the Polyglot key is built from: one for each piece
+/// on each square, four castling rights, eight en passant files and the side
+/// to move.
+const RANDOM_COUNT: usize = 781;
+
+/// Where the castling rights' random numbers start in the table.
+const CASTLING_OFFSET: usize = 768;
+
+/// Where the en passant files' random numbers start in the table.
+const EN_PASSANT_OFFSET: usize = 772;
+
+/// The random number hashed in when White is to move.
+const TURN_OFFSET: usize = 780;
+
+/// The size of a book entry in bytes.
+const ENTRY_SIZE: usize = 16;
+
+/// One move of a book: the key of the position it is played from, the move
+/// in Polyglot's encoding and how often it should be picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BookEntry {
+    key: u64,
+    raw_move: u16,
+    weight: u16,
+}
+
+thread_local! {
+    /// The random numbers Polyglot keys are built from, once loaded.
+    static RANDOMS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+
+    /// The loaded book's entries, sorted by key.
+    static BOOK: RefCell<Vec<BookEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Read a big-endian unsigned integer of `N` bytes.
+fn read_be<const N: usize>(bytes: &[u8]) -> u64 {
+    bytes[..N]
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+/// Polyglot's index for a piece: pawns to kings, Black's before White's.
+fn piece_kind(piece: Piece, color: Color) -> usize {
+    2 * piece.to_index() + (color == Color::White) as usize
+}
+
+/// The Polyglot key of a position, built from the given random numbers. An
+/// en passant square only counts when a pawn of the side to move stands next
+/// to the pawn that just moved two squares.
+fn polyglot_key(position: &Board, randoms: &[u64]) -> u64 {
+    let mut key = 0;
+    for square in ALL_SQUARES {
+        if let (Some(piece), Some(color)) = (position.piece_on(square), position.color_on(square)) {
+            key ^= randoms[64 * piece_kind(piece, color) + square.to_index()];
+        }
+    }
+
+    let rights = [
+        position.castle_rights(Color::White).has_kingside(),
+        position.castle_rights(Color::White).has_queenside(),
+        position.castle_rights(Color::Black).has_kingside(),
+        position.castle_rights(Color::Black).has_queenside(),
+    ];
+    for (i, _) in rights.iter().enumerate().filter(|(_, &right)| right) {
+        key ^= randoms[CASTLING_OFFSET + i];
+    }
+
+    if let Some(pawn) = position.en_passant() {
+        let side = position.side_to_move();
+        let own_pawns = position.pieces(Piece::Pawn) & position.color_combined(side);
+        let beside = [pawn.left(), pawn.right()];
+        if beside
+            .iter()
+            .flatten()
+            .any(|&square| own_pawns & BitBoard::from_square(square) != EMPTY)
+        {
+            key ^= randoms[EN_PASSANT_OFFSET + pawn.get_file().to_index()];
+        }
+    }
+
+    if position.side_to_move() == Color::White {
+        key ^= randoms[TURN_OFFSET];
+    }
+    key
+}
+
+/// Decode a Polyglot move against the position it is played in, returning
+/// None if it isn't legal there. Castling is encoded as the king taking its
+/// own rook, so it is turned into the king's two square move.
+fn decode_move(position: &Board, raw_move: u16) -> Option<ChessMove> {
+    let square = |bits: u16| {
+        Square::make_square(
+            Rank::from_index((bits >> 3 & 7) as usize),
+            File::from_index((bits & 7) as usize),
+        )
+    };
+    let source = square(raw_move >> 6);
+    let mut dest = square(raw_move);
+    let promotion = match raw_move >> 12 & 7 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+
+    let castling = position.piece_on(source) == Some(Piece::King)
+        && position.piece_on(dest) == Some(Piece::Rook)
+        && position.color_on(dest) == position.color_on(source);
+    if castling {
+        let file = if dest.get_file() == File::H {
+            File::G
+        } else {
+            File::C
+        };
+        dest = Square::make_square(source.get_rank(), file);
+    }
+
+    let m = ChessMove::new(source, dest, promotion);
+    MoveGen::new_legal(position).find(|&legal| legal == m)
+}
+
+/// Pick one of the book's moves for the position, each with a chance in
+/// proportion to its weight, from a PRNG seeded with `seed`. Returns None if
+/// the position isn't in the book, no random numbers are loaded, or none of
+/// its moves has any weight.
+fn book_move(position: &Board, seed: u64) -> Option<ChessMove> {
+    let key = RANDOMS.with(|randoms| {
+        let randoms = randoms.borrow();
+        (randoms.len() == RANDOM_COUNT).then(|| polyglot_key(position, &randoms))
+    })?;
+
+    let candidates: Vec<(ChessMove, u64)> = BOOK.with(|book| {
+        let book = book.borrow();
+        let start = book.partition_point(|entry| entry.key < key);
+        book[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(position, entry.raw_move)?, entry.weight as u64)))
+            .collect()
+    });
+    let total: u64 = candidates.iter().map(|&(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut pick = splitmix64(seed ^ key) % total;
+    for (m, weight) in candidates {
+        if pick < weight {
+            return Some(m);
+        }
+        pick -= weight;
+    }
+    None
+}
+
+/// Exposed to javascript to load the random numbers Polyglot keys are built
+/// from: the 781 numbers of the Polyglot specification's Random64 table, in
+/// order, each as 8 big-endian bytes. Needed before any book can be consulted.
+/// Throws if there aren't exactly 781 numbers.
+#[wasm_bindgen]
+pub fn load_polyglot_randoms(bytes: &[u8]) -> Result<(), InputError> {
+    if bytes.len() != 8 * RANDOM_COUNT {
+        return Err(InputError::InvalidBook(format!(
+            "expected {} bytes of random numbers, got {}",
+            8 * RANDOM_COUNT,
+            bytes.len()
+        )));
+    }
+    let randoms = bytes.chunks(8).map(read_be::<8>).collect();
+    RANDOMS.with(|loaded| *loaded.borrow_mut() = randoms);
+    Ok(())
+}
+
+/// Exposed to javascript to load a Polyglot opening book from the bytes of its
+/// `.bin` file, replacing any book loaded before. Throws if the length isn't a
+/// whole number of 16 byte entries.
+#[wasm_bindgen]
+pub fn load_polyglot_book(bytes: &[u8]) -> Result<(), InputError> {
+    if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+        return Err(InputError::InvalidBook(format!(
+            "length {} isn't a whole number of {} byte entries",
+            bytes.len(),
+            ENTRY_SIZE
+        )));
+    }
+    let mut entries: Vec<BookEntry> = bytes
+        .chunks(ENTRY_SIZE)
+        .map(|chunk| BookEntry {
+            key: read_be::<8>(chunk),
+            raw_move: read_be::<2>(&chunk[8..]) as u16,
+            weight: read_be::<2>(&chunk[10..]) as u16,
+        })
+        .collect();
+    // Books are meant to be sorted already, but one that isn't still works.
+    entries.sort_by_key(|entry| entry.key);
+    BOOK.with(|book| *book.borrow_mut() = entries);
+    Ok(())
+}
+
+/// Exposed to javascript to play from the loaded Polyglot book. If the
+/// position is in the book, returns one of its moves picked in proportion to
+/// their weights by a PRNG seeded with `seed`; otherwise returns the best move
+/// searched to `depth`, like `get_best_move_minimax_alpha_beta`. Throws if the
+/// FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_with_book(
+    current_position: &str,
+    depth: u32,
+    seed: u64,
+) -> Result<String, InputError> {
+    let position = board_from_fen(current_position)?;
+    match book_move(&position, seed) {
+        Some(m) => Ok(format_coordinate_move(&m)),
+        None => get_best_move_minimax_alpha_beta(current_position, depth),
+    }
+}
+
+// A tiny book keyed with stand-in random numbers: the booked move is played in
+// the starting position, castling is decoded from king-takes-rook, and
+// positions outside the book are searched.
+#[test]
+fn plays_booked_moves() {
+    use std::str::FromStr;
+
+    let mut z = 0;
+    let randoms: Vec<u64> = (0..RANDOM_COUNT)
+        .map(|_| {
+            z = splitmix64(z);
+            z
+        })
+        .collect();
+    let random_bytes: Vec<u8> = randoms.iter().flat_map(|r| r.to_be_bytes()).collect();
+    load_polyglot_randoms(&random_bytes).unwrap();
+
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let castle = "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let entry = |fen: &str, raw_move: u16, weight: u16| {
+        let key = polyglot_key(&Board::from_str(fen).unwrap(), &randoms);
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend(raw_move.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend([0; 4]);
+        bytes
+    };
+    // e2e4 is from 12 to 28, d2d4 from 11 to 27, e1h1 from 4 to 7.
+    let book = [
+        entry(start, 12 << 6 | 28, 1),
+        entry(start, 11 << 6 | 27, 0),
+        entry(castle, 4 << 6 | 7, 1),
+    ]
+    .concat();
+    load_polyglot_book(&book).unwrap();
+
+    for seed in 0..4 {
+        assert_eq!(get_best_move_with_book(start, 0, seed).unwrap(), "e2 e4");
+    }
+    assert_eq!(get_best_move_with_book(castle, 0, 0).unwrap(), "e1 g1");
+
+    let after = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+    assert_eq!(
+        get_best_move_with_book(after, 0, 0).unwrap(),
+        get_best_move_minimax_alpha_beta(after, 0).unwrap()
+    );
+    assert!(load_polyglot_book(&book[1..]).is_err());
+}
@@ -0,0 +1,142 @@
+//! Evaluation of heavy pieces working together. Scores are White-relative.
+
+use chess::{between, get_rank, BitBoard, Board, Color, Piece, Rank, Square};
+
+use crate::color_sign;
+use crate::king_safety::piece_attacks;
+
+/// Bonus for two heavy pieces (rooks or a rook and queen) lined up on a file
+/// or rank with nothing between them.
+const BATTERY_BONUS: i32 = 20;
+
+/// Extra bonus for a battery on the opponent's back two ranks, where it hits
+/// the pawns and king from the side.
+const ADVANCED_RANK_BATTERY_BONUS: i32 = 40;
+
+/// Bonus for two rooks defending each other along a rank or file with nothing
+/// between them, ready to double on whichever file opens up.
+const CONNECTED_ROOKS_BONUS: i32 = 15;
+
+/// Penalty for a rook hemmed in by its own pieces, without a single move. A
+/// rook in its corner with the right to castle on its side isn't counted, as
+/// castling will bring it out.
+const BOXED_ROOK_PENALTY: i32 = 20;
+
+/// Whether a rank has no pawns of either color on it.
+fn is_open_rank(position: &Board, rank: Rank) -> bool {
+    (get_rank(rank) & position.pieces(Piece::Pawn)).popcnt() == 0
+}
+
+/// Whether the given color's heavy pieces on `a` and `b` form a battery along
+/// a file, or along a rank free of pawns, with nothing standing between them.
+fn is_battery(position: &Board, a: Square, b: Square) -> bool {
+    let same_file = a.get_file() == b.get_file();
+    let same_open_rank = a.get_rank() == b.get_rank() && is_open_rank(position, a.get_rank());
+    (same_file || same_open_rank) && (between(a, b) & position.combined()).popcnt() == 0
+}
+
+/// Generate a value rewarding rooks and queens that support each other along
+/// a file or an open rank, counting every pair that includes a rook (two
+/// queens being rare enough to ignore). Rank batteries on the opponent's
+/// seventh or back rank get an extra bonus.
+pub fn heavy_piece_batteries(position: &Board) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let sign = color_sign(color);
+        let own = position.color_combined(color);
+        let rooks = position.pieces(Piece::Rook) & own;
+        let heavy: Vec<Square> = ((position.pieces(Piece::Queen) & own) | rooks).collect();
+
+        // The opponent's back rank and the one in front of it.
+        let advanced = [(!color).to_my_backrank(), (!color).to_second_rank()];
+
+        for (i, &a) in heavy.iter().enumerate() {
+            for &b in &heavy[i + 1..] {
+                let has_rook =
+                    (rooks & (BitBoard::from_square(a) | BitBoard::from_square(b))).popcnt() > 0;
+                if !has_rook || !is_battery(position, a, b) {
+                    continue;
+                }
+
+                score += sign * BATTERY_BONUS;
+                let on_rank = a.get_rank() == b.get_rank();
+                if on_rank && advanced.contains(&a.get_rank()) {
+                    score += sign * ADVANCED_RANK_BATTERY_BONUS;
+                }
+            }
+        }
+    }
+
+    score
+}
+
+/// Generate a value for how ready each side's rooks are to work together:
+/// a bonus when the two rooks see each other along a rank or file, and a
+/// penalty for each rook boxed in by its own pieces with no moves at all and no
+/// castling to free it.
+pub fn rook_coordination(position: &Board) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let sign = color_sign(color);
+        let own = position.color_combined(color);
+        let rooks: Vec<Square> = (position.pieces(Piece::Rook) & own).collect();
+        let castling_rooks = position.castle_rights(color).unmoved_rooks(color);
+
+        for (i, &a) in rooks.iter().enumerate() {
+            for &b in &rooks[i + 1..] {
+                let lined_up = a.get_file() == b.get_file() || a.get_rank() == b.get_rank();
+                if lined_up && (between(a, b) & position.combined()).popcnt() == 0 {
+                    score += sign * CONNECTED_ROOKS_BONUS;
+                }
+            }
+
+            let boxed = (piece_attacks(position, Piece::Rook, color, a) & !own).popcnt() == 0;
+            if boxed && (castling_rooks & BitBoard::from_square(a)).popcnt() == 0 {
+                score -= sign * BOXED_ROOK_PENALTY;
+            }
+        }
+    }
+
+    score
+}
+
+// Rooks doubled on the seventh rank beat rooks split across the board, and a
+// rank full of pawns doesn't count as a battery.
+#[test]
+fn doubled_rooks_on_seventh() {
+    use std::str::FromStr;
+
+    let doubled = Board::from_str("6k1/RR6/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    let split = Board::from_str("6k1/R7/8/8/8/1R6/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(
+        heavy_piece_batteries(&doubled),
+        BATTERY_BONUS + ADVANCED_RANK_BATTERY_BONUS
+    );
+    assert_eq!(heavy_piece_batteries(&split), 0);
+    assert!(
+        crate::position_evaluation(&doubled, &Default::default())
+            > crate::position_evaluation(&split, &Default::default())
+    );
+
+    let behind_pawns = Board::from_str("6k1/8/8/8/8/8/R1P2PPP/1R4K1 w - - 0 1").unwrap();
+    let blocked = Board::from_str("6k1/8/8/8/8/8/RP3PPP/6RK w - - 0 1").unwrap();
+    assert_eq!(heavy_piece_batteries(&behind_pawns), 0);
+    assert_eq!(heavy_piece_batteries(&blocked), 0);
+}
+
+// Rooks connected along the back rank beat rooks split by their own minor
+// pieces and boxed into the corners.
+#[test]
+fn connected_rooks_beat_boxed_rooks() {
+    use std::str::FromStr;
+
+    let connected = Board::from_str("6k1/5ppp/8/8/8/8/P4PPP/3RR1K1 w - - 0 1").unwrap();
+    let boxed = Board::from_str("6k1/5ppp/8/8/8/8/P4PPP/RB3BKR w - - 0 1").unwrap();
+    assert_eq!(rook_coordination(&connected), CONNECTED_ROOKS_BONUS);
+    assert_eq!(rook_coordination(&boxed), -2 * BOXED_ROOK_PENALTY);
+
+    let split = Board::from_str("6k1/5ppp/8/8/8/8/P4PPP/R2B1RK1 w - - 0 1").unwrap();
+    assert_eq!(rook_coordination(&split), 0);
+    let start = Board::default();
+    assert_eq!(rook_coordination(&start), 0);
+}
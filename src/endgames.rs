@@ -0,0 +1,349 @@
+//! Knowledge of specific endgames the search can't work out for itself.
+
+use chess::{between, get_file, get_pawn_attacks, BitBoard, Board, Color, File, Piece, Square};
+
+use crate::pawns::passed_pawns;
+use crate::{color_sign, distance, game_phase, piece_score, PHASE_MAX};
+
+/// The light squares of the board (b1, d1, ..., a2, c2, ...).
+pub const LIGHT_SQUARES: BitBoard = BitBoard(0x55aa_55aa_55aa_55aa);
+
+/// The share of the eval kept, in percent, in an opposite colored bishop
+/// ending where the side ahead has a single extra pawn.
+const OPPOSITE_BISHOPS_SCALE: i32 = 25;
+
+/// The share of the eval kept, in percent, with opposite colored bishops and
+/// other pieces still on the board, once those pieces are down to the least
+/// there can be. With more material on it is scaled back up towards the full
+/// eval, as the bishops are then as good for attacking as they are bad for
+/// converting.
+const OPPOSITE_BISHOPS_MIDDLEGAME_SCALE: i32 = 75;
+
+/// The share of the eval kept, in percent, when the side ahead only has rook
+/// pawns and a bishop that can't cover their promotion square, and the
+/// defending king has reached it.
+const WRONG_BISHOP_SCALE: i32 = 10;
+
+/// The share of the eval kept, in percent, when a lone pawn is lost: the
+/// defending king is in its square and far nearer to it than the pawn's own
+/// king, so it will take the pawn and draw.
+const LOST_LONE_PAWN_SCALE: i32 = 0;
+
+/// Bonus for winning the race to promote in a pawn ending: nearly a queen,
+/// since nothing can stop the pawn becoming one.
+const UNSTOPPABLE_PASSER_BONUS: i32 = 700;
+
+/// The share of the eval to keep, in percent, given how drawish the position
+/// is despite the material. Recognised fortresses scale the eval towards zero,
+/// so the engine neither gives up holdable positions nor overrates ones it
+/// can't win.
+pub fn drawish_scale(position: &Board) -> i32 {
+    if is_opposite_bishops_with_extra_pawn(position) {
+        OPPOSITE_BISHOPS_SCALE
+    } else if is_wrong_bishop_fortress(position) {
+        WRONG_BISHOP_SCALE
+    } else if is_lost_lone_pawn(position) {
+        LOST_LONE_PAWN_SCALE
+    } else if has_opposite_bishops(position) {
+        let taken_off = PHASE_MAX - game_phase(position);
+        100 - (100 - OPPOSITE_BISHOPS_MIDDLEGAME_SCALE) * taken_off / PHASE_MAX
+    } else {
+        100
+    }
+}
+
+/// Whether only kings and pawns are left on the board.
+fn is_pawn_ending(position: &Board) -> bool {
+    let kings_and_pawns = position.pieces(Piece::King) | position.pieces(Piece::Pawn);
+    *position.combined() == kings_and_pawns
+}
+
+/// Whether the given color is at risk of zugzwang, where every move it has
+/// makes its position worse and passing would be better. That takes few
+/// pieces with few waiting moves between them: no rook or queen and at most
+/// one minor piece, or no more than three men in all.
+pub fn in_zugzwang_risk(position: &Board, color: Color) -> bool {
+    let own = position.color_combined(color);
+    let minors = (position.pieces(Piece::Knight) | position.pieces(Piece::Bishop)) & own;
+    let majors = (position.pieces(Piece::Rook) | position.pieces(Piece::Queen)) & own;
+    (majors.popcnt() == 0 && minors.popcnt() <= 1) || own.popcnt() <= 3
+}
+
+/// The number of moves the given color's pawn on `square` needs to promote,
+/// counting the double step from its starting rank.
+fn moves_to_promote(square: Square, color: Color) -> u32 {
+    let rank = square.get_rank().to_index() as u32;
+    let ranks_to_go = match color {
+        Color::White => 7 - rank,
+        Color::Black => rank,
+    };
+    if square.get_rank() == color.to_second_rank() {
+        ranks_to_go - 1
+    } else {
+        ranks_to_go
+    }
+}
+
+/// The square the given color's pawn on `square` promotes on.
+fn promotion_square(square: Square, color: Color) -> Square {
+    Square::make_square((!color).to_my_backrank(), square.get_file())
+}
+
+/// Whether the given color's pawn on `square` outruns the enemy king to its
+/// promotion square (the rule of the square), the king getting a move's head
+/// start when it is to move. Pieces in the pawn's way aren't considered.
+fn outruns_king(position: &Board, square: Square, color: Color) -> bool {
+    let enemy_king = position.king_square(!color);
+    let enemy_to_move = position.side_to_move() != color;
+    let king_moves = distance(enemy_king, promotion_square(square, color)) as u32;
+    king_moves.saturating_sub(enemy_to_move as u32) > moves_to_promote(square, color)
+}
+
+/// The fewest moves any of the given color's passed pawns needs to promote
+/// without the enemy king being able to catch it (the rule of the square),
+/// or None if every passer can be caught. Pawns with a piece in their path
+/// are ignored, as are undefended pawns the enemy king can take at once.
+fn fastest_unstoppable_passer(position: &Board, color: Color) -> Option<u32> {
+    let enemy_king = position.king_square(!color);
+    let enemy_to_move = position.side_to_move() != color;
+    let own_pawns = pieces(position, Piece::Pawn, color);
+
+    passed_pawns(position, color)
+        .filter(|&pawn| {
+            let promotion = promotion_square(pawn, color);
+            let path = between(pawn, promotion) | BitBoard::from_square(promotion);
+            let defended = distance(position.king_square(color), pawn) == 1
+                || (get_pawn_attacks(pawn, !color, own_pawns)).popcnt() > 0;
+            let taken = enemy_to_move && distance(enemy_king, pawn) == 1 && !defended;
+            (path & position.combined()).popcnt() == 0
+                && !taken
+                && outruns_king(position, pawn, color)
+        })
+        .map(|pawn| moves_to_promote(pawn, color))
+        .min()
+}
+
+/// Whether a king and pawn against king ending is drawn because the lone king
+/// catches the pawn and gets to it well before the pawn's own king can defend
+/// it: three moves sooner, or four for a pawn that can still make a double
+/// step. Checked against the exact results for every such position, it never
+/// calls a win a draw.
+fn is_lost_lone_pawn(position: &Board) -> bool {
+    let pawns = position.pieces(Piece::Pawn);
+    if position.combined().popcnt() != 3 || pawns.popcnt() != 1 {
+        return false;
+    }
+    let pawn = pawns.to_square();
+    let color = position.color_on(pawn).unwrap();
+    if outruns_king(position, pawn, color) {
+        return false;
+    }
+
+    let to_move = |side: Color| (position.side_to_move() == side) as i32;
+    let defender_moves = distance(position.king_square(!color), pawn) - to_move(!color);
+    let supporter_moves = distance(position.king_square(color), pawn) - to_move(color);
+    let margin = if pawn.get_rank() == color.to_second_rank() {
+        3
+    } else {
+        2
+    };
+    defender_moves + margin < supporter_moves
+}
+
+/// Generate a large bonus for the side winning a promotion race in a pawn
+/// ending: the side with an uncatchable passed pawn, or if both have one, the
+/// side that promotes first. Material counts alone can't see that a single
+/// far advanced pawn outweighs everything else on the board.
+pub fn promotion_race(position: &Board) -> i32 {
+    if !is_pawn_ending(position) {
+        return 0;
+    }
+
+    let winner = match (
+        fastest_unstoppable_passer(position, Color::White),
+        fastest_unstoppable_passer(position, Color::Black),
+    ) {
+        (Some(_), None) => Color::White,
+        (None, Some(_)) => Color::Black,
+        // Equal races go to the side to move, which gets there a move sooner.
+        (Some(white), Some(black)) if white == black => position.side_to_move(),
+        (Some(white), Some(black)) if white < black => Color::White,
+        (Some(_), Some(_)) => Color::Black,
+        (None, None) => return 0,
+    };
+    color_sign(winner) * UNSTOPPABLE_PASSER_BONUS
+}
+
+/// The pieces of the given type and color.
+fn pieces(position: &Board, piece: Piece, color: Color) -> BitBoard {
+    position.pieces(piece) & position.color_combined(color)
+}
+
+/// Whether each side has a single bishop, with the two on opposite colors.
+fn has_opposite_bishops(position: &Board) -> bool {
+    let white_bishop = pieces(position, Piece::Bishop, Color::White);
+    let black_bishop = pieces(position, Piece::Bishop, Color::Black);
+    if white_bishop.popcnt() != 1 || black_bishop.popcnt() != 1 {
+        return false;
+    }
+    let white_on_light = (white_bishop & LIGHT_SQUARES).popcnt() == 1;
+    let black_on_light = (black_bishop & LIGHT_SQUARES).popcnt() == 1;
+    white_on_light != black_on_light
+}
+
+/// Whether each side has just a king, a bishop and pawns, with the bishops on
+/// opposite colors and no more than a pawn between the sides.
+fn is_opposite_bishops_with_extra_pawn(position: &Board) -> bool {
+    let others = position.pieces(Piece::Knight)
+        | position.pieces(Piece::Rook)
+        | position.pieces(Piece::Queen);
+    if others.popcnt() > 0 || !has_opposite_bishops(position) {
+        return false;
+    }
+
+    let pawn_difference = pieces(position, Piece::Pawn, Color::White).popcnt() as i32
+        - pieces(position, Piece::Pawn, Color::Black).popcnt() as i32;
+    pawn_difference.abs() <= 1
+}
+
+/// Whether the side ahead has only a bishop and pawns on one rook file, the
+/// bishop can't control the promotion square, and the lone defending king is
+/// already next to (or on) that square.
+fn is_wrong_bishop_fortress(position: &Board) -> bool {
+    let strong = match piece_score(position) {
+        0 => return false,
+        score if score > 0 => Color::White,
+        _ => Color::Black,
+    };
+    let weak = !strong;
+
+    // The defender must have a bare king, the attacker a king, bishop and
+    // pawns.
+    if position.color_combined(weak).popcnt() != 1 {
+        return false;
+    }
+    let bishop = pieces(position, Piece::Bishop, strong);
+    let pawns = pieces(position, Piece::Pawn, strong);
+    if bishop.popcnt() != 1
+        || pawns.popcnt() == 0
+        || position.color_combined(strong).popcnt() != 2 + pawns.popcnt()
+    {
+        return false;
+    }
+
+    let file = match [File::A, File::H]
+        .into_iter()
+        .find(|&file| pawns & get_file(file) == pawns)
+    {
+        Some(file) => file,
+        None => return false,
+    };
+    let promotion_square = Square::make_square(weak.to_my_backrank(), file);
+    let promotes_on_light = (BitBoard::from_square(promotion_square) & LIGHT_SQUARES).popcnt() == 1;
+    let bishop_on_light = (bishop & LIGHT_SQUARES).popcnt() == 1;
+
+    promotes_on_light != bishop_on_light
+        && distance(position.king_square(weak), promotion_square) <= 1
+}
+
+// An extra pawn with opposite colored bishops is pulled most of the way
+// towards a draw, while the same pawn with bishops of the same color is not.
+#[test]
+fn opposite_bishops_scale_towards_draw() {
+    use std::str::FromStr;
+
+    let opposite = Board::from_str("8/5k2/3b4/8/3P4/4K3/4B3/8 w - - 0 1").unwrap();
+    let same = Board::from_str("8/5k2/2b5/8/3P4/4K3/4B3/8 w - - 0 1").unwrap();
+    assert_eq!(drawish_scale(&opposite), OPPOSITE_BISHOPS_SCALE);
+    assert_eq!(drawish_scale(&same), 100);
+
+    let weights = Default::default();
+    let opposite_eval = crate::position_evaluation(&opposite, &weights);
+    assert!(opposite_eval > 0);
+    assert!(opposite_eval < piece_score(&opposite));
+    assert!(opposite_eval < crate::position_evaluation(&same, &weights));
+}
+
+// With rooks still on, opposite colored bishops pull the eval a little towards
+// a draw, less so than in a pure bishop ending and more so as pieces come off.
+#[test]
+fn opposite_bishops_middlegame_scale() {
+    use std::str::FromStr;
+
+    let opposite = Board::from_str("2r3k1/5ppp/2b5/8/3P4/4B3/5PPP/2R3K1 w - - 0 1").unwrap();
+    let same = Board::from_str("2r3k1/5ppp/3b4/8/3P4/4B3/5PPP/2R3K1 w - - 0 1").unwrap();
+    let fewer = Board::from_str("6k1/5ppp/2b5/8/3P4/4B3/5PPP/2R3K1 w - - 0 1").unwrap();
+    let opposite_scale = drawish_scale(&opposite);
+    assert!(opposite_scale < 100 && opposite_scale > OPPOSITE_BISHOPS_MIDDLEGAME_SCALE);
+    assert_eq!(drawish_scale(&same), 100);
+    assert!(drawish_scale(&fewer) < opposite_scale);
+
+    let weights = Default::default();
+    let opposite_eval = crate::position_evaluation(&opposite, &weights);
+    assert!(opposite_eval > 0);
+    assert!(opposite_eval < crate::position_evaluation(&same, &weights));
+}
+
+// A rook pawn with the wrong colored bishop can't be forced home once the
+// defending king reaches the corner.
+#[test]
+fn wrong_bishop_fortress() {
+    use std::str::FromStr;
+
+    let wrong = Board::from_str("7k/8/8/7P/8/3B4/8/6K1 w - - 0 1").unwrap();
+    let right = Board::from_str("7k/8/8/7P/5B2/8/8/6K1 w - - 0 1").unwrap();
+    let far_king = Board::from_str("8/8/8/3k3P/8/3B4/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(drawish_scale(&wrong), WRONG_BISHOP_SCALE);
+    assert_eq!(drawish_scale(&right), 100);
+    assert_eq!(drawish_scale(&far_king), 100);
+}
+
+// A passed pawn outside the enemy king's square is as good as a queen, but one
+// the king can reach in time is just a pawn.
+#[test]
+fn unstoppable_passed_pawn() {
+    use std::str::FromStr;
+
+    let unstoppable = Board::from_str("7k/7p/8/P7/8/8/8/7K w - - 0 1").unwrap();
+    let caught = Board::from_str("8/2k4p/8/P7/8/8/8/7K w - - 0 1").unwrap();
+    assert_eq!(promotion_race(&unstoppable), UNSTOPPABLE_PASSER_BONUS);
+    assert_eq!(promotion_race(&caught), 0);
+
+    // Moving first is just enough for the king to get back in the square.
+    let just_caught = Board::from_str("8/7p/4k3/P7/8/8/8/7K b - - 0 1").unwrap();
+    let just_missed = Board::from_str("8/7p/4k3/P7/8/8/8/7K w - - 0 1").unwrap();
+    assert_eq!(promotion_race(&just_caught), 0);
+    assert_eq!(promotion_race(&just_missed), UNSTOPPABLE_PASSER_BONUS);
+
+    assert!(crate::evaluate("7k/7p/8/P7/8/8/8/7K w - - 0 1", 2).unwrap() > 50);
+}
+
+// A lone pawn outside the defending king's square wins, but one the king
+// catches, with its own king too far away to help, is a draw. A pawn the king
+// can take at once doesn't outrun it either.
+#[test]
+fn rule_of_the_square() {
+    use std::str::FromStr;
+
+    let outruns = Board::from_str("8/8/8/8/P7/8/7k/K7 w - - 0 1").unwrap();
+    assert_eq!(promotion_race(&outruns), UNSTOPPABLE_PASSER_BONUS);
+    assert_eq!(drawish_scale(&outruns), 100);
+    assert!(crate::evaluate("8/8/8/8/P7/8/7k/K7 w - - 0 1", 0).unwrap() > 700);
+
+    let caught = Board::from_str("8/8/1k6/8/P7/8/8/7K w - - 0 1").unwrap();
+    assert_eq!(promotion_race(&caught), 0);
+    assert_eq!(drawish_scale(&caught), LOST_LONE_PAWN_SCALE);
+    assert_eq!(
+        crate::evaluate("8/8/1k6/8/P7/8/8/7K w - - 0 1", 0).unwrap(),
+        0
+    );
+
+    // With White's king close enough to defend the pawn it isn't written off.
+    let supported = Board::from_str("8/8/1k6/8/P7/1K6/8/8 w - - 0 1").unwrap();
+    assert_eq!(drawish_scale(&supported), 100);
+
+    let taken = Board::from_str("8/8/8/8/8/8/2P5/K1k5 b - - 0 1").unwrap();
+    assert_eq!(promotion_race(&taken), 0);
+    let black_pawn = Board::from_str("k7/8/8/8/8/8/p7/7K b - - 0 1").unwrap();
+    assert_eq!(promotion_race(&black_pawn), -UNSTOPPABLE_PASSER_BONUS);
+}
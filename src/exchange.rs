@@ -0,0 +1,124 @@
+//! Static exchange evaluation: the material a capture wins or loses once every
+//! piece able to recapture on the square has had its turn.
+
+use std::cmp;
+
+use chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, ChessMove, Color, Piece, Square, EMPTY,
+};
+
+use crate::{game_phase, phased_value};
+
+/// The pieces in the order they are sent in to recapture, cheapest first.
+const CAPTURE_ORDER: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+/// Every piece of either color attacking `square`, given which squares are
+/// still occupied. Sliders are looked up against `occupied`, so a piece
+/// behind one that has already captured joins in.
+pub fn attackers_to(position: &Board, square: Square, occupied: BitBoard) -> BitBoard {
+    let white = position.color_combined(Color::White);
+    let black = position.color_combined(Color::Black);
+    let pawns = position.pieces(Piece::Pawn);
+    let diagonal = position.pieces(Piece::Bishop) | position.pieces(Piece::Queen);
+    let straight = position.pieces(Piece::Rook) | position.pieces(Piece::Queen);
+
+    let attackers = get_pawn_attacks(square, Color::Black, pawns & white)
+        | get_pawn_attacks(square, Color::White, pawns & black)
+        | (get_knight_moves(square) & position.pieces(Piece::Knight))
+        | (get_bishop_moves(square, occupied) & diagonal)
+        | (get_rook_moves(square, occupied) & straight)
+        | (get_king_moves(square) & position.pieces(Piece::King));
+    attackers & occupied
+}
+
+/// The value of a piece in the exchange, in centipawns at the
+/// position's game phase. The king can't be captured, so it is never counted
+/// as a victim, and as an attacker it only goes in last.
+fn exchange_value(piece: Piece, phase: i32) -> i32 {
+    match piece {
+        Piece::King => 0,
+        piece => phased_value(piece, phase),
+    }
+}
+
+/// The material (in centipawns) the side to move comes out with after
+/// the capture `m` and the best sequence of recaptures on its square, each
+/// side capturing with its cheapest piece and free to stop whenever
+/// recapturing would lose. Pins are ignored. A promotion's gain is counted.
+pub fn static_exchange(position: &Board, m: ChessMove) -> i32 {
+    let phase = game_phase(position);
+    let (source, target) = (m.get_source(), m.get_dest());
+    let attacker = position.piece_on(source).unwrap_or(Piece::Pawn);
+    let mut occupied = *position.combined() ^ BitBoard::from_square(source);
+
+    // A pawn landing on an empty square diagonally is capturing en passant,
+    // and takes the pawn beside it off the board.
+    let victim = match position.piece_on(target) {
+        Some(victim) => victim,
+        None => {
+            let captured = target.backward(position.side_to_move()).unwrap();
+            occupied ^= BitBoard::from_square(captured);
+            Piece::Pawn
+        }
+    };
+
+    let promotion_gain = m.get_promotion().map_or(0, |piece| {
+        exchange_value(piece, phase) - exchange_value(Piece::Pawn, phase)
+    });
+    let mut gains = vec![exchange_value(victim, phase) + promotion_gain];
+    let mut on_square = m.get_promotion().unwrap_or(attacker);
+    let mut side = !position.side_to_move();
+    loop {
+        let attackers = attackers_to(position, target, occupied);
+        let own = attackers & position.color_combined(side);
+        let Some((piece, from)) = CAPTURE_ORDER.iter().find_map(|&piece| {
+            let candidates = own & position.pieces(piece);
+            (candidates != EMPTY).then(|| (piece, candidates.to_square()))
+        }) else {
+            break;
+        };
+        // The king can only recapture if nothing is left to take it back.
+        if piece == Piece::King && attackers & position.color_combined(!side) != EMPTY {
+            break;
+        }
+
+        gains.push(exchange_value(on_square, phase) - gains.last().unwrap());
+        on_square = piece;
+        occupied ^= BitBoard::from_square(from);
+        side = !side;
+    }
+
+    // Either side may stand pat rather than make a losing recapture, so work
+    // back from the end of the sequence.
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.last_mut().unwrap();
+        *previous = -cmp::max(-*previous, last);
+    }
+    gains[0]
+}
+
+// A rook lined up behind the one that captures joins the exchange once the
+// first has gone, so doubled rooks win a pawn defended by a single rook.
+#[test]
+fn x_ray_recapture() {
+    use std::str::FromStr;
+
+    let position = Board::from_str("4k3/4r3/8/8/4p3/8/4R3/4R1K1 w - - 0 1").unwrap();
+    let m = ChessMove::new(Square::E2, Square::E4, None);
+    assert_eq!(
+        static_exchange(&position, m),
+        phased_value(Piece::Pawn, game_phase(&position))
+    );
+
+    let single = Board::from_str("4k3/4r3/8/8/4p3/8/4R3/6K1 w - - 0 1").unwrap();
+    assert!(static_exchange(&single, m) < 0);
+}
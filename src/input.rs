@@ -0,0 +1,410 @@
+//! Validation of the arguments javascript passes in, with errors that become
+//! exceptions on the javascript side.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chess::{
+    get_king_moves, BitBoard, Board, BoardBuilder, CastleRights, Color, File, Piece, Square,
+    ALL_SQUARES,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::exchange::attackers_to;
+use crate::notation::SanError;
+use crate::tuning::WeightsError;
+
+/// The reasons an entry point can reject its arguments. Returned from an
+/// exported function, it is thrown in javascript as an `Error` carrying the
+/// displayed message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputError {
+    /// The FEN couldn't be parsed, or describes an impossible position, for
+    /// the given reason.
+    InvalidFen(String, FenError),
+    /// The color wasn't "white" or "black".
+    InvalidColor(String),
+    /// The move couldn't be played in the position, for the given reason.
+    InvalidMove(String, SanError),
+    /// The coordinate move couldn't be read at all.
+    MalformedMove(String),
+    /// The move is legal, but doesn't capture anything.
+    NotACapture(String),
+    /// The move is legal, but doesn't lead to the position it was said to.
+    MoveMismatch(String),
+    /// The evaluation weights couldn't be read, for the given reason.
+    InvalidWeights(WeightsError),
+    /// The opening book data couldn't be loaded, for the given reason.
+    InvalidBook(String),
+    /// The position has more pieces than the function handles.
+    TooManyPieces(String),
+    /// The text isn't a square with a piece on it.
+    NotAnOccupiedSquare(String),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::InvalidFen(fen, reason) => write!(f, "invalid FEN \"{}\": {}", fen, reason),
+            InputError::InvalidColor(color) => {
+                write!(f, "invalid color \"{}\", expected white or black", color)
+            }
+            InputError::InvalidMove(text, reason) => write!(f, "{} \"{}\"", reason, text),
+            InputError::MalformedMove(text) => write!(f, "malformed move \"{}\"", text),
+            InputError::NotACapture(text) => write!(f, "move \"{}\" isn't a capture", text),
+            InputError::MoveMismatch(text) => {
+                write!(
+                    f,
+                    "move \"{}\" doesn't lead to the resulting position",
+                    text
+                )
+            }
+            InputError::InvalidWeights(reason) => write!(f, "invalid weights: {}", reason),
+            InputError::InvalidBook(reason) => write!(f, "invalid book: {}", reason),
+            InputError::TooManyPieces(fen) => write!(f, "too many pieces in \"{}\"", fen),
+            InputError::NotAnOccupiedSquare(text) => {
+                write!(f, "\"{}\" isn't a square with a piece on it", text)
+            }
+        }
+    }
+}
+
+impl From<InputError> for JsValue {
+    fn from(error: InputError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+/// The reasons a FEN can be rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// There are fewer than the four fields up to the en passant square.
+    MissingFields,
+    /// The piece placement doesn't describe eight ranks of eight squares.
+    MalformedPlacement,
+    /// The side to move isn't "w" or "b".
+    InvalidSideToMove,
+    /// The castling rights aren't "-" or some of "KQkq".
+    InvalidCastling,
+    /// The en passant field isn't "-" or a square behind a pawn that could
+    /// just have moved two squares.
+    InvalidEnPassant,
+    /// A move counter isn't a number.
+    InvalidCounter,
+    /// The given color has no king.
+    MissingKing(Color),
+    /// The given color has more than one king.
+    TooManyKings(Color),
+    /// The given color has more than sixteen pieces.
+    TooManyPieces(Color),
+    /// The given color has more than eight pawns.
+    TooManyPawns(Color),
+    /// A pawn stands on the first or eighth rank.
+    PawnOnBackRank,
+    /// The kings stand next to each other.
+    KingsTouching,
+    /// The side that just moved left its king in check.
+    OpponentInCheck,
+    /// A side has castling rights with its king or rook off its starting
+    /// square.
+    CastlingPiecesMoved,
+    /// There is no pawn in front of the en passant square to be captured.
+    EnPassantWithoutPawn,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color_name = |color: &Color| match color {
+            Color::White => "white",
+            Color::Black => "black",
+        };
+        match self {
+            FenError::MissingFields => write!(f, "missing fields"),
+            FenError::MalformedPlacement => write!(f, "malformed piece placement"),
+            FenError::InvalidSideToMove => write!(f, "invalid side to move"),
+            FenError::InvalidCastling => write!(f, "invalid castling rights"),
+            FenError::InvalidEnPassant => write!(f, "invalid en passant square"),
+            FenError::InvalidCounter => write!(f, "invalid move counter"),
+            FenError::MissingKing(color) => write!(f, "missing {} king", color_name(color)),
+            FenError::TooManyKings(color) => write!(f, "too many {} kings", color_name(color)),
+            FenError::TooManyPieces(color) => write!(f, "too many {} pieces", color_name(color)),
+            FenError::TooManyPawns(color) => write!(f, "too many {} pawns", color_name(color)),
+            FenError::PawnOnBackRank => write!(f, "pawn on the first or eighth rank"),
+            FenError::KingsTouching => write!(f, "kings next to each other"),
+            FenError::OpponentInCheck => write!(f, "side not to move is in check"),
+            FenError::CastlingPiecesMoved => {
+                write!(f, "castling rights without the king and rook in place")
+            }
+            FenError::EnPassantWithoutPawn => write!(f, "en passant square without a pawn"),
+        }
+    }
+}
+
+/// The piece and color a FEN piece letter stands for.
+fn piece_from_letter(letter: char) -> Option<(Piece, Color)> {
+    let piece = match letter.to_ascii_lowercase() {
+        'p' => Piece::Pawn,
+        'n' => Piece::Knight,
+        'b' => Piece::Bishop,
+        'r' => Piece::Rook,
+        'q' => Piece::Queen,
+        'k' => Piece::King,
+        _ => return None,
+    };
+    let color = if letter.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((piece, color))
+}
+
+/// Check the piece placement field: eight ranks of eight squares, with one
+/// king and no more than sixteen pieces and eight pawns a side, and no pawns
+/// on the back ranks. The `chess` crate takes in placements which are
+/// nothing like a real position, so these are checked before it sees them.
+fn check_placement(placement: &str) -> Result<(), FenError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::MalformedPlacement);
+    }
+
+    // Piece counts indexed by color, then piece.
+    let mut counts = [[0; 6]; 2];
+    for (index, rank) in ranks.iter().enumerate() {
+        let mut squares = 0;
+        for letter in rank.chars() {
+            if let Some(empty @ 1..=8) = letter.to_digit(10) {
+                squares += empty;
+                continue;
+            }
+            let (piece, color) = piece_from_letter(letter).ok_or(FenError::MalformedPlacement)?;
+            if piece == Piece::Pawn && (index == 0 || index == 7) {
+                return Err(FenError::PawnOnBackRank);
+            }
+            counts[color.to_index()][piece.to_index()] += 1;
+            squares += 1;
+        }
+        if squares != 8 {
+            return Err(FenError::MalformedPlacement);
+        }
+    }
+
+    for color in [Color::White, Color::Black] {
+        let counts = counts[color.to_index()];
+        match counts[Piece::King.to_index()] {
+            0 => return Err(FenError::MissingKing(color)),
+            1 => {}
+            _ => return Err(FenError::TooManyKings(color)),
+        }
+        if counts.iter().sum::<u32>() > 16 {
+            return Err(FenError::TooManyPieces(color));
+        }
+        if counts[Piece::Pawn.to_index()] > 8 {
+            return Err(FenError::TooManyPawns(color));
+        }
+    }
+    Ok(())
+}
+
+/// Find out why the `chess` crate rejects a FEN whose fields are well formed,
+/// which it reports no more specifically than as an invalid board.
+fn diagnose_position(fen: &str) -> FenError {
+    let builder = match BoardBuilder::from_str(fen) {
+        Ok(builder) => builder,
+        Err(_) => return FenError::MalformedPlacement,
+    };
+    let king_square = |color: Color| {
+        ALL_SQUARES
+            .into_iter()
+            .find(|&square| builder[square] == Some((Piece::King, color)))
+    };
+    let (white_king, black_king) = match (king_square(Color::White), king_square(Color::Black)) {
+        (Some(white_king), Some(black_king)) => (white_king, black_king),
+        _ => return FenError::MalformedPlacement,
+    };
+    if (get_king_moves(white_king) & BitBoard::from_square(black_king)).popcnt() > 0 {
+        return FenError::KingsTouching;
+    }
+
+    for color in [Color::White, Color::Black] {
+        let rights = builder.get_castle_rights(color);
+        let back_rank = color.to_my_backrank();
+        let rook_home = |file: File| builder[Square::make_square(back_rank, file)];
+        let king_home = Square::make_square(back_rank, File::E);
+        let rooks_home = (!rights.has_kingside()
+            || rook_home(File::H) == Some((Piece::Rook, color)))
+            && (!rights.has_queenside() || rook_home(File::A) == Some((Piece::Rook, color)));
+        let king_home =
+            rights == CastleRights::NoRights || builder[king_home] == Some((Piece::King, color));
+        if !rooks_home || !king_home {
+            return FenError::CastlingPiecesMoved;
+        }
+    }
+
+    let mover = builder.get_side_to_move();
+    if let Some(pawn) = builder.get_en_passant() {
+        if builder[pawn] != Some((Piece::Pawn, !mover)) {
+            return FenError::EnPassantWithoutPawn;
+        }
+    }
+    FenError::OpponentInCheck
+}
+
+/// Parse a FEN, or say what is wrong with it.
+pub fn check_fen(fen: &str) -> Result<Board, FenError> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(FenError::MissingFields);
+    }
+    check_placement(fields[0])?;
+    if !matches!(fields[1], "w" | "b") {
+        return Err(FenError::InvalidSideToMove);
+    }
+
+    let castling = fields[2];
+    let repeated = |right: char| castling.matches(right).count() > 1;
+    if castling != "-"
+        && (castling.is_empty()
+            || !castling.chars().all(|right| "KQkq".contains(right))
+            || castling.chars().any(repeated))
+    {
+        return Err(FenError::InvalidCastling);
+    }
+
+    // The square a pawn passed over can only be on the third rank, behind a
+    // white pawn with Black to move, or the sixth behind a black pawn.
+    let en_passant_rank = if fields[1] == "w" { "6" } else { "3" };
+    if fields[3] != "-"
+        && (Square::from_str(fields[3]).is_err() || !fields[3].ends_with(en_passant_rank))
+    {
+        return Err(FenError::InvalidEnPassant);
+    }
+    if fields[4..]
+        .iter()
+        .take(2)
+        .any(|counter| counter.parse::<u32>().is_err())
+    {
+        return Err(FenError::InvalidCounter);
+    }
+
+    // The `chess` crate splits the fields on single spaces.
+    let fen = fields.join(" ");
+    let position = Board::from_str(&fen).map_err(|_| diagnose_position(&fen))?;
+
+    // The side to move could take the king, which no search makes sense of.
+    // Checked here too rather than relying on the `chess` crate to refuse it.
+    let mover = position.side_to_move();
+    let enemy_king = position.king_square(!mover);
+    let attackers = attackers_to(&position, enemy_king, *position.combined());
+    if (attackers & position.color_combined(mover)).popcnt() > 0 {
+        return Err(FenError::OpponentInCheck);
+    }
+    Ok(position)
+}
+
+/// Parse a FEN passed in from javascript.
+pub fn board_from_fen(fen: &str) -> Result<Board, InputError> {
+    check_fen(fen).map_err(|reason| InputError::InvalidFen(String::from(fen), reason))
+}
+
+/// Map "white" or "black" onto the color it names.
+pub fn color_from_name(color: &str) -> Result<Color, InputError> {
+    match color {
+        "white" => Ok(Color::White),
+        "black" => Ok(Color::Black),
+        _ => Err(InputError::InvalidColor(String::from(color))),
+    }
+}
+
+// The messages say what was wrong and with which argument.
+#[test]
+fn error_messages() {
+    assert_eq!(
+        board_from_fen("not a fen").unwrap_err().to_string(),
+        "invalid FEN \"not a fen\": missing fields"
+    );
+    assert_eq!(
+        color_from_name("green").unwrap_err().to_string(),
+        "invalid color \"green\", expected white or black"
+    );
+    assert_eq!(
+        InputError::InvalidMove(String::from("Nd2"), SanError::Ambiguous).to_string(),
+        "ambiguous move \"Nd2\""
+    );
+}
+
+// A FEN whose side to move could take the enemy king is rejected by every
+// entry point, rather than searched.
+#[test]
+fn rejects_capturable_king() {
+    let fen = "4k3/8/8/8/8/8/8/4R1K1 w - - 0 1";
+    assert_eq!(
+        board_from_fen(fen).unwrap_err().to_string(),
+        "invalid FEN \"4k3/8/8/8/8/8/8/4R1K1 w - - 0 1\": side not to move is in check"
+    );
+    assert!(crate::get_best_move_minimax_alpha_beta(fen, 1).is_err());
+    assert!(crate::evaluate(fen, 0).is_err());
+    assert!(!crate::queries::is_in_check(fen));
+    assert!(board_from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").is_ok());
+}
+
+// Each kind of invalid FEN is diagnosed for what it is.
+#[test]
+fn fen_diagnostics() {
+    let diagnose = |fen: &str| check_fen(fen).unwrap_err();
+    assert_eq!(diagnose("8/8/8/8 w - -"), FenError::MalformedPlacement);
+    assert_eq!(
+        diagnose("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenError::MissingKing(Color::Black)
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+        FenError::TooManyKings(Color::White)
+    );
+    assert_eq!(
+        diagnose("4k3/pppppppp/pppppppp/pp6/8/8/8/4K3 w - - 0 1"),
+        FenError::TooManyPieces(Color::Black)
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/P7/PPPPPPPP/8/4K3 w - - 0 1"),
+        FenError::TooManyPawns(Color::White)
+    );
+    assert_eq!(
+        diagnose("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenError::PawnOnBackRank
+    );
+    assert_eq!(
+        diagnose("8/8/8/3kK3/8/8/8/8 w - - 0 1"),
+        FenError::KingsTouching
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+        FenError::OpponentInCheck
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+        FenError::CastlingPiecesMoved
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+        FenError::InvalidSideToMove
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4K3 w KX - 0 1"),
+        FenError::InvalidCastling
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4K3 w - e4 0 1"),
+        FenError::InvalidEnPassant
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+        FenError::InvalidCounter
+    );
+    assert_eq!(
+        diagnose("4k3/8/8/3P4/8/8/8/4K3 w - e6 0 1"),
+        FenError::EnPassantWithoutPawn
+    );
+    assert!(check_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+}
@@ -0,0 +1,346 @@
+//! Evaluation of king safety. Scores are White-relative.
+
+use std::cmp;
+
+use chess::{
+    get_adjacent_files, get_bishop_moves, get_file, get_king_moves, get_knight_moves,
+    get_pawn_attacks, get_rank, get_rook_moves, BitBoard, Board, Color, File, Piece, Square,
+};
+
+use crate::pawns::ranks_ahead;
+use crate::{color_sign, game_phase, EvalWeights, PHASE_MAX};
+
+/// Bonus for each friendly pawn sheltering the king, at full middlegame phase.
+const SHELTER_PAWN_BONUS: i32 = 30;
+
+/// Bonus for each attack on a square next to the enemy king, at full
+/// middlegame phase.
+const KING_PRESSURE_WEIGHT: i32 = 10;
+
+/// Penalty for each file on or next to the king's file with no friendly pawns
+/// on it, at full middlegame phase.
+const HALF_OPEN_FILE_PENALTY: i32 = 20;
+
+/// Penalty for each file on or next to the king's file with no pawns on it at
+/// all, at full middlegame phase.
+const OPEN_FILE_PENALTY: i32 = 30;
+
+/// Penalty for each enemy pawn storming the king, on or next to its file and in
+/// front of it, at full middlegame phase. Indexed by how many ranks the pawn
+/// has advanced from its starting rank: the further it gets, the closer it is
+/// to prising open the king's shelter.
+const PAWN_STORM_PENALTY: [i32; 6] = [0, 0, 10, 20, 40, 40];
+
+/// Penalty for a king exposed to a back rank mate.
+const BACK_RANK_PENALTY: i32 = 50;
+
+/// Attack units each piece type adds when it attacks the enemy king's zone,
+/// indexed knight, bishop, rook, queen.
+const KING_ATTACK_UNITS: [i32; 4] = [2, 2, 3, 5];
+
+/// The share of the attack units counted, in percent, by the number of pieces
+/// taking part. A lone piece can rarely break through on its own, while each
+/// extra attacker makes the others more dangerous.
+const ATTACKER_COUNT_SCALE: [i32; 5] = [0, 0, 50, 75, 100];
+
+/// The danger to a king, at full middlegame phase, by the attack units aimed at
+/// its zone. It rises slowly at first and steeply once a real attack is on.
+const KING_DANGER_TABLE: [i32; 21] = [
+    0, 0, 10, 20, 30, 40, 60, 80, 100, 120, 150, 180, 210, 250, 290, 330, 380, 430, 480, 540, 600,
+];
+
+/// The squares a piece of the given type and color on `square` attacks.
+pub fn piece_attacks(position: &Board, piece: Piece, color: Color, square: Square) -> BitBoard {
+    let occupied = *position.combined();
+    match piece {
+        Piece::Pawn => get_pawn_attacks(square, color, !BitBoard::new(0)),
+        Piece::Knight => get_knight_moves(square),
+        Piece::Bishop => get_bishop_moves(square, occupied),
+        Piece::Rook => get_rook_moves(square, occupied),
+        Piece::Queen => get_bishop_moves(square, occupied) | get_rook_moves(square, occupied),
+        Piece::King => get_king_moves(square),
+    }
+}
+
+/// The king's square along with every square next to it.
+pub fn king_zone(position: &Board, color: Color) -> BitBoard {
+    let king = position.king_square(color);
+    get_king_moves(king) | BitBoard::from_square(king)
+}
+
+/// Count the friendly pawns sheltering the king: those on its own or the
+/// adjacent files, no more than two ranks in front of it.
+pub fn king_shelter(position: &Board, color: Color) -> i32 {
+    let king = position.king_square(color);
+    let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let near_ranks = ranks_ahead(king, color) & !two_ranks_beyond(king, color);
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    (files & near_ranks & pawns).popcnt() as i32
+}
+
+/// Every rank more than two in front of the square, from the point of view of
+/// the given color.
+fn two_ranks_beyond(square: Square, color: Color) -> BitBoard {
+    match square.forward(color).and_then(|s| s.forward(color)) {
+        Some(two_ahead) => ranks_ahead(two_ahead, color),
+        None => BitBoard::new(0),
+    }
+}
+
+/// Generate a penalty for the open and half-open files on or next to the
+/// king's file. Each file's penalty is multiplied up by the number of enemy
+/// rooks and queens standing on it, ready to use it.
+pub fn open_file_exposure(position: &Board, color: Color) -> i32 {
+    let king_file = position.king_square(color).get_file().to_index();
+    let pawns = position.pieces(Piece::Pawn);
+    let own_pawns = pawns & position.color_combined(color);
+    let enemy_heavy_pieces = (position.pieces(Piece::Rook) | position.pieces(Piece::Queen))
+        & position.color_combined(!color);
+
+    let mut exposure = 0;
+    for file in king_file.saturating_sub(1)..=cmp::min(king_file + 1, 7) {
+        let file_mask = get_file(File::from_index(file));
+        let penalty = if (file_mask & pawns).popcnt() == 0 {
+            OPEN_FILE_PENALTY
+        } else if (file_mask & own_pawns).popcnt() == 0 {
+            HALF_OPEN_FILE_PENALTY
+        } else {
+            continue;
+        };
+        exposure += penalty * (1 + (file_mask & enemy_heavy_pieces).popcnt() as i32);
+    }
+    exposure
+}
+
+/// Generate a penalty for the enemy pawns advancing on the given color's king:
+/// those on its file or the adjacent files, in front of it, each penalised by
+/// how far it has come.
+pub fn pawn_storm(position: &Board, color: Color) -> i32 {
+    let king = position.king_square(color);
+    let files = get_file(king.get_file()) | get_adjacent_files(king.get_file());
+    let enemy_pawns = position.pieces(Piece::Pawn) & position.color_combined(!color);
+
+    (files & ranks_ahead(king, color) & enemy_pawns)
+        .map(|pawn| {
+            let rank = pawn.get_rank().to_index();
+            let advanced = match !color {
+                Color::White => rank - 1,
+                Color::Black => 6 - rank,
+            };
+            PAWN_STORM_PENALTY[advanced]
+        })
+        .sum()
+}
+
+/// Count the attacks the given color's pieces make on the enemy king's zone.
+/// A square attacked by several pieces counts once for each of them.
+pub fn king_pressure(position: &Board, color: Color) -> i32 {
+    let zone = king_zone(position, !color);
+    let mut pressure = 0;
+    for piece in [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ] {
+        for square in position.pieces(piece) & position.color_combined(color) {
+            pressure += (piece_attacks(position, piece, color, square) & zone).popcnt() as i32;
+        }
+    }
+    pressure
+}
+
+/// Generate a penalty for the danger the enemy pieces pose to the given
+/// color's king. Every knight, bishop, rook and queen attacking the king's
+/// zone adds its attack units, the total is scaled by how many pieces take
+/// part, and the result is looked up in KING_DANGER_TABLE.
+pub fn king_danger(position: &Board, color: Color) -> i32 {
+    let zone = king_zone(position, color);
+    let enemy = position.color_combined(!color);
+
+    let (mut attackers, mut units) = (0, 0);
+    for (piece, piece_units) in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .into_iter()
+        .zip(KING_ATTACK_UNITS)
+    {
+        for square in position.pieces(piece) & enemy {
+            if (piece_attacks(position, piece, !color, square) & zone).popcnt() > 0 {
+                attackers += 1;
+                units += piece_units;
+            }
+        }
+    }
+
+    let scale = ATTACKER_COUNT_SCALE[cmp::min(attackers, ATTACKER_COUNT_SCALE.len() - 1)];
+    let index = (units * scale / 100) as usize;
+    KING_DANGER_TABLE[cmp::min(index, KING_DANGER_TABLE.len() - 1)]
+}
+
+/// Whether the given color's king is open to a back rank mate: it stands on its
+/// back rank, every square in front of it holds one of its own pawns, so it
+/// has no luft to escape to, and an enemy rook or queen has a clear line to
+/// the back rank.
+pub fn back_rank_weakness(position: &Board, color: Color) -> bool {
+    let king = position.king_square(color);
+    let back_rank = get_rank(color.to_my_backrank());
+    if king.get_rank() != color.to_my_backrank() {
+        return false;
+    }
+
+    let in_front = get_king_moves(king) & get_rank(color.to_second_rank());
+    let own_pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    if in_front & own_pawns != in_front {
+        return false;
+    }
+
+    let enemy_heavy_pieces = (position.pieces(Piece::Rook) | position.pieces(Piece::Queen))
+        & position.color_combined(!color);
+    enemy_heavy_pieces
+        .into_iter()
+        .any(|square| (get_rook_moves(square, *position.combined()) & back_rank).popcnt() > 0)
+}
+
+/// Generate a value for the back rank mate threats against both kings, a
+/// penalty for the side whose king is weak and so a reward for the side
+/// threatening it. Unlike the rest of king safety it doesn't fade in the
+/// endgame, where rook endings are full of back rank mates.
+pub fn back_rank_threats(position: &Board) -> i32 {
+    [Color::White, Color::Black]
+        .into_iter()
+        .filter(|&color| back_rank_weakness(position, color))
+        .map(|color| -color_sign(color) * BACK_RANK_PENALTY)
+        .sum()
+}
+
+/// Generate a value for the safety of both kings: how well each is sheltered
+/// by its own pawns, how exposed it is along open files and to enemy pawns
+/// storming it, and how much pressure
+/// and danger the enemy pieces put on it. Each side's components are scaled by
+/// its entry in the weights, and the whole term fades out as the game moves
+/// into the endgame.
+pub fn king_safety(position: &Board, weights: &EvalWeights) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let sign = color_sign(color);
+        let shelter = (king_shelter(position, color) * SHELTER_PAWN_BONUS
+            - open_file_exposure(position, color)
+            - pawn_storm(position, color))
+            * weights.king_shelter[color.to_index()];
+        let pressure = (king_pressure(position, color) * KING_PRESSURE_WEIGHT
+            + king_danger(position, !color))
+            * weights.king_pressure[color.to_index()];
+        score += sign * (shelter + pressure) / 100;
+    }
+
+    score * game_phase(position) / PHASE_MAX
+}
+
+// Pawns in front of a castled king shelter it, advanced ones don't.
+#[test]
+fn shelter_counts_nearby_pawns() {
+    use std::str::FromStr;
+
+    let castled = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(king_shelter(&castled, Color::White), 3);
+    assert_eq!(king_shelter(&castled, Color::Black), 3);
+
+    let advanced = Board::from_str("6k1/8/5ppp/8/5PPP/8/8/6K1 w - - 0 1").unwrap();
+    assert_eq!(king_shelter(&advanced, Color::White), 0);
+    assert_eq!(king_shelter(&advanced, Color::Black), 3);
+}
+
+// Open files next to the king are a liability, more so with an enemy rook on
+// one of them.
+#[test]
+fn open_files_expose_king() {
+    use std::str::FromStr;
+
+    let intact = Board::from_str("r2q2rk/5ppp/8/8/8/8/5PPP/R2Q1RK1 w - - 0 1").unwrap();
+    let open = Board::from_str("r2q3k/5pp1/8/8/8/8/5P1P/R2Q1RK1 w - - 0 1").unwrap();
+    let open_with_rook = Board::from_str("r2q2rk/5pp1/8/8/8/8/5P1P/R2Q1RK1 w - - 0 1").unwrap();
+    assert_eq!(open_file_exposure(&intact, Color::White), 0);
+    assert!(open_file_exposure(&open, Color::White) > 0);
+    assert!(
+        open_file_exposure(&open_with_rook, Color::White) > open_file_exposure(&open, Color::White)
+    );
+
+    let weights = EvalWeights::default();
+    assert!(king_safety(&intact, &weights) > king_safety(&open_with_rook, &weights));
+}
+
+// Enemy pawns marching up the board towards the king weigh on its safety, more
+// so the further they get.
+#[test]
+fn pawn_storm_against_king() {
+    use std::str::FromStr;
+
+    let home = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    let storming = Board::from_str("6k1/8/8/8/5ppp/8/5PPP/6K1 w - - 0 1").unwrap();
+    let close = Board::from_str("6k1/8/8/8/8/5ppp/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(pawn_storm(&home, Color::White), 0);
+    assert!(pawn_storm(&storming, Color::White) > 0);
+    assert!(pawn_storm(&close, Color::White) > pawn_storm(&storming, Color::White));
+    assert_eq!(pawn_storm(&storming, Color::Black), 0);
+
+    // King safety fades out in a pawn ending, so bring on the heavy pieces.
+    // The storm's open files weigh on Black's king too, so compare with the
+    // same pawns further from White's king.
+    let distant = Board::from_str("r2q2k1/8/8/5ppp/8/8/5PPP/R2Q2K1 w - - 0 1").unwrap();
+    let storming = Board::from_str("r2q2k1/8/8/8/5ppp/8/5PPP/R2Q2K1 w - - 0 1").unwrap();
+    let weights = EvalWeights::default();
+    assert!(king_safety(&distant, &weights) > king_safety(&storming, &weights));
+}
+
+// Pieces aimed at the king's surroundings increase the pressure on it.
+#[test]
+fn pressure_counts_attacks_on_king_zone() {
+    use std::str::FromStr;
+
+    let quiet = Board::from_str("6k1/5ppp/8/8/8/8/5PPP/Q5K1 w - - 0 1").unwrap();
+    let attacking = Board::from_str("6k1/5ppp/8/6N1/8/8/5PPP/3Q2K1 w - - 0 1").unwrap();
+    assert!(king_pressure(&attacking, Color::White) > king_pressure(&quiet, Color::White));
+}
+
+// A lone queen near the king is no danger by itself, but each piece that joins
+// in makes the attack more dangerous than the last.
+#[test]
+fn attackers_pile_up_king_danger() {
+    use std::str::FromStr;
+
+    let queen = Board::from_str("6k1/5ppp/8/8/8/3Q4/5PPP/6K1 w - - 0 1").unwrap();
+    let knight = Board::from_str("6k1/5ppp/8/6N1/8/3Q4/5PPP/6K1 w - - 0 1").unwrap();
+    let rook = Board::from_str("6k1/4Rppp/8/6N1/8/3Q4/5PPP/6K1 w - - 0 1").unwrap();
+    assert_eq!(king_danger(&queen, Color::Black), 0);
+    assert!(king_danger(&knight, Color::Black) > 0);
+    assert!(
+        king_danger(&rook, Color::Black) - king_danger(&knight, Color::Black)
+            > king_danger(&knight, Color::Black)
+    );
+    assert_eq!(king_danger(&rook, Color::White), 0);
+
+    let weights = EvalWeights::default();
+    assert!(king_safety(&rook, &weights) > king_safety(&knight, &weights));
+    assert!(king_safety(&knight, &weights) > king_safety(&queen, &weights));
+}
+
+// Pawns boxing in the king on its back rank are a liability with an enemy rook
+// on an open file, but not once one of them has stepped forward.
+#[test]
+fn back_rank_without_luft() {
+    use std::str::FromStr;
+
+    let boxed_in = Board::from_str("4r1k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+    let luft = Board::from_str("4r1k1/5ppp/8/8/8/7P/5PP1/6K1 w - - 0 1").unwrap();
+    assert!(back_rank_weakness(&boxed_in, Color::White));
+    assert!(!back_rank_weakness(&boxed_in, Color::Black));
+    assert!(!back_rank_weakness(&luft, Color::White));
+    assert_eq!(back_rank_threats(&boxed_in), -BACK_RANK_PENALTY);
+    assert_eq!(back_rank_threats(&luft), 0);
+
+    let weights = EvalWeights::default();
+    assert!(
+        crate::position_evaluation(&luft, &weights)
+            > crate::position_evaluation(&boxed_in, &weights)
+    );
+}
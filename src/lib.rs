@@ -1,50 +1,225 @@
+//! A chess engine compiled to WebAssembly, choosing moves with an alpha-beta
+//! minimax search.
+//!
+//! Every evaluation in the crate, from the individual terms up to the scores
+//! the search returns and the ones exposed to javascript, is White-relative:
+//! positive favours White and negative favours Black, whoever is to move. A
+//! term that looks at each side in turn scores it from that side's point of
+//! view and converts with `color_sign`. The search maximises for White and
+//! minimises for Black rather than negating scores, so the only places the
+//! side to move changes how a score is read are the comparisons at each node
+//! and the engine's own contempt for draws.
+
+use std::cell::{Cell, RefCell};
 use std::cmp;
+use std::mem;
 use std::str::FromStr;
 
-use chess::{BitBoard, Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
+use chess::{
+    between, get_bishop_rays, get_pawn_moves, get_rank, get_rook_rays, BitBoard, Board,
+    BoardBuilder, BoardStatus, CastleRights, ChessMove, Color, MoveGen, Piece, Rank, Square, EMPTY,
+};
+use input::{board_from_fen, InputError};
+use instant::{Duration, Instant};
+use score::{Score, MATE_SCORE};
+use transposition::{Bound, TranspositionTable, DEFAULT_HASH_SIZE_MB};
 use wasm_bindgen::prelude::*;
 
-/// Calculate the score as associated with traditional chess piece count.
+mod book;
+mod coordination;
+mod endgames;
+mod exchange;
+mod input;
+mod king_safety;
+mod mobility;
+mod motifs;
+mod notation;
+mod pawns;
+mod queries;
+mod retrograde;
+mod score;
+#[cfg(test)]
+mod tactics;
+mod transposition;
+mod tuning;
+mod zobrist;
+
+/// The pawn count around which the imbalance adjustments are centred.
+const IMBALANCE_PAWN_PIVOT: i32 = 8;
+
+/// How much (in centipawns) a knight's value shifts between a board with
+/// the pivot number of pawns and one with all sixteen or none. Knights need
+/// outposts and closed positions, so they get better with more pawns.
+const KNIGHT_PAWN_ADJUSTMENT: i32 = 20;
+
+/// As above for rooks. The engine's search is too shallow to convert rook
+/// endgames well, so rooks are valued slightly lower as the pawns disappear.
+const ROOK_PAWN_ADJUSTMENT: i32 = 30;
+
+/// The middlegame and endgame values of each piece type, in centipawns and
+/// indexed by `Piece::to_index` (the king's is never counted). Pawns gain
+/// the most as the board empties, since each is a potential queen, and
+/// bishops and rooks gain from the open lines. The middlegame values are the
+/// traditional 1, 3, 3, 5 and 9 pawns.
+const PIECE_VALUES: [(i32, i32); 5] = [(100, 130), (300, 290), (300, 330), (500, 530), (900, 940)];
+
+/// The value of a piece type at the given game phase, blending its middlegame
+/// and endgame values.
+fn phased_value(piece: Piece, phase: i32) -> i32 {
+    let (middlegame, endgame) = PIECE_VALUES[piece.to_index()];
+    (middlegame * phase + endgame * (PHASE_MAX - phase)) / PHASE_MAX
+}
+
+/// Calculate the score as associated with traditional chess piece count, in
+/// centipawns and adjusted for the material imbalance. Each piece's value is
+/// blended between its middlegame and endgame values by the game phase.
 fn piece_score(pos: &Board) -> i32 {
-    // Get the bitboards for the Black and White pieces.
     let white_pieces_bb = pos.color_combined(Color::White);
     let black_pieces_bb = pos.color_combined(Color::Black);
+    let phase = game_phase(pos);
+
+    let mut score = 0;
+    for piece in [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ] {
+        let value = phased_value(piece, phase);
+        let piece_bb = pos.pieces(piece);
+        let difference = (white_pieces_bb & piece_bb).popcnt() as i32
+            - (black_pieces_bb & piece_bb).popcnt() as i32;
+        score += value * difference;
+    }
 
-    // Get the bitboards for each piece type.
     let rook_bb = pos.pieces(Piece::Rook);
     let pawn_bb = pos.pieces(Piece::Pawn);
     let knight_bb = pos.pieces(Piece::Knight);
-    let bishop_bb = pos.pieces(Piece::Bishop);
-    let queen_bb = pos.pieces(Piece::Queen);
 
-    // Calculate the total value of all White pieces present.
-    let white_score = 5 * (white_pieces_bb & rook_bb).popcnt()
-        + (white_pieces_bb & pawn_bb).popcnt()
-        + 3 * (white_pieces_bb & bishop_bb).popcnt()
-        + 9 * (white_pieces_bb & queen_bb).popcnt()
-        + 3 * (white_pieces_bb & knight_bb).popcnt();
+    // Knights and rooks change in value with the number of pawns left on the
+    // board, scaled so the extremes of zero and sixteen pawns give the full
+    // adjustment.
+    let pawn_shift = pawn_bb.popcnt() as i32 - IMBALANCE_PAWN_PIVOT;
+    let knight_difference = (white_pieces_bb & knight_bb).popcnt() as i32
+        - (black_pieces_bb & knight_bb).popcnt() as i32;
+    let rook_difference =
+        (white_pieces_bb & rook_bb).popcnt() as i32 - (black_pieces_bb & rook_bb).popcnt() as i32;
+    let imbalance = (knight_difference * KNIGHT_PAWN_ADJUSTMENT
+        + rook_difference * ROOK_PAWN_ADJUSTMENT)
+        * pawn_shift
+        / IMBALANCE_PAWN_PIVOT;
+
+    // Material is counted in centipawns, like every evaluation term, and its
+    // values are far larger than the positional terms' so that it has a much
+    // higher effect on the evaluation of a given board state.
+    score + imbalance
+}
+
+/// The most the positional terms of the eval may add up to either way at the
+/// normal weights, five pawns. It is far more than they reach in practice, but
+/// a hard cap lets the search skip them once material alone settles a leaf.
+const LAZY_EVAL_MARGIN: i32 = 500;
+
+/// Scaling applied to the evaluation terms, in percent of their normal
+/// weight. Terms that apply to each side separately are indexed by color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct EvalWeights {
+    /// How much weight each side gives to its own king's pawn shelter.
+    king_shelter: [i32; 2],
+    /// How much weight each side gives to its pressure on the enemy king.
+    king_pressure: [i32; 2],
+    /// How much weight is given to getting the minor pieces developed.
+    development: i32,
+    /// How much weight is given to keeping the knights off the rim.
+    rim_knights: i32,
+    /// Whether mobility counts each side's legal moves exactly, rather than
+    /// the cheaper pseudo-legal moves read off the attack bitboards.
+    exact_mobility: bool,
+    /// The largest perturbation noise may add to or take from an eval, zero
+    /// for none.
+    noise_amplitude: i32,
+    /// Seed mixed into the position hash to pick each position's noise.
+    noise_seed: u64,
+}
+
+impl Default for EvalWeights {
+    fn default() -> EvalWeights {
+        EvalWeights {
+            king_shelter: [100; 2],
+            king_pressure: [100; 2],
+            development: 100,
+            rim_knights: 100,
+            exact_mobility: false,
+            noise_amplitude: 0,
+            noise_seed: 0,
+        }
+    }
+}
+
+impl EvalWeights {
+    /// Weights for a search starting at the given fullmove number, from the
+    /// tuned weights. Development gets its full weight in the opening and
+    /// fades out over the moves after it, as pieces left at home by then are
+    /// there for a reason.
+    fn for_move_number(fullmove_number: u32) -> EvalWeights {
+        let moves_past_opening = fullmove_number.saturating_sub(DEVELOPMENT_FULL_UNTIL);
+        let fade = cmp::min(moves_past_opening, DEVELOPMENT_FADE_MOVES);
+        let tuned = tuning::tuned_weights();
+        EvalWeights {
+            development: tuned.development * (DEVELOPMENT_FADE_MOVES - fade) as i32
+                / DEVELOPMENT_FADE_MOVES as i32,
+            ..tuned
+        }
+    }
+
+    /// The most the positional terms (everything but material, the promotion
+    /// race and noise) may add up to either way: LAZY_EVAL_MARGIN, scaled up by
+    /// the largest weight so that heavier weighting isn't cut off.
+    fn positional_cap(&self) -> i32 {
+        let largest = [
+            self.king_shelter[0],
+            self.king_shelter[1],
+            self.king_pressure[0],
+            self.king_pressure[1],
+            self.development,
+            self.rim_knights,
+            100,
+        ]
+        .into_iter()
+        .max()
+        .unwrap();
+        LAZY_EVAL_MARGIN * largest / 100
+    }
 
-    // Calculate the total value of all Black pieces present.
-    let black_score = 5 * (black_pieces_bb & rook_bb).popcnt()
-        + (black_pieces_bb & pawn_bb).popcnt()
-        + 3 * (black_pieces_bb & bishop_bb).popcnt()
-        + 9 * (black_pieces_bb & queen_bb).popcnt()
-        + 3 * (black_pieces_bb & knight_bb).popcnt();
+    /// These weights adjusted for an engine playing `color` with the given
+    /// aggression (in percent, 100 being the normal style). Higher values make
+    /// the engine value its attack on the enemy king more, and worry less
+    /// about its own king's shelter and the pressure put on it.
+    fn with_aggression(self, color: Color, aggression: u32) -> EvalWeights {
+        let aggression = cmp::max(aggression, 10) as i32;
+        let caution = 10000 / aggression;
 
-    // Return the difference between the black score and the white score.
-    (white_score as i32) - (black_score as i32)
+        let mut weights = self;
+        weights.king_pressure[color.to_index()] = aggression;
+        weights.king_pressure[(!color).to_index()] = caution;
+        weights.king_shelter[color.to_index()] = caution;
+        weights
+    }
 }
 
-/// Take a ChessMove object and formats it as a string describing a move between
-/// two squares.
-fn format_best_move(m: &ChessMove) -> String {
-    // TODO - Refactor this function to handle the case where a promotion occurs
-    // (detailing what we want to promote to).
-    format!("{} {}", &m.get_source(), &m.get_dest())
+/// The sign that turns a score from `color`'s point of view into a White
+/// relative one.
+fn color_sign(color: Color) -> i32 {
+    match color {
+        Color::White => 1,
+        Color::Black => -1,
+    }
 }
 
-/// Return a static numerical evaluation for a given position.
-fn position_evaluation(position: &Board) -> i32 {
+/// Return a static numerical evaluation for a given position, with the
+/// evaluation terms scaled by the given weights.
+fn position_evaluation(position: &Board, weights: &EvalWeights) -> i32 {
     // Handle the checkmate and stalemate cases.
     if position.status() != BoardStatus::Ongoing {
         if position.status() == BoardStatus::Stalemate {
@@ -53,19 +228,57 @@ fn position_evaluation(position: &Board) -> i32 {
             // The current position is checkmate for the player to move. The
             // player to move has lost.
             if position.side_to_move() == Color::White {
-                return -10000;
+                return -MATE_SCORE;
             } else {
-                return 10000;
+                return MATE_SCORE;
             }
         }
     }
 
-    // The factor of 10 is to ensure that piece count considerations have a
-    // much higher effect on the evaluation of a given board state than
-    // positional evaluations.
-    10 * piece_score(position) + central_control(position)
+    ongoing_evaluation(position, weights, -MATE_SCORE, MATE_SCORE)
+}
+
+/// The static evaluation of a position known to have legal moves, skipping the
+/// checkmate and stalemate checks of `position_evaluation`. Those generate the
+/// legal moves, which the search has already done by the time it evaluates.
+///
+/// The evaluation is lazy: the positional terms are capped (see
+/// `EvalWeights::positional_cap`), so if material alone puts the eval outside
+/// the window `alpha` to `beta` by more than the cap they can't bring it back,
+/// and the nearer bound on the eval is returned without computing them.
+fn ongoing_evaluation(position: &Board, weights: &EvalWeights, alpha: i32, beta: i32) -> i32 {
+    let cap = weights.positional_cap();
+    let base =
+        piece_score(position) + eval_noise(position, weights) + endgames::promotion_race(position);
+    let scale = endgames::drawish_scale(position);
+
+    let lower = (base - cap) * scale / 100;
+    let upper = (base + cap) * scale / 100;
+    if lower >= beta {
+        return lower;
+    }
+    if upper <= alpha {
+        return upper;
+    }
+
+    let positional = central_control(position)
+        + development(position) * weights.development / 100
+        + mobility::mobility(position, weights.exact_mobility)
+        + mobility::rim_knights(position) * weights.rim_knights / 100
+        + mobility::bad_bishops(position)
+        + pawns::passed_pawn_king_support(position)
+        + pawns::rooks_behind_passed_pawns(position)
+        + coordination::heavy_piece_batteries(position)
+        + coordination::rook_coordination(position)
+        + king_safety::king_safety(position, weights)
+        + king_safety::back_rank_threats(position);
+
+    (base + positional.clamp(-cap, cap)) * scale / 100
 }
 
+/// Bonus for each piece or pawn standing on one of the four central squares.
+const CENTRAL_SQUARE_BONUS: i32 = 10;
+
 /// Generate a value representing the control over the centre that both sides
 /// have in the given position.
 fn central_control(position: &Board) -> i32 {
@@ -83,112 +296,3905 @@ fn central_control(position: &Board) -> i32 {
         + -((position.color_combined(Color::Black) & e5_bb).popcnt() as i32)
         + -((position.color_combined(Color::Black) & d5_bb).popcnt() as i32);
 
-    cc_score
+    cc_score * CENTRAL_SQUARE_BONUS
 }
 
-/// Minimax algorithm to search for the optimal move, with appropriate
-/// alpha-beta pruning.
-fn minimax_alpha_beta(
-    position: Board,
-    depth: u32,
-    alpha: i32,
-    beta: i32,
-    player_color: Color,
+/// Penalty for each knight or bishop still standing on its starting square.
+const UNDEVELOPED_MINOR_PENALTY: i32 = 30;
+
+/// Penalty for moving a knight or bishop that has already moved again while
+/// others of its side are still on their starting squares.
+const REPEATED_MINOR_MOVE_PENALTY: i32 = 15;
+
+/// The last fullmove number at which development is given its full weight.
+const DEVELOPMENT_FULL_UNTIL: u32 = 10;
+
+/// The number of moves after DEVELOPMENT_FULL_UNTIL over which the weight
+/// given to development fades to nothing.
+const DEVELOPMENT_FADE_MOVES: u32 = 10;
+
+/// Generate a value rewarding the side that has brought more of its minor
+/// pieces off their starting squares.
+fn development(position: &Board) -> i32 {
+    // The starting squares of the knights and bishops, b1, c1, f1 and g1 for
+    // White and the same files on the eighth rank for Black.
+    let white_home = BitBoard::new(0x66);
+    let black_home = BitBoard::new(0x66 << 56);
+    let minors = position.pieces(Piece::Knight) | position.pieces(Piece::Bishop);
+    let white_undeveloped = (minors & position.color_combined(Color::White) & white_home).popcnt();
+    let black_undeveloped = (minors & position.color_combined(Color::Black) & black_home).popcnt();
+
+    (black_undeveloped as i32 - white_undeveloped as i32) * UNDEVELOPED_MINOR_PENALTY
+}
+
+/// One step of splitmix64, spreading every bit of the input over the output.
+/// Feeding each output back in gives a seeded stream of pseudo-random values.
+const fn splitmix64(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Generate a pseudo-random value within the weights' noise amplitude. It is
+/// derived from the position's hash and the seed alone, so a position is
+/// always perturbed the same way for a given seed.
+fn eval_noise(position: &Board, weights: &EvalWeights) -> i32 {
+    if weights.noise_amplitude == 0 {
+        return 0;
+    }
+
+    let z = splitmix64(position.get_hash() ^ weights.noise_seed);
+    let range = 2 * weights.noise_amplitude as u64 + 1;
+    (z % range) as i32 - weights.noise_amplitude
+}
+
+/// The game phase of a position with all of its pieces still on the board.
+const PHASE_MAX: i32 = 256;
+
+/// Estimate how far the game has progressed from the non-pawn material left on
+/// the board, from PHASE_MAX (every piece present) down to 0 (kings and pawns
+/// only).
+fn game_phase(position: &Board) -> i32 {
+    // Minor pieces count once, rooks twice and queens four times, giving a
+    // total of 24 in the starting position.
+    let minors = (position.pieces(Piece::Knight) | position.pieces(Piece::Bishop)).popcnt();
+    let rooks = position.pieces(Piece::Rook).popcnt();
+    let queens = position.pieces(Piece::Queen).popcnt();
+    let material = cmp::min(minors + 2 * rooks + 4 * queens, 24) as i32;
+
+    material * PHASE_MAX / 24
+}
+
+/// The number of king moves needed to get from one square to another.
+fn distance(a: Square, b: Square) -> i32 {
+    let file_distance = (a.get_file().to_index() as i32 - b.get_file().to_index() as i32).abs();
+    let rank_distance = (a.get_rank().to_index() as i32 - b.get_rank().to_index() as i32).abs();
+    cmp::max(file_distance, rank_distance)
+}
+
+/// Whether a move resets the fifty move clock (pawn moves and captures).
+fn is_irreversible(position: &Board, m: ChessMove) -> bool {
+    position.piece_on(m.get_source()) == Some(Piece::Pawn)
+        || position.piece_on(m.get_dest()).is_some()
+}
+
+/// Whether a move is quiet: neither a capture (en passant included), a
+/// promotion nor a check.
+fn is_quiet(position: &Board, m: ChessMove) -> bool {
+    mvv_lva(position, m) == 0
+        && m.get_promotion().is_none()
+        && position.make_move_new(m).checkers().popcnt() == 0
+}
+
+/// The key positions are told apart by, for repetitions and the
+/// transposition table, hashed from scratch. The search updates it move by
+/// move instead, and only needs this for the root.
+fn position_key(position: &Board) -> u64 {
+    zobrist::full_key(position)
+}
+
+/// The value of a piece in pawns. The king can't be captured, and is valued
+/// above everything else so that it sorts last as a capturing piece.
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 10,
+    }
+}
+
+/// Rank a move by most valuable victim, least valuable attacker: captures of
+/// bigger pieces first and, among those, captures with smaller pieces first.
+/// Quiet moves all rank zero, below every capture.
+fn mvv_lva(position: &Board, m: ChessMove) -> i32 {
+    let attacker = position.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
+    let victim = match position.piece_on(m.get_dest()) {
+        Some(victim) => victim,
+        // A pawn changing file onto an empty square is capturing en passant.
+        None if attacker == Piece::Pawn && m.get_source().get_file() != m.get_dest().get_file() => {
+            Piece::Pawn
+        }
+        None => return 0,
+    };
+    16 * piece_value(victim) - piece_value(attacker)
+}
+
+/// Ordering boost for a capture on the square the opponent's last move went
+/// to, enough to rank it above every other capture: taking back is usually
+/// the critical line.
+const RECAPTURE_BONUS: i32 = 16 * 10;
+
+/// How much a promotion's material gain counts for in the move ordering by
+/// default, in percent (see `move_order_score`).
+const DEFAULT_PROMOTION_ORDERING: i32 = 100;
+
+/// The highest promotion ordering weight that can be set, in percent.
+const MAX_PROMOTION_ORDERING: u32 = 1000;
+
+/// Rank a move for searching first: its MVV-LVA, plus for a promotion the
+/// material it gains, on the same scale as a capture of the piece promoted to
+/// and scaled by `promotion_ordering` (in percent). At the default weight a
+/// queen promotion ranks alongside the best captures, a capturing promotion
+/// above them, and underpromotions still rank above the quiet moves, so a
+/// knight promotion with check is looked at early too. A capture on
+/// `recapture_square`, where the last move went, gets RECAPTURE_BONUS on top.
+fn move_order_score(
+    position: &Board,
+    m: ChessMove,
+    recapture_square: Option<Square>,
+    promotion_ordering: i32,
 ) -> i32 {
-    if (depth == 0) || position.status() != BoardStatus::Ongoing {
-        return position_evaluation(&position);
-    };
-
-    let legal_moves = MoveGen::new_legal(&position);
-    if player_color == Color::White {
-        let mut tracking_alpha = alpha;
-        let mut max_eval = -10000;
-        for legal_move in legal_moves {
-            let eval = minimax_alpha_beta(
-                position.make_move_new(legal_move),
-                depth - 1,
-                tracking_alpha,
-                beta,
-                Color::Black,
+    let promotion_gain = m.get_promotion().map_or(0, |piece| {
+        16 * (piece_value(piece) - 1) * promotion_ordering / 100
+    });
+    let recapture_bonus = if recapture_square == Some(m.get_dest()) && mvv_lva(position, m) > 0 {
+        RECAPTURE_BONUS
+    } else {
+        0
+    };
+    mvv_lva(position, m) + promotion_gain + recapture_bonus
+}
+
+/// The legal moves of a position in the order the root search tries them.
+/// The search keeps the first of several equally good moves, so this order is
+/// also the tie-break: highest `move_order_score` first, then the smaller
+/// source and destination squares (as formatted by
+/// `notation::format_coordinate_move`), then the more valuable promotion.
+fn root_moves(
+    position: &Board,
+    recapture_square: Option<Square>,
+    promotion_ordering: i32,
+) -> Vec<ChessMove> {
+    let squares = |m: ChessMove| {
+        notation::format_coordinate_move(&ChessMove::new(m.get_source(), m.get_dest(), None))
+    };
+    let mut moves: Vec<ChessMove> = MoveGen::new_legal(position).collect();
+    moves.sort_by_key(|&m| {
+        (
+            cmp::Reverse(move_order_score(
+                position,
+                m,
+                recapture_square,
+                promotion_ordering,
+            )),
+            squares(m),
+            cmp::Reverse(m.get_promotion().map_or(0, piece_value)),
+        )
+    });
+    moves
+}
+
+/// The fifty move rule is claimable after 100 halfmoves without a pawn move or
+/// capture.
+const FIFTY_MOVE_HALFMOVES: u32 = 100;
+
+/// Over how many halfmoves before the fifty move rule the material eval fades
+/// away.
+const FIFTY_MOVE_FADE_HALFMOVES: u32 = 20;
+
+/// How much to take off the eval for the fifty move rule drawing near. The
+/// material eval fades linearly to nothing over the last
+/// FIFTY_MOVE_FADE_HALFMOVES halfmoves, as the chance of converting an
+/// advantage fades, so a winning side is pushed to reset the clock with a pawn
+/// move or capture rather than drift into the draw.
+fn fifty_move_discount(position: &Board, halfmove_clock: u32) -> i32 {
+    let remaining = FIFTY_MOVE_HALFMOVES.saturating_sub(halfmove_clock);
+    if remaining >= FIFTY_MOVE_FADE_HALFMOVES {
+        return 0;
+    }
+    let faded = (FIFTY_MOVE_FADE_HALFMOVES - remaining) as i32;
+    piece_score(position) * faded / FIFTY_MOVE_FADE_HALFMOVES as i32
+}
+
+/// Whether the side to move may have a move that gives check, judged from where
+/// its pieces reach rather than by playing every move, so that it is cheap to
+/// ask at every node. It errs towards yes: any piece alone between one of its
+/// sliders and the enemy king may give a discovered check, and any promotion,
+/// en passant capture or castling that is possible at all may give check too.
+fn may_give_check(position: &Board) -> bool {
+    let side = position.side_to_move();
+    let king = position.king_square(!side);
+    let own = *position.color_combined(side);
+    let occupied = *position.combined();
+    let own_pieces = |piece| position.pieces(piece) & own;
+
+    let diagonal = own_pieces(Piece::Bishop) | own_pieces(Piece::Queen);
+    let straight = own_pieces(Piece::Rook) | own_pieces(Piece::Queen);
+    let lined_up = (get_bishop_rays(king) & diagonal) | (get_rook_rays(king) & straight);
+    let discovers = lined_up.into_iter().any(|slider| {
+        let blockers = between(slider, king) & occupied;
+        blockers.popcnt() == 1 && blockers & own != EMPTY
+    });
+
+    let (seventh, castled_rook) = match side {
+        Color::White => (
+            Rank::Seventh,
+            BitBoard::from_square(Square::D1) | BitBoard::from_square(Square::F1),
+        ),
+        Color::Black => (
+            Rank::Second,
+            BitBoard::from_square(Square::D8) | BitBoard::from_square(Square::F8),
+        ),
+    };
+    let rook_checks = king_safety::piece_attacks(position, Piece::Rook, !side, king);
+    let special = position.en_passant().is_some()
+        || own_pieces(Piece::Pawn) & get_rank(seventh) != EMPTY
+        || (position.castle_rights(side) != CastleRights::NoRights
+            && rook_checks & castled_rook != EMPTY);
+
+    // A piece checks from the squares the same piece on the king's square
+    // would attack, were it the king's color.
+    let direct = [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ]
+    .into_iter()
+    .any(|piece| {
+        let checks = king_safety::piece_attacks(position, piece, !side, king) & !own;
+        own_pieces(piece).into_iter().any(|square| {
+            let reach = match piece {
+                Piece::Pawn => get_pawn_moves(square, side, occupied),
+                _ => king_safety::piece_attacks(position, piece, side, square),
+            };
+            reach & checks != EMPTY
+        })
+    });
+
+    discovers || special || direct
+}
+
+/// Returned in place of a best move when the game is already over (checkmate
+/// or stalemate), so there is no move to make.
+const GAME_OVER: &str = "game-over";
+
+/// The deepest iteration iterative deepening will attempt, regardless of the
+/// time or node budget remaining.
+const MAX_DEPTH: u32 = 64;
+
+/// How many nodes are searched between checks of the clock.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// The number of moves the remaining clock time is shared between.
+const CLOCK_MOVES_TO_GO: u32 = 30;
+
+/// Time held back from every allocation, to cover the overhead of getting the
+/// move back to the clock.
+const CLOCK_SAFETY_BUFFER_MILLIS: u32 = 50;
+
+/// The time reserved by default on every clocked move for the move to travel
+/// back to the clock, say through a GUI or over the network.
+const DEFAULT_MOVE_OVERHEAD_MILLIS: u32 = 30;
+
+/// How many times the normal allocation a move may take when the best move
+/// keeps changing between depths.
+const UNSTABLE_TIME_FACTOR: u32 = 3;
+
+/// How much worse than even a draw is scored when the engine throws away a
+/// material advantage to reach it.
+const DRAW_CONTEMPT: i32 = 100;
+
+/// How many plies shallower than the node the reply to a null move is
+/// searched.
+const NULL_MOVE_REDUCTION: u32 = 2;
+
+/// The least remaining depth at which a null move is tried. Any shallower and
+/// the reduced search would be next to nothing.
+const NULL_MOVE_MIN_DEPTH: u32 = 3;
+
+/// How far below the window the static eval has to be, by remaining depth 1 to
+/// 3, before a node is razored: too far behind for a quiet move to catch up,
+/// so only captures are searched to confirm it.
+const RAZOR_MARGINS: [i32; 3] = [300, 450, 600];
+
+/// How many moves a node with remaining depth 1 to 3 searches before the
+/// quiet moves after them are pruned, in nodes off the principal variation.
+/// Past these, a quiet move this close to the leaves rarely changes the result.
+const LATE_MOVE_COUNTS: [usize; 3] = [8, 12, 18];
+
+/// State carried through a search. Holds the positions that have already
+/// occurred so that draws by repetition can be seen inside the tree, along with
+/// any limits on how long the search may run.
+struct Searcher {
+    /// Hashes of every position reached so far, both from the game history
+    /// and along the line currently being searched (oldest first).
+    history: Vec<u64>,
+    /// The moves leading to the current position, from the game history
+    /// where it is known and along the line currently being searched (oldest
+    /// first).
+    moves: Vec<ChessMove>,
+    /// How many moves have been penalized so far for moving a minor piece
+    /// again (see `is_repeated_minor_move`). Whether a move counts as one
+    /// depends on the line leading up to it, so a node whose score took the
+    /// penalty in isn't stored in the transposition table.
+    repeated_minor_penalties: u64,
+    /// Number of positions visited so far.
+    nodes: u64,
+    /// Number of those positions visited by the quiescence search.
+    qnodes: u64,
+    /// Number of times a move scored well enough to cut off the rest of a
+    /// position's moves.
+    cutoffs: u64,
+    /// Stop searching once this many nodes have been visited.
+    max_nodes: Option<u64>,
+    /// Stop searching once this instant has passed.
+    deadline: Option<Instant>,
+    /// A later deadline to switch to if the best move changes between
+    /// iterations, as an unstable choice is worth spending more time on.
+    extended_deadline: Option<Instant>,
+    /// Set once a limit has been hit. Any results produced afterwards are
+    /// incomplete and must be discarded.
+    stopped: bool,
+    /// The side the engine is choosing a move for.
+    root_color: Color,
+    /// Only consider quiet moves (see `is_quiet`) at the root. The rest of the
+    /// tree still searches every move.
+    quiet_root: bool,
+    /// Moves not to consider at the root. The rest of the tree still searches
+    /// them.
+    excluded_root_moves: Vec<ChessMove>,
+    /// Whether to razor nodes near the leaves whose static eval is hopeless
+    /// (see RAZOR_MARGINS).
+    razoring: bool,
+    /// Whether to prune nodes where even passing keeps the eval beyond the
+    /// window (see `null_move_cutoff`).
+    null_move_pruning: bool,
+    /// Whether to skip the late quiet moves of nodes near the leaves (see
+    /// LATE_MOVE_COUNTS).
+    late_move_pruning: bool,
+    /// The ply the null move being searched was made from, if any, so that
+    /// the reply isn't another null move.
+    null_move_ply: Option<u32>,
+    /// How much a promotion's material gain counts for in the move ordering,
+    /// in percent (see `move_order_score`).
+    promotion_ordering: i32,
+    /// How many moves deep into the tree the current position is.
+    ply: u32,
+    /// A move list for each ply, reused by every node at that ply so that the
+    /// moves are copied into a buffer that is already allocated rather than a
+    /// new one (see `take_move_buffer`).
+    move_buffers: Vec<Vec<ChessMove>>,
+    /// Scaling applied to the evaluation terms.
+    weights: EvalWeights,
+    /// Results of positions already searched.
+    tt: TranspositionTable,
+}
+
+impl Searcher {
+    /// Create a searcher primed with the hashes of earlier game positions.
+    fn new(history: Vec<u64>) -> Searcher {
+        Searcher {
+            history,
+            moves: Vec::new(),
+            repeated_minor_penalties: 0,
+            nodes: 0,
+            qnodes: 0,
+            cutoffs: 0,
+            max_nodes: None,
+            deadline: None,
+            extended_deadline: None,
+            stopped: false,
+            root_color: Color::White,
+            quiet_root: false,
+            excluded_root_moves: Vec::new(),
+            razoring: RAZORING.with(Cell::get),
+            null_move_pruning: true,
+            late_move_pruning: LATE_MOVE_PRUNING.with(Cell::get),
+            null_move_ply: None,
+            promotion_ordering: PROMOTION_ORDERING.with(Cell::get),
+            ply: 0,
+            move_buffers: vec![Vec::new(); MAX_DEPTH as usize + 1],
+            weights: tuning::tuned_weights(),
+            tt: TranspositionTable::with_size_mb(HASH_SIZE_MB.with(Cell::get)),
+        }
+    }
+
+    /// Borrow the move list of the current ply, emptied. A node takes the list
+    /// out while it loops over its moves, as its children need the searcher,
+    /// and hands it back with `return_move_buffer` when done. Plies deeper
+    /// than MAX_DEPTH, which only quiescence reaches, get a list of their own.
+    fn take_move_buffer(&mut self) -> Vec<ChessMove> {
+        let mut buffer = self
+            .move_buffers
+            .get_mut(self.ply as usize)
+            .map(mem::take)
+            .unwrap_or_default();
+        buffer.clear();
+        buffer
+    }
+
+    /// Hand back a move list taken by `take_move_buffer`, keeping its
+    /// allocation for the next node at the same ply.
+    fn return_move_buffer(&mut self, buffer: Vec<ChessMove>) {
+        if let Some(slot) = self.move_buffers.get_mut(self.ply as usize) {
+            *slot = buffer;
+        }
+    }
+
+    /// Whether the search has run out of nodes or time.
+    fn out_of_budget(&mut self) -> bool {
+        if !self.stopped {
+            let out_of_nodes = self.max_nodes.is_some_and(|max| self.nodes >= max);
+            let out_of_time = self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+                && self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+            self.stopped = out_of_nodes || out_of_time;
+        }
+        self.stopped
+    }
+
+    /// Whether the position (which must be the last entry in the history) is
+    /// a draw by threefold repetition or by the fifty move rule.
+    fn is_draw(&self, halfmove_clock: u32) -> bool {
+        let hash = self.history.last().copied().unwrap_or_default();
+        halfmove_clock >= FIFTY_MOVE_HALFMOVES
+            || self.history.iter().filter(|&&h| h == hash).count() >= 3
+    }
+
+    /// Score a drawn position (stalemate, repetition or the fifty move rule)
+    /// for the search. The game result is a draw either way, but if the side
+    /// the engine is playing for brought it about on its own move while ahead
+    /// on material it has thrown away a win, so it is scored as slightly worse
+    /// than an even position to steer the engine towards playing on.
+    fn draw_score(&self, position: &Board) -> Score {
+        let engine_sign = color_sign(self.root_color);
+        let engine_ahead = engine_sign * piece_score(position) > 0;
+        let drawn_on_our_move = position.side_to_move() != self.root_color;
+
+        if engine_ahead && drawn_on_our_move {
+            Score::eval(-engine_sign * DRAW_CONTEMPT)
+        } else {
+            Score::DRAW
+        }
+    }
+
+    /// Minimax algorithm to search for the optimal move, with appropriate
+    /// alpha-beta pruning.
+    fn minimax_alpha_beta(
+        &mut self,
+        position: Board,
+        depth: u32,
+        alpha: Score,
+        beta: Score,
+        halfmove_clock: u32,
+    ) -> Score {
+        // The result is thrown away once the budget runs out, so bail quickly.
+        self.nodes += 1;
+        if self.out_of_budget() {
+            return Score::DRAW;
+        }
+
+        // The legal moves are generated once per node: their count settles
+        // checkmate and stalemate, and the same generator drives the move
+        // loop. Checkmate takes precedence over the fifty move rule, so it is
+        // looked for before the draw rules.
+        let legal_moves = MoveGen::new_legal(&position);
+        if legal_moves.len() == 0 {
+            return if position.checkers().popcnt() > 0 {
+                Score::mate(!position.side_to_move(), self.ply)
+            } else {
+                self.draw_score(&position)
+            };
+        }
+        if self.is_draw(halfmove_clock) {
+            return self.draw_score(&position);
+        }
+        if depth == 0 {
+            // Captures and promotions are played out before the eval is taken,
+            // so it isn't taken in the middle of an exchange. The discount
+            // comes off afterwards, so shift the window to match.
+            let discount = fifty_move_discount(&position, halfmove_clock);
+            let eval = self.quiescence(
+                position,
+                alpha.with_bonus(discount),
+                beta.with_bonus(discount),
+                self.recapture_square(),
             );
-            max_eval = cmp::max(eval, max_eval);
-            tracking_alpha = cmp::max(tracking_alpha, eval);
-            if beta <= tracking_alpha {
-                break;
+            return eval.with_bonus(-discount);
+        }
+
+        // A result at least as deep as the one needed settles the position, so
+        // long as it is exact or a bound falling outside the window.
+        let hash = self.history.last().copied().unwrap_or_default();
+        if let Some(entry) = self.tt.probe(hash, self.ply) {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {}
+                }
             }
         }
-        max_eval
-    } else {
-        let mut tracking_beta = beta;
-        let mut min_eval = 10000;
-        for legal_move in legal_moves {
-            let eval = minimax_alpha_beta(
-                position.make_move_new(legal_move),
-                depth - 1,
-                alpha,
-                tracking_beta,
-                Color::White,
-            );
-            min_eval = cmp::min(eval, min_eval);
-            tracking_beta = cmp::min(tracking_beta, eval);
-            if tracking_beta <= alpha {
+
+        if let Some(eval) = self.razor(&position, depth, alpha, beta) {
+            return eval;
+        }
+        if let Some(eval) = self.null_move_cutoff(&position, depth, alpha, beta, halfmove_clock) {
+            return eval;
+        }
+
+        let penalties = self.repeated_minor_penalties;
+        let eval = self.search_moves(position, legal_moves, depth, alpha, beta, halfmove_clock);
+        if !self.stopped && self.repeated_minor_penalties == penalties {
+            let bound = if eval <= alpha {
+                Bound::Upper
+            } else if eval >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.tt.store(hash, depth, eval, bound, self.ply);
+        }
+        eval
+    }
+
+    /// Razor a node near the leaves: if the side to move is so far behind that
+    /// even the margin for its remaining depth doesn't bring the static eval
+    /// back into the window, search only its captures to confirm it. Returns
+    /// the quiescence result if it is still outside the window, or None if the
+    /// node has to be searched in full.
+    ///
+    /// Nodes in check aren't razored, as their evasions may be the only save,
+    /// and neither are mate windows or nodes where the side to move may be
+    /// able to give check (see `may_give_check`), as a quiet check may still
+    /// turn the position around.
+    fn razor(&mut self, position: &Board, depth: u32, alpha: Score, beta: Score) -> Option<Score> {
+        if !self.razoring
+            || depth == 0
+            || depth as usize > RAZOR_MARGINS.len()
+            || position.checkers().popcnt() > 0
+            || alpha.is_mate()
+            || beta.is_mate()
+        {
+            return None;
+        }
+
+        let margin = RAZOR_MARGINS[depth as usize - 1];
+        let eval = ongoing_evaluation(position, &self.weights, alpha.value(), beta.value());
+        let hopeless = if position.side_to_move() == Color::White {
+            eval + margin <= alpha.value()
+        } else {
+            eval - margin >= beta.value()
+        };
+        if !hopeless || may_give_check(position) {
+            return None;
+        }
+
+        if position.side_to_move() == Color::White {
+            let eval = self.quiescence(*position, alpha, alpha.next_up(), self.recapture_square());
+            (eval <= alpha).then_some(eval)
+        } else {
+            let eval = self.quiescence(*position, beta.next_down(), beta, self.recapture_square());
+            (eval >= beta).then_some(eval)
+        }
+    }
+
+    /// Try passing instead of moving: if the side to move is still beyond the
+    /// window after the opponent's reply to a null move, searched
+    /// NULL_MOVE_REDUCTION plies shallower, any real move would surely do at
+    /// least as well and the node is cut off. Returns the null move's eval if
+    /// so, or None if the node has to be searched in full.
+    ///
+    /// Passing is only a safe guess when having the move is an advantage, so
+    /// nodes in check, mate windows and positions at risk of zugzwang (see
+    /// `endgames::in_zugzwang_risk`) are never pruned this way, and neither is
+    /// the reply to a null move.
+    fn null_move_cutoff(
+        &mut self,
+        position: &Board,
+        depth: u32,
+        alpha: Score,
+        beta: Score,
+        halfmove_clock: u32,
+    ) -> Option<Score> {
+        let side = position.side_to_move();
+        if !self.null_move_pruning
+            || depth < NULL_MOVE_MIN_DEPTH
+            || alpha.is_mate()
+            || beta.is_mate()
+            || self.null_move_ply.is_some_and(|ply| ply + 1 == self.ply)
+            || endgames::in_zugzwang_risk(position, side)
+        {
+            return None;
+        }
+
+        let eval = ongoing_evaluation(position, &self.weights, alpha.value(), beta.value());
+        let beyond_window = match side {
+            Color::White => eval >= beta.value(),
+            Color::Black => eval <= alpha.value(),
+        };
+        if !beyond_window {
+            return None;
+        }
+        // There is no null move out of check.
+        let passed = position.null_move()?;
+
+        // The null move breaks the line of moves, so the reply sees none.
+        let moves = mem::take(&mut self.moves);
+        let outer_null_move_ply = self.null_move_ply.replace(self.ply);
+        self.history.push(position_key(&passed));
+        self.ply += 1;
+        let reduced = depth - 1 - NULL_MOVE_REDUCTION;
+        let eval = match side {
+            Color::White => {
+                self.minimax_alpha_beta(passed, reduced, beta.next_down(), beta, halfmove_clock + 1)
+            }
+            Color::Black => {
+                self.minimax_alpha_beta(passed, reduced, alpha, alpha.next_up(), halfmove_clock + 1)
+            }
+        };
+        self.ply -= 1;
+        self.history.pop();
+        self.null_move_ply = outer_null_move_ply;
+        self.moves = moves;
+
+        let cutoff = match side {
+            Color::White => eval >= beta,
+            Color::Black => eval <= alpha,
+        };
+        (cutoff && !eval.is_mate() && !self.stopped).then_some(eval)
+    }
+
+    /// Search only the captures and promotions in a position, letting the side
+    /// to move stand pat on the static eval instead if that is better, so that
+    /// the eval isn't taken in the middle of an exchange. In check every
+    /// evasion is searched, as standing pat isn't an option. Recaptures on
+    /// `recapture_square`, where the last move went, are searched first.
+    fn quiescence(
+        &mut self,
+        position: Board,
+        alpha: Score,
+        beta: Score,
+        recapture_square: Option<Square>,
+    ) -> Score {
+        self.nodes += 1;
+        self.qnodes += 1;
+        if self.out_of_budget() {
+            return Score::DRAW;
+        }
+
+        let mut moves = MoveGen::new_legal(&position);
+        if moves.len() == 0 {
+            return if position.checkers().popcnt() > 0 {
+                Score::mate(!position.side_to_move(), self.ply)
+            } else {
+                self.draw_score(&position)
+            };
+        }
+
+        let maximising = position.side_to_move() == Color::White;
+        let in_check = position.checkers().popcnt() > 0;
+        let mut best = if maximising { Score::MIN } else { Score::MAX };
+        let (mut alpha, mut beta) = (alpha, beta);
+        if !in_check {
+            best = Score::eval(ongoing_evaluation(
+                &position,
+                &self.weights,
+                alpha.value(),
+                beta.value(),
+            ));
+            if maximising {
+                alpha = cmp::max(alpha, best);
+            } else {
+                beta = cmp::min(beta, best);
+            }
+            if beta <= alpha {
+                return best;
+            }
+            let promotion_rank = match position.side_to_move() {
+                Color::White => get_rank(Rank::Eighth),
+                Color::Black => get_rank(Rank::First),
+            };
+            let enemies = *position.color_combined(!position.side_to_move());
+            moves.set_iterator_mask(enemies | promotion_rank);
+        }
+
+        // Other pieces reach the promotion rank too, but only pawns promote.
+        let mut ordered = self.take_move_buffer();
+        ordered.extend(moves.filter(|m| {
+            in_check || m.get_promotion().is_some() || position.piece_on(m.get_dest()).is_some()
+        }));
+        let promotion_ordering = self.promotion_ordering;
+        ordered.sort_by_key(|&m| {
+            cmp::Reverse(move_order_score(
+                &position,
+                m,
+                recapture_square,
+                promotion_ordering,
+            ))
+        });
+        for &m in &ordered {
+            self.ply += 1;
+            let eval = self.quiescence(position.make_move_new(m), alpha, beta, Some(m.get_dest()));
+            self.ply -= 1;
+            if maximising {
+                best = cmp::max(best, eval);
+                alpha = cmp::max(alpha, eval);
+            } else {
+                best = cmp::min(best, eval);
+                beta = cmp::min(beta, eval);
+            }
+            if beta <= alpha {
+                self.cutoffs += 1;
                 break;
             }
         }
-        min_eval
+        self.return_move_buffer(ordered);
+        best
     }
-}
 
-/// Exposed to javascript to perform move calculation.
-#[wasm_bindgen]
-pub fn get_best_move_minimax_alpha_beta(current_position: &str, depth: u32) -> String {
-    // Parse current position into Board object.
-    let current_position = Board::from_str(current_position).unwrap_or_else(|error| {
-        panic!("Hit error parsing fen: {:?}", error);
-    });
+    /// The move loop of `minimax_alpha_beta`, maximising for White and
+    /// minimising for Black.
+    fn search_moves(
+        &mut self,
+        position: Board,
+        legal_moves: MoveGen,
+        depth: u32,
+        alpha: Score,
+        beta: Score,
+        halfmove_clock: u32,
+    ) -> Score {
+        let mut ordered = self.take_move_buffer();
+        self.order_moves(&position, legal_moves, &mut ordered);
+        let late_move_count = self.late_move_count(&position, depth, alpha, beta);
+        let is_pruned = |i: usize, m: ChessMove| {
+            late_move_count.is_some_and(|count| i >= count) && is_quiet(&position, m)
+        };
+        let eval = if position.side_to_move() == Color::White {
+            let mut tracking_alpha = alpha;
+            let mut max_eval = Score::MIN;
+            for (i, &legal_move) in ordered.iter().enumerate() {
+                if is_pruned(i, legal_move) {
+                    continue;
+                }
+                let eval = self.search_child(
+                    &position,
+                    legal_move,
+                    depth - 1,
+                    tracking_alpha,
+                    beta,
+                    halfmove_clock,
+                );
+                max_eval = cmp::max(eval, max_eval);
+                tracking_alpha = cmp::max(tracking_alpha, eval);
+                if beta <= tracking_alpha {
+                    self.cutoffs += 1;
+                    break;
+                }
+            }
+            max_eval
+        } else {
+            let mut tracking_beta = beta;
+            let mut min_eval = Score::MAX;
+            for (i, &legal_move) in ordered.iter().enumerate() {
+                if is_pruned(i, legal_move) {
+                    continue;
+                }
+                let eval = self.search_child(
+                    &position,
+                    legal_move,
+                    depth - 1,
+                    alpha,
+                    tracking_beta,
+                    halfmove_clock,
+                );
+                min_eval = cmp::min(eval, min_eval);
+                tracking_beta = cmp::min(tracking_beta, eval);
+                if tracking_beta <= alpha {
+                    self.cutoffs += 1;
+                    break;
+                }
+            }
+            min_eval
+        };
+        self.return_move_buffer(ordered);
+        eval
+    }
+
+    /// How many of a node's moves are searched before its quiet moves (see
+    /// `is_quiet`) are pruned, or None if none are. Only nodes near the leaves
+    /// with a null window, so off the principal variation, prune late moves,
+    /// and never in check or in a mate window.
+    fn late_move_count(
+        &self,
+        position: &Board,
+        depth: u32,
+        alpha: Score,
+        beta: Score,
+    ) -> Option<usize> {
+        if !self.late_move_pruning
+            || depth == 0
+            || depth as usize > LATE_MOVE_COUNTS.len()
+            || beta != alpha.next_up()
+            || position.checkers().popcnt() > 0
+            || alpha.is_mate()
+            || beta.is_mate()
+        {
+            return None;
+        }
+        Some(LATE_MOVE_COUNTS[depth as usize - 1])
+    }
+
+    /// The square the last move went to, where a recapture may be due.
+    fn recapture_square(&self) -> Option<Square> {
+        self.moves.last().map(ChessMove::get_dest)
+    }
+
+    /// Fill `ordered` with the legal moves of a node in the order they are
+    /// searched: any recaptures on the square the last move went to, taken out
+    /// of the generator first, then any promotions, best first by
+    /// `move_order_score` (so capturing and queen promotions lead), then the
+    /// rest in the generator's order.
+    fn order_moves(
+        &self,
+        position: &Board,
+        mut legal_moves: MoveGen,
+        ordered: &mut Vec<ChessMove>,
+    ) {
+        if let Some(square) = self.recapture_square() {
+            legal_moves.set_iterator_mask(BitBoard::from_square(square));
+            ordered.extend(&mut legal_moves);
+            legal_moves.set_iterator_mask(!BitBoard::new(0));
+        }
+        let recaptures = ordered.len();
+        ordered.extend(legal_moves);
+
+        // Promotions are rare, so only sort when there can be one. The sort is
+        // stable, leaving the other moves in order behind them.
+        let side = position.side_to_move();
+        let about_to_promote = position.pieces(Piece::Pawn)
+            & position.color_combined(side)
+            & get_rank(side.to_seventh_rank());
+        if about_to_promote.popcnt() > 0 {
+            let recapture_square = self.recapture_square();
+            ordered[recaptures..].sort_by_key(|&m| match m.get_promotion() {
+                Some(_) => cmp::Reverse(
+                    1 + move_order_score(position, m, recapture_square, self.promotion_ordering),
+                ),
+                None => cmp::Reverse(0),
+            });
+        }
+    }
 
-    // Create iterator for candidate moves.
-    let candidate_moves = MoveGen::new_legal(&current_position);
-    // Option for holding the a chess move and the resulting eval.
-    let mut best_move: Option<(ChessMove, i32)> = None;
+    /// Whether a move is one of the side to move's knights or bishops moving
+    /// again, having already moved earlier in the game or the line, while
+    /// others of its minor pieces still stand on their starting squares.
+    /// Captures don't count, as taking material is more than a shuffle.
+    fn is_repeated_minor_move(&self, position: &Board, m: ChessMove) -> bool {
+        let side = position.side_to_move();
+        let minors = position.pieces(Piece::Knight) | position.pieces(Piece::Bishop);
+        let home = match side {
+            Color::White => BitBoard::new(0x66),
+            Color::Black => BitBoard::new(0x66 << 56),
+        };
+        // The side's own moves are every other one, counting back from the
+        // one before last.
+        (minors & BitBoard::from_square(m.get_source())).popcnt() > 0
+            && position.piece_on(m.get_dest()).is_none()
+            && (minors & position.color_combined(side) & home).popcnt() > 0
+            && self
+                .moves
+                .iter()
+                .rev()
+                .skip(1)
+                .step_by(2)
+                .any(|earlier| earlier.get_dest() == m.get_source())
+    }
 
-    // Iterate through the candidate moves getting an eval for every one, retain
-    // the best one.
+    /// Play a move and search the resulting position, keeping the repetition
+    /// history and fifty move clock in step with the line being searched.
+    fn search_child(
+        &mut self,
+        position: &Board,
+        m: ChessMove,
+        depth: u32,
+        alpha: Score,
+        beta: Score,
+        halfmove_clock: u32,
+    ) -> Score {
+        let new_position = position.make_move_new(m);
+        let new_halfmove_clock = if is_irreversible(position, m) {
+            0
+        } else {
+            halfmove_clock + 1
+        };
 
-    // TODO - currently alpha and beta are reset after each invocation of minimax_alpha_beta.
-    // We could dramatically reduce the amount we had to calculate by fixing this.
-    for candidate_move in candidate_moves {
-        let new_position = current_position.make_move_new(candidate_move);
-        let eval = minimax_alpha_beta(
+        let parent_key = self.history.last().copied().unwrap_or_default();
+        let key = zobrist::updated_key(parent_key, position, m, &new_position);
+        debug_assert_eq!(key, position_key(&new_position));
+        // The penalty goes on after the child is searched, so the child's own
+        // score (and anything stored for it) is left without it. Shift the
+        // window to match.
+        let penalty = if self.is_repeated_minor_move(position, m) {
+            -color_sign(position.side_to_move())
+                * REPEATED_MINOR_MOVE_PENALTY
+                * self.weights.development
+                / 100
+        } else {
+            0
+        };
+        if penalty != 0 {
+            self.repeated_minor_penalties += 1;
+        }
+        self.history.push(key);
+        self.moves.push(m);
+        self.ply += 1;
+        let eval = self.minimax_alpha_beta(
             new_position,
             depth,
-            -10000,
-            10000,
-            new_position.side_to_move(),
+            alpha.with_bonus(-penalty),
+            beta.with_bonus(-penalty),
+            new_halfmove_clock,
+        );
+        self.ply -= 1;
+        self.moves.pop();
+        self.history.pop();
+
+        eval.with_bonus(penalty)
+    }
+
+    /// Search every legal move in the position, returning the best one along
+    /// with its evaluation (or None if there are no legal moves).
+    fn search_root(
+        &mut self,
+        current_position: &Board,
+        depth: u32,
+        halfmove_clock: u32,
+    ) -> Option<(ChessMove, Score)> {
+        self.search_root_window(
+            current_position,
+            depth,
+            halfmove_clock,
+            Score::MIN,
+            Score::MAX,
+        )
+    }
+
+    /// As `search_root`, but only looking for evals within the (White
+    /// relative) window `alpha` to `beta`. The search is fail-soft, so if the
+    /// best move's eval falls outside the window it is still returned, as an
+    /// upper bound at or below `alpha` or a lower bound at or above `beta`.
+    fn search_root_window(
+        &mut self,
+        current_position: &Board,
+        depth: u32,
+        halfmove_clock: u32,
+        mut alpha: Score,
+        mut beta: Score,
+    ) -> Option<(ChessMove, Score)> {
+        self.root_color = current_position.side_to_move();
+        let maximising = self.root_color == Color::White;
+        let improves = |eval: Score, top_eval: Score| {
+            (maximising && eval > top_eval) || (!maximising && eval < top_eval)
+        };
+
+        // Option for holding the a chess move and the resulting eval.
+        let mut best_move: Option<(ChessMove, Score)> = None;
+
+        // Iterate through the candidate moves getting an eval for every one,
+        // retain the best one. The resulting positions go through the same
+        // draw-aware path as the rest of the tree.
+        let mut candidates = root_moves(
+            current_position,
+            self.recapture_square(),
+            self.promotion_ordering,
         );
+        if self.quiet_root {
+            candidates.retain(|&m| is_quiet(current_position, m));
+        }
+        candidates.retain(|m| !self.excluded_root_moves.contains(m));
+        for candidate_move in candidates {
+            let eval = if best_move.is_none() {
+                // The first move is searched with the whole window to
+                // establish the score every other move has to beat.
+                self.search_child(
+                    current_position,
+                    candidate_move,
+                    depth,
+                    alpha,
+                    beta,
+                    halfmove_clock,
+                )
+            } else {
+                // Later moves only need to show whether they beat that score,
+                // which a null window around it answers cheaply. The search is
+                // fail-soft, so only a move which does beat it is searched
+                // again (with the window open on that side) for its exact eval.
+                let (null_alpha, null_beta) = if maximising {
+                    (alpha, alpha.next_up())
+                } else {
+                    (beta.next_down(), beta)
+                };
+                let bound = self.search_child(
+                    current_position,
+                    candidate_move,
+                    depth,
+                    null_alpha,
+                    null_beta,
+                    halfmove_clock,
+                );
 
-        // Check whether the candidate move is the best found.
-        if let Some((_, top_eval)) = best_move {
-            if (current_position.side_to_move() == Color::White && eval > top_eval)
-                || (current_position.side_to_move() == Color::Black && eval < top_eval)
-            {
+                let beats_window = (maximising && bound > alpha) || (!maximising && bound < beta);
+                if beats_window && !self.stopped {
+                    self.search_child(
+                        current_position,
+                        candidate_move,
+                        depth,
+                        alpha,
+                        beta,
+                        halfmove_clock,
+                    )
+                } else {
+                    bound
+                }
+            };
+
+            // A move whose search was cut short has no trustworthy eval.
+            if self.stopped {
+                break;
+            }
+
+            // Check whether the candidate move is the best found.
+            if best_move.is_none_or(|(_, top_eval)| improves(eval, top_eval)) {
                 best_move = Some((candidate_move, eval));
             }
+
+            // Tighten the window on the side to move's side, and stop once a
+            // move is good enough that the window's other side is exceeded.
+            if maximising {
+                alpha = cmp::max(alpha, eval);
+            } else {
+                beta = cmp::min(beta, eval);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best_move
+    }
+
+    /// The line of best play the search expects from the position: the best
+    /// move, then the best reply to it searched one ply shallower, and so on
+    /// until the depth runs out or the game ends, for `depth` + 1 moves at
+    /// most.
+    fn principal_variation(
+        &mut self,
+        position: &Board,
+        depth: u32,
+        halfmove_clock: u32,
+    ) -> Vec<ChessMove> {
+        let (mut position, mut halfmove_clock) = (*position, halfmove_clock);
+        let mut line = Vec::new();
+        for remaining in (0..=depth).rev() {
+            let Some((best_move, _)) = self.search_root(&position, remaining, halfmove_clock)
+            else {
+                break;
+            };
+            halfmove_clock = if is_irreversible(&position, best_move) {
+                0
+            } else {
+                halfmove_clock + 1
+            };
+            position = position.make_move_new(best_move);
+            self.history.push(position_key(&position));
+            line.push(best_move);
+        }
+        line
+    }
+
+    /// Search every legal move with the full window, so that each gets an
+    /// exact evaluation rather than just the best one. Slower than
+    /// `search_root`, but needed when the moves are to be compared.
+    fn evaluate_all_moves(
+        &mut self,
+        current_position: &Board,
+        depth: u32,
+        halfmove_clock: u32,
+    ) -> Vec<(ChessMove, Score)> {
+        self.root_color = current_position.side_to_move();
+        root_moves(
+            current_position,
+            self.recapture_square(),
+            self.promotion_ordering,
+        )
+        .into_iter()
+        .map(|candidate_move| {
+            let eval = self.search_child(
+                current_position,
+                candidate_move,
+                depth,
+                Score::MIN,
+                Score::MAX,
+                halfmove_clock,
+            );
+            (candidate_move, eval)
+        })
+        .collect()
+    }
+
+    /// Search to successively greater depths, up to `max_depth`, until the
+    /// node or time budget runs out, returning the result of the deepest
+    /// completed iteration, or None if there are no legal moves. `on_iteration`
+    /// is called with the result of each iteration as it completes.
+    fn iterative_deepening(
+        &mut self,
+        current_position: &Board,
+        halfmove_clock: u32,
+        max_depth: u32,
+        mut on_iteration: impl FnMut(&MoveResult),
+    ) -> Option<MoveResult> {
+        // The shallowest iteration is always allowed to finish so there is a
+        // move to return however small the budget.
+        let (max_nodes, deadline) = (self.max_nodes.take(), self.deadline.take());
+        let shallowest = self.search_root(current_position, 0, halfmove_clock);
+        self.max_nodes = max_nodes;
+        self.deadline = deadline;
+        let (best_move, score) = shallowest?;
+        let mut result = MoveResult {
+            best_move: notation::format_coordinate_move(&best_move),
+            score,
+            depth: 0,
+        };
+        on_iteration(&result);
+
+        for depth in 1..=max_depth {
+            match self.search_root(current_position, depth, halfmove_clock) {
+                Some((best_move, score)) if !self.stopped => {
+                    let best_move = notation::format_coordinate_move(&best_move);
+                    if best_move != result.best_move && self.extended_deadline.is_some() {
+                        self.deadline = self.extended_deadline.take();
+                    }
+                    result = MoveResult {
+                        best_move,
+                        score,
+                        depth,
+                    };
+                    on_iteration(&result);
+                }
+                _ => break,
+            }
+        }
+
+        Some(result)
+    }
+}
+
+/// The outcome of a budgeted search, exposed to javascript.
+#[wasm_bindgen]
+pub struct MoveResult {
+    best_move: String,
+    score: Score,
+    depth: u32,
+}
+
+impl MoveResult {
+    /// The result reported for a position with no legal moves: GAME_OVER in
+    /// place of the best move, scored as the checkmate or stalemate it is.
+    fn game_over(position: &Board) -> MoveResult {
+        let score = if position.checkers().popcnt() > 0 {
+            Score::mate(!position.side_to_move(), 0)
         } else {
-            best_move = Some((candidate_move, eval))
+            Score::DRAW
+        };
+        MoveResult {
+            best_move: String::from(GAME_OVER),
+            score,
+            depth: 0,
         }
     }
+}
+
+#[wasm_bindgen]
+impl MoveResult {
+    /// The best move found, formatted as by `get_best_move_minimax_alpha_beta`,
+    /// or "game-over" if the position has no legal moves.
+    #[wasm_bindgen(getter)]
+    pub fn best_move(&self) -> String {
+        self.best_move.clone()
+    }
 
-    let (best_move, _) = best_move.unwrap();
+    /// The evaluation of the position after the best move is played, as
+    /// `evaluate` would give for it searched to the same depth. Like every
+    /// score the engine reports it is White-relative, in centipawns (a pawn
+    /// is 100): positive favours White whichever side is to move. With the
+    /// game already over, the score of the checkmate or stalemate itself.
+    #[wasm_bindgen(getter)]
+    pub fn score(&self) -> i32 {
+        self.score.value()
+    }
+
+    /// The last depth that was searched to completion, in the same units as
+    /// the depth passed to `get_best_move_minimax_alpha_beta`.
+    #[wasm_bindgen(getter)]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
 
-    format_best_move(&best_move)
+    /// The evaluation formatted for display, showing forced mates as a mate
+    /// distance (e.g. "mate 3") rather than a raw score.
+    #[wasm_bindgen(getter)]
+    pub fn score_text(&self) -> String {
+        self.score.to_text()
+    }
 }
 
+/// Statistics gathered over a search, exposed to javascript for tuning the
+/// engine.
+#[wasm_bindgen]
+pub struct SearchStats {
+    nodes: u64,
+    qnodes: u64,
+    tt_probes: u64,
+    tt_hits: u64,
+    cutoffs: u64,
+    branching_factor: f64,
+}
 
-// Simple functionality test.
-#[test]
-fn mate_in_one() {
-    let mate_in_one_fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1";
-    let best_move = get_best_move_minimax_alpha_beta(mate_in_one_fen, 3);
-    assert_eq!(best_move, "h5 f7");
+#[wasm_bindgen]
+impl SearchStats {
+    /// The number of positions visited over every iteration.
+    #[wasm_bindgen(getter)]
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// The number of those positions visited by the quiescence search, which
+    /// plays out the captures and promotions at the leaves.
+    #[wasm_bindgen(getter)]
+    pub fn qnodes(&self) -> u64 {
+        self.qnodes
+    }
+
+    /// The number of transposition table lookups.
+    #[wasm_bindgen(getter)]
+    pub fn tt_probes(&self) -> u64 {
+        self.tt_probes
+    }
+
+    /// The number of transposition table lookups that found the position.
+    #[wasm_bindgen(getter)]
+    pub fn tt_hits(&self) -> u64 {
+        self.tt_hits
+    }
+
+    /// The number of beta cutoffs, where a move was good enough that the
+    /// position's remaining moves needn't be searched.
+    #[wasm_bindgen(getter)]
+    pub fn cutoffs(&self) -> u64 {
+        self.cutoffs
+    }
+
+    /// The effective branching factor: how many times more nodes the last
+    /// iteration visited than the one before it.
+    #[wasm_bindgen(getter)]
+    pub fn branching_factor(&self) -> f64 {
+        self.branching_factor
+    }
+}
+
+/// Exposed to javascript to set up the module. Call it once before anything
+/// else; calling it again does nothing. With the `console_error_panic_hook`
+/// feature enabled, panics are logged to the console with their message
+/// rather than surfacing as a bare "unreachable executed".
+#[wasm_bindgen]
+pub fn init() {
+    #[cfg(feature = "console_error_panic_hook")]
+    console_error_panic_hook::set_once();
+}
+
+/// Exposed to javascript to say which build of the engine is running, for bug
+/// reports. Returns the crate version and the cargo features compiled in, as
+/// "version;features" with the features separated by commas (e.g.
+/// "0.1.0;console_error_panic_hook"). The features field is empty when none
+/// are enabled.
+#[wasm_bindgen]
+pub fn engine_info() -> String {
+    let features = [(
+        "console_error_panic_hook",
+        cfg!(feature = "console_error_panic_hook"),
+    )];
+    let enabled: Vec<&str> = features
+        .iter()
+        .filter(|&&(_, enabled)| enabled)
+        .map(|&(name, _)| name)
+        .collect();
+    format!("{};{}", env!("CARGO_PKG_VERSION"), enabled.join(","))
+}
+
+/// Parse a FEN into a Board object, panicking with the parse error if it is
+/// invalid. Only for FENs known to be valid; those passed in from javascript
+/// go through `board_from_fen`.
+fn parse_fen(fen: &str) -> Board {
+    Board::from_str(fen).unwrap_or_else(|error| {
+        panic!("Hit error parsing fen: {:?}", error);
+    })
+}
+
+/// Parse a FEN and set up a searcher for it, with the evaluation weighted for
+/// the FEN's fullmove number. Returns the position, the searcher and the
+/// halfmove clock.
+fn prepare_search(fen: &str) -> Result<(Board, Searcher, u32), InputError> {
+    let (halfmove_clock, fullmove_number) = notation::fen_counters(fen);
+    let position = board_from_fen(fen)?;
+
+    let mut searcher = Searcher::new(vec![position_key(&position)]);
+    searcher.weights = EvalWeights::for_move_number(fullmove_number);
+    Ok((position, searcher, halfmove_clock))
+}
+
+/// Exposed to javascript to perform move calculation. The move is given in
+/// coordinates, with the piece promoted to appended to promotions (e.g.
+/// "e2 e4" or "f7 f8n"), as every best move the engine reports is. Returns
+/// "game-over" if the game is already over (checkmate or stalemate).
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_minimax_alpha_beta(
+    current_position: &str,
+    depth: u32,
+) -> Result<String, InputError> {
+    get_best_move_with_history(current_position, "", depth)
+}
+
+/// Exposed to javascript to play the engine's move, for UIs that would rather
+/// not apply the move themselves. Returns the FEN after the best move, with
+/// the move counters advanced. If the game is already over (checkmate or
+/// stalemate) the FEN is returned unchanged, followed by ";game-over".
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn play_best_move(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    match searcher.search_root(&position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::fen_after_move(
+            current_position,
+            &position,
+            best_move,
+        )),
+        None => Ok(format!("{};game-over", current_position)),
+    }
+}
+
+/// Exposed to javascript to play the engine against itself from `start_fen`,
+/// searching every move to `depth`, for plotting how its eval develops over a
+/// game. Returns one line per move, giving the move in SAN and the eval of the
+/// position after it separated by a semicolon (e.g. "Nf3;2"). The game ends at
+/// checkmate or stalemate, at a draw by threefold repetition or the fifty move
+/// rule, or once `max_moves` moves (counting each side's separately) have been
+/// played. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn self_play_with_evals(
+    start_fen: &str,
+    depth: u32,
+    max_moves: u32,
+) -> Result<String, InputError> {
+    let mut fen = String::from(start_fen);
+    let mut history = Vec::new();
+    let mut moves = Vec::new();
+    let mut lines = Vec::new();
+
+    for _ in 0..max_moves {
+        let (position, mut searcher, halfmove_clock) = prepare_search(&fen)?;
+        history.push(position_key(&position));
+        searcher.history.clone_from(&history);
+        searcher.moves.clone_from(&moves);
+        if searcher.is_draw(halfmove_clock) {
+            break;
+        }
+
+        // No legal moves left means checkmate or stalemate.
+        let (best_move, eval) = match searcher.search_root(&position, depth, halfmove_clock) {
+            Some(result) => result,
+            None => break,
+        };
+        lines.push(format!(
+            "{};{}",
+            notation::format_san(&position, best_move),
+            eval.value()
+        ));
+        fen = notation::fen_after_move(&fen, &position, best_move);
+        moves.push(best_move);
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// The FEN of the standard starting position.
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Exposed to javascript to generate a random legal position, for fuzzing the
+/// engine and building test suites. Plays `plies` moves from the starting
+/// position, each picked from the legal moves by a PRNG seeded with `seed`, so
+/// the same arguments always give the same position. Stops early if the game
+/// ends. Returns the resulting FEN.
+#[wasm_bindgen]
+pub fn random_position(seed: u64, plies: u32) -> String {
+    let mut fen = String::from(START_FEN);
+    let mut position = parse_fen(START_FEN);
+    let mut state = seed;
+
+    for _ in 0..plies {
+        let moves: Vec<ChessMove> = MoveGen::new_legal(&position).collect();
+        if moves.is_empty() {
+            break;
+        }
+        state = splitmix64(state);
+        let m = moves[(state % moves.len() as u64) as usize];
+        fen = notation::fen_after_move(&fen, &position, m);
+        position = position.make_move_new(m);
+    }
+
+    fen
+}
+
+/// Exposed to javascript to search a position with some pieces taken off the
+/// board, for handicap games and "what if White had no rook" analysis.
+/// `remove_squares` lists the squares to empty, separated by commas or spaces
+/// (e.g. "a1,h1"). Castling rights that relied on a removed king or rook go
+/// with it. Returns the best move searched to `depth` in the new position.
+/// Throws if the FEN is invalid, a square is empty or not a square at all, or
+/// the new position isn't legal (say a king was removed).
+#[wasm_bindgen]
+pub fn get_best_move_handicap(
+    current_position: &str,
+    depth: u32,
+    remove_squares: &str,
+) -> Result<String, InputError> {
+    let position = board_from_fen(current_position)?;
+    let mut builder = BoardBuilder::from(&position);
+    for text in remove_squares
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|text| !text.is_empty())
+    {
+        let occupied = Square::from_str(text)
+            .ok()
+            .and_then(|square| Some((square, builder[square]?.1)));
+        let Some((square, color)) = occupied else {
+            return Err(InputError::NotAnOccupiedSquare(String::from(text)));
+        };
+        let rights = builder.get_castle_rights(color);
+        builder.castle_rights(
+            color,
+            rights.remove(CastleRights::square_to_castle_rights(color, square)),
+        );
+        if builder.get_en_passant() == Some(square) {
+            builder.en_passant(None);
+        }
+        builder.clear_square(square);
+    }
+
+    // The builder writes the move counters as "0 1", so keep the FEN's own.
+    let (halfmove_clock, fullmove_number) = notation::fen_counters(current_position);
+    let placement = builder.to_string();
+    let fen = format!(
+        "{} {} {}",
+        placement.trim_end_matches(" 0 1"),
+        halfmove_clock,
+        fullmove_number
+    );
+    get_best_move_minimax_alpha_beta(&fen, depth)
+}
+
+/// Exposed to javascript to perform move calculation taking the game history
+/// into account, so that draws by repetition are recognised, as are minor
+/// pieces moved twice in the opening. The history is a newline separated list
+/// of the FENs of earlier positions in the game. Returns "game-over" if there
+/// are no legal moves. Throws if any of the FENs are invalid.
+#[wasm_bindgen]
+pub fn get_best_move_with_history(
+    current_position: &str,
+    history: &str,
+    depth: u32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+
+    // The history comes before the current position, which counts towards any
+    // repetition too.
+    let earlier_positions = history
+        .lines()
+        .filter(|fen| !fen.trim().is_empty())
+        .map(board_from_fen)
+        .collect::<Result<Vec<Board>, InputError>>()?;
+    let earlier_hashes = earlier_positions.iter().map(position_key);
+    searcher.history.splice(0..0, earlier_hashes);
+
+    // The moves between the positions, so far back as they follow on from
+    // one another without a gap.
+    let mut positions = earlier_positions;
+    positions.push(current_position);
+    for pair in positions.windows(2) {
+        match notation::move_between(&pair[0], &pair[1]) {
+            Some(m) => searcher.moves.push(m),
+            None => searcher.moves.clear(),
+        }
+    }
+
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::format_coordinate_move(&best_move)),
+        None => Ok(String::from(GAME_OVER)),
+    }
+}
+
+/// Exposed to javascript to perform move calculation with an adjustable
+/// attacking style. `aggression` is a percentage, 100 being the normal style:
+/// higher values make the engine chase the enemy king at the expense of its
+/// own king's safety, lower values make it play more cautiously. Returns
+/// "game-over" if there are no legal moves. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_weighted(
+    current_position: &str,
+    depth: u32,
+    aggression: u32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    searcher.weights = searcher
+        .weights
+        .with_aggression(current_position.side_to_move(), aggression);
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::format_coordinate_move(&best_move)),
+        None => Ok(String::from(GAME_OVER)),
+    }
+}
+
+/// Exposed to javascript to perform move calculation with seeded noise of up to
+/// `amplitude` centipawns added to every eval, for generating varied but
+/// reproducible games. The same seed always perturbs a position's eval the
+/// same way. Returns "game-over" if there are no legal moves.
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_noisy(
+    current_position: &str,
+    depth: u32,
+    seed: u32,
+    amplitude: u32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    searcher.weights.noise_seed = seed as u64;
+    searcher.weights.noise_amplitude = amplitude as i32;
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::format_coordinate_move(&best_move)),
+        None => Ok(String::from(GAME_OVER)),
+    }
+}
+
+/// How deep the opening moves picked by `get_best_move_opening_variety` are
+/// searched to find the reasonable ones.
+const OPENING_VARIETY_DEPTH: u32 = 1;
+
+/// How far (in centipawns) below the best move an opening move may score
+/// and still be picked by `get_best_move_opening_variety`.
+const OPENING_VARIETY_MARGIN: i32 = 30;
+
+/// Exposed to javascript to vary the engine's openings without a book. For the
+/// first `opening_plies` plies of the game, as given by the FEN's fullmove
+/// number and side to move, plays a move picked by a PRNG seeded with `seed`
+/// from those within a small margin of the best at a shallow search. After
+/// that it plays the best move searched to `depth`, like
+/// `get_best_move_minimax_alpha_beta`. Returns "game-over" if there are no
+/// legal moves. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_opening_variety(
+    current_position: &str,
+    depth: u32,
+    opening_plies: u32,
+    seed: u64,
+) -> Result<String, InputError> {
+    let (position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let (_, fullmove_number) = notation::fen_counters(current_position);
+    let ply =
+        2 * fullmove_number.saturating_sub(1) + (position.side_to_move() == Color::Black) as u32;
+    if ply >= opening_plies {
+        return get_best_move_minimax_alpha_beta(current_position, depth);
+    }
+
+    let sign = color_sign(position.side_to_move());
+    let evals = searcher.evaluate_all_moves(&position, OPENING_VARIETY_DEPTH, halfmove_clock);
+    let Some(best) = evals.iter().map(|&(_, eval)| sign * eval.value()).max() else {
+        return Ok(String::from(GAME_OVER));
+    };
+    let reasonable: Vec<ChessMove> = evals
+        .into_iter()
+        .filter(|&(_, eval)| sign * eval.value() >= best - OPENING_VARIETY_MARGIN)
+        .map(|(m, _)| m)
+        .collect();
+
+    // Mix in the position so each opening move is picked independently.
+    let z = splitmix64(seed ^ position_key(&position));
+    Ok(notation::format_coordinate_move(
+        &reasonable[(z % reasonable.len() as u64) as usize],
+    ))
+}
+
+thread_local! {
+    /// The memory each search's transposition table may use, in megabytes.
+    static HASH_SIZE_MB: Cell<u32> = const { Cell::new(DEFAULT_HASH_SIZE_MB) };
+
+    /// The time reserved on every clocked move for getting the move back to
+    /// the clock, in milliseconds.
+    static MOVE_OVERHEAD_MILLIS: Cell<u32> = const { Cell::new(DEFAULT_MOVE_OVERHEAD_MILLIS) };
+
+    /// Whether searches razor hopeless nodes near the leaves.
+    static RAZORING: Cell<bool> = const { Cell::new(true) };
+
+    /// Whether searches skip the late quiet moves of nodes near the leaves.
+    static LATE_MOVE_PRUNING: Cell<bool> = const { Cell::new(true) };
+
+    /// How much a promotion's material gain counts for in the move ordering,
+    /// in percent.
+    static PROMOTION_ORDERING: Cell<i32> = const { Cell::new(DEFAULT_PROMOTION_ORDERING) };
+
+    /// The transposition table kept between calls to
+    /// `get_best_move_persistent`, along with the weights its scores were
+    /// found with and the side they were searched for, as draws are scored
+    /// against that side when it is ahead (see `Searcher::draw_score`).
+    static PERSISTENT_TABLE: RefCell<((EvalWeights, Color), TranspositionTable)> = RefCell::new((
+        (EvalWeights::default(), Color::White),
+        TranspositionTable::with_size_mb(DEFAULT_HASH_SIZE_MB),
+    ));
+}
+
+/// Exposed to javascript to set how much memory (in megabytes) the
+/// transposition table may use, 2 by default. Each megabyte holds 32768
+/// entries, and the table is only allocated once a search stores something in
+/// it. Applies to every search from now on, and clears the table kept by
+/// `get_best_move_persistent`.
+#[wasm_bindgen]
+pub fn set_hash_size_mb(mb: u32) {
+    HASH_SIZE_MB.with(|size| size.set(mb));
+    PERSISTENT_TABLE.with(|persistent| {
+        persistent.borrow_mut().1 = TranspositionTable::with_size_mb(mb);
+    });
+}
+
+/// Exposed to javascript to turn razoring on or off (it is on by default).
+/// Razoring searches only the captures of a node near the leaves whose eval is
+/// far outside the window, which saves nodes but could in principle miss a
+/// quiet save. Applies to every search from now on.
+#[wasm_bindgen]
+pub fn set_razoring(enabled: bool) {
+    RAZORING.with(|razoring| razoring.set(enabled));
+}
+
+/// Exposed to javascript to turn late move pruning on or off (it is on by
+/// default). Late move pruning skips the quiet moves a node near the leaves
+/// would search after its first few, off the principal variation, which
+/// saves nodes but could miss a quiet move that matters. Captures, checks and
+/// promotions are always searched. Applies to every search from now on.
+#[wasm_bindgen]
+pub fn set_late_move_pruning(enabled: bool) {
+    LATE_MOVE_PRUNING.with(|pruning| pruning.set(enabled));
+}
+
+/// Exposed to javascript to set how early promotions are searched, as the
+/// percentage of their material gain they are ordered by (100 by default, up
+/// to 1000). Inside the tree promotions are always tried before the quiet
+/// moves; at the root and among captures, at 100 a queen promotion ranks
+/// alongside the best captures, higher values put promotions ahead of more of
+/// them, and 0 ranks a promotion like the move it would be without
+/// promoting. Doesn't change the moves' scores, only how soon the search
+/// finds them. Applies to every search from now on.
+#[wasm_bindgen]
+pub fn set_promotion_ordering(percent: u32) {
+    let percent = cmp::min(percent, MAX_PROMOTION_ORDERING) as i32;
+    PROMOTION_ORDERING.with(|ordering| ordering.set(percent));
+}
+
+/// Exposed to javascript to set how many milliseconds `get_best_move_clock`
+/// reserves on every move for latency between the engine and the real clock,
+/// such as a GUI or a network connection, 30 by default. The reserve comes off
+/// each move's time, so the move is returned that much before the allocation
+/// runs out. Applies to every clocked search from now on.
+#[wasm_bindgen]
+pub fn set_move_overhead(millis: u32) {
+    MOVE_OVERHEAD_MILLIS.with(|overhead| overhead.set(millis));
+}
+
+/// Exposed to javascript to perform move calculation reusing what was learnt
+/// in earlier calls. Over a game most of the tree searched for one move is
+/// still relevant for the next, so the transposition table is kept between
+/// calls. Call `reset_engine` between games. Returns "game-over" if there
+/// are no legal moves. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_persistent(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+
+    Ok(PERSISTENT_TABLE.with(|persistent| {
+        let mut persistent = persistent.borrow_mut();
+        let (found_with, table) = &mut *persistent;
+
+        // Scores found with other weights, or for the other side, no longer
+        // mean the same thing.
+        let searching_with = (searcher.weights, current_position.side_to_move());
+        if *found_with != searching_with {
+            table.clear();
+            *found_with = searching_with;
+        }
+        table.new_search();
+
+        mem::swap(table, &mut searcher.tt);
+        let best_move = searcher.search_root(&current_position, depth, halfmove_clock);
+        mem::swap(table, &mut searcher.tt);
+
+        best_move.map_or_else(
+            || String::from(GAME_OVER),
+            |(m, _)| notation::format_coordinate_move(&m),
+        )
+    }))
+}
+
+/// Exposed to javascript to search many positions in one call, for analysis
+/// pipelines that would rather not cross into the engine once per position.
+/// Takes newline separated FENs and returns one line for each: the best move
+/// searched to `depth` as `get_best_move_minimax_alpha_beta` gives it, "none"
+/// if the position has no legal moves, or "error: " followed by the reason
+/// the FEN is invalid. One transposition table is shared by the whole batch.
+#[wasm_bindgen]
+pub fn get_best_moves_batch(fens: &str, depth: u32) -> String {
+    let mut table = TranspositionTable::with_size_mb(HASH_SIZE_MB.with(Cell::get));
+    let mut table_weights = None;
+    fens.lines()
+        .map(|fen| {
+            let (position, mut searcher, halfmove_clock) = match prepare_search(fen) {
+                Ok(search) => search,
+                Err(error) => return format!("error: {}", error),
+            };
+
+            // Scores found with other weights no longer mean the same thing.
+            if table_weights != Some(searcher.weights) {
+                table.clear();
+                table_weights = Some(searcher.weights);
+            }
+            table.new_search();
+
+            mem::swap(&mut table, &mut searcher.tt);
+            let best_move = searcher.search_root(&position, depth, halfmove_clock);
+            mem::swap(&mut table, &mut searcher.tt);
+            best_move.map_or(String::from("none"), |(m, _)| {
+                notation::format_coordinate_move(&m)
+            })
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Exposed to javascript to clear everything `get_best_move_persistent` has
+/// kept from earlier calls, ready for a new game.
+#[wasm_bindgen]
+pub fn reset_engine() {
+    PERSISTENT_TABLE.with(|persistent| {
+        let mut persistent = persistent.borrow_mut();
+        persistent.0 = (EvalWeights::default(), Color::White);
+        persistent.1.clear();
+    });
+}
+
+/// Exposed to javascript to show what each side is threatening. Returns the
+/// best move and its eval for the side to move, followed by the same for the
+/// opponent as if it were their move instead, all separated by semicolons
+/// (e.g. "f3 h4;960;h4 e4;-80"). When the side to move is in check the opponent
+/// can't be given the move, so only the first two fields are returned, and
+/// when the side to move has no legal moves "game-over" is returned instead.
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn threats_both_sides(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let weights = searcher.weights;
+    let Some((best_move, eval)) = searcher.search_root(&current_position, depth, halfmove_clock)
+    else {
+        return Ok(String::from(GAME_OVER));
+    };
+    let mut output = format!(
+        "{};{}",
+        notation::format_coordinate_move(&best_move),
+        eval.value()
+    );
+
+    // Passing is only legal for the analysis when it doesn't leave a king in
+    // check, and the opponent needs a move to play.
+    if let Some(passed) = current_position.null_move() {
+        let mut searcher = Searcher::new(vec![position_key(&passed)]);
+        searcher.weights = weights;
+        if let Some((threat, threat_eval)) = searcher.search_root(&passed, depth, halfmove_clock) {
+            output.push_str(&format!(
+                ";{};{}",
+                notation::format_coordinate_move(&threat),
+                threat_eval.value()
+            ));
+        }
+    }
+    Ok(output)
+}
+
+/// How many plies deep each candidate move of a forcing line is searched.
+const FORCING_LINE_DEPTH: u32 = 2;
+
+/// Exposed to javascript to find the most forcing continuation, for tactics
+/// analysis. At each step only checks and captures are considered (or every
+/// move when in check, since they are all forced king safety moves), and the
+/// best of them by a shallow search is played. The line ends after
+/// `max_plies` moves, or once the position is quiet: there is no forcing move,
+/// or (when not in check) none scores better than the static eval, so the side
+/// to move would rather not force matters. Returns the moves one per line in
+/// coordinates (e.g. "b5 c7"). Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn forcing_line(current_position: &str, max_plies: u32) -> Result<String, InputError> {
+    let (mut position, mut searcher, mut halfmove_clock) = prepare_search(current_position)?;
+    let mut line = Vec::new();
+
+    for _ in 0..max_plies {
+        let in_check = position.checkers().popcnt() > 0;
+        let candidates: Vec<ChessMove> = if in_check {
+            MoveGen::new_legal(&position).collect()
+        } else {
+            let captures = queries::captures(&position);
+            let quiet_checks: Vec<ChessMove> = queries::checks(&position)
+                .into_iter()
+                .filter(|m| !captures.contains(m))
+                .collect();
+            [captures, quiet_checks].concat()
+        };
+
+        searcher.root_color = position.side_to_move();
+        let sign = color_sign(position.side_to_move());
+        let best = candidates
+            .into_iter()
+            .map(|m| {
+                let eval = searcher.search_child(
+                    &position,
+                    m,
+                    FORCING_LINE_DEPTH,
+                    Score::MIN,
+                    Score::MAX,
+                    halfmove_clock,
+                );
+                (m, eval.value())
+            })
+            .min_by_key(|&(_, eval)| -sign * eval);
+        let (best_move, best_eval) = match best {
+            Some(best) => best,
+            None => break,
+        };
+        let stand_pat = position_evaluation(&position, &searcher.weights);
+        if !in_check && sign * best_eval <= sign * stand_pat {
+            break;
+        }
+
+        line.push(notation::format_coordinate_move(&best_move));
+        halfmove_clock = if is_irreversible(&position, best_move) {
+            0
+        } else {
+            halfmove_clock + 1
+        };
+        position = position.make_move_new(best_move);
+        searcher.history.push(position_key(&position));
+    }
+
+    Ok(line.join("\n"))
+}
+
+/// Exposed to javascript to find the best quiet move, for studying the plans
+/// in a position rather than its tactics: the best move that is neither a
+/// capture, a promotion nor a check, found with the full search beneath it.
+/// Returns the move as `get_best_move_minimax_alpha_beta` does, or "none" if
+/// every legal move is forcing or there are none. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn best_quiet_move(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    searcher.quiet_root = true;
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::format_coordinate_move(&best_move)),
+        None => Ok(String::from("none")),
+    }
+}
+
+/// Exposed to javascript to offer the best way out of check. Every legal move
+/// in check is an evasion, so this is the best move searched to `depth`, but
+/// with the precondition checked: returns "not-in-check" if the side to move
+/// isn't in check and "checkmate" if there is no way out. Otherwise returns the
+/// evasion and its eval separated by a semicolon (e.g. "e1 f1;-120"). Throws if
+/// the FEN is invalid.
+#[wasm_bindgen]
+pub fn best_check_response(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    if current_position.checkers().popcnt() == 0 {
+        return Ok(String::from("not-in-check"));
+    }
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((evasion, eval)) => Ok(format!(
+            "{};{}",
+            notation::format_coordinate_move(&evasion),
+            eval.value()
+        )),
+        None => Ok(String::from("checkmate")),
+    }
+}
+
+/// Exposed to javascript to find the best move other than those listed, for
+/// exploring the alternatives to the engine's first choice. `excluded` is a
+/// newline separated list of coordinate moves (e.g. "e2 e4"); any that aren't
+/// legal in the position are ignored. Returns the move as
+/// `get_best_move_minimax_alpha_beta` does, or "none" if every legal move is
+/// excluded. Throws if the FEN is invalid or an excluded move is malformed.
+#[wasm_bindgen]
+pub fn best_move_excluding(
+    current_position: &str,
+    depth: u32,
+    excluded: &str,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    for text in excluded.lines().filter(|text| !text.trim().is_empty()) {
+        let m = notation::read_coordinate_move(text)
+            .ok_or_else(|| InputError::MalformedMove(String::from(text)))?;
+        searcher.excluded_root_moves.push(m);
+    }
+
+    match searcher.search_root(&current_position, depth, halfmove_clock) {
+        Some((best_move, _)) => Ok(notation::format_coordinate_move(&best_move)),
+        None => Ok(String::from("none")),
+    }
+}
+
+/// Exposed to javascript to show the line of play the engine expects, in SAN
+/// with move numbers (e.g. "1. e4 e5 2. Nf3", or "1... e5 2. Nf3" with Black
+/// to move). The line is the best move searched to `depth`, then the best
+/// reply searched one ply shallower and so on, so it is `depth` + 1 moves long
+/// unless the game ends first. Returns an empty string if the game is already
+/// over. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_pv_san(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let (_, fullmove_number) = notation::fen_counters(current_position);
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let line = searcher.principal_variation(&current_position, depth, halfmove_clock);
+    Ok(notation::format_san_line(
+        &current_position,
+        fullmove_number,
+        &line,
+    ))
+}
+
+/// Exposed to javascript to evaluate a position, searching `depth` plies ahead
+/// (0 gives the static evaluation). The score is White-relative in centipawns
+/// (a pawn is 100), as with every score the engine reports. The score reported
+/// alongside a best move is this evaluation of the position after that move,
+/// searched to the same depth the best move was searched to. Throws if the FEN
+/// is invalid.
+#[wasm_bindgen]
+pub fn evaluate(current_position: &str, depth: u32) -> Result<i32, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    if depth == 0 {
+        return Ok(position_evaluation(&current_position, &searcher.weights));
+    }
+
+    // Searching the moves one ply shallower and taking the best is the same
+    // as searching the position itself to the full depth.
+    match searcher.search_root(&current_position, depth - 1, halfmove_clock) {
+        Some((_, eval)) => Ok(eval.value()),
+        None => Ok(position_evaluation(&current_position, &searcher.weights)),
+    }
+}
+
+/// Exposed to javascript to give the static evaluation from the point of view
+/// of the side to move, as UCI tools expect (`info score cp`): positive is good
+/// for whoever is to move. This is `evaluate` at depth 0, negated when Black is
+/// to move; `evaluate` and every other score the engine reports stay
+/// White-relative. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn evaluate_position_stm(current_position: &str) -> Result<i32, InputError> {
+    let position = board_from_fen(current_position)?;
+    Ok(color_sign(position.side_to_move()) * evaluate(current_position, 0)?)
+}
+
+/// Exposed to javascript to find positions with an "only move": one that is
+/// better than every alternative by at least `margin`. Returns the best move
+/// and whether it is an only move, separated by a semicolon (e.g.
+/// "d1 d4;true"). A position with a single legal move always has an only move.
+/// Returns "game-over" if there are no legal moves. Throws if the FEN is
+/// invalid.
+#[wasm_bindgen]
+pub fn only_move(current_position: &str, depth: u32, margin: i32) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+
+    // Order the moves best first from the point of view of the side to move.
+    let mut evals = searcher.evaluate_all_moves(&current_position, depth, halfmove_clock);
+    if current_position.side_to_move() == Color::White {
+        evals.sort_by_key(|&(_, eval)| -eval);
+    } else {
+        evals.sort_by_key(|&(_, eval)| eval);
+    }
+
+    let Some(&(best_move, best_eval)) = evals.first() else {
+        return Ok(String::from(GAME_OVER));
+    };
+    let is_only_move = evals
+        .get(1)
+        .is_none_or(|&(_, second_eval)| (best_eval.value() - second_eval.value()).abs() >= margin);
+
+    Ok(format!(
+        "{};{}",
+        notation::format_coordinate_move(&best_move),
+        is_only_move
+    ))
+}
+
+/// The least loss (in centipawns) classed as an inaccuracy.
+const INACCURACY_LOSS: i32 = 50;
+
+/// The least loss classed as a mistake.
+const MISTAKE_LOSS: i32 = 100;
+
+/// The least loss classed as a blunder.
+const BLUNDER_LOSS: i32 = 300;
+
+/// Exposed to javascript to tell a player how much their move lost against
+/// the engine's choice. `user_move` is in coordinates (e.g. "e2 e4") or SAN.
+/// Returns the eval after the user's move, the engine's best move and the eval
+/// after it, how much worse the user's move is for them in centipawns, and a
+/// classification ("best", "good", "inaccuracy", "mistake" or "blunder"), all
+/// separated by semicolons (e.g. "-280;f3 h4;960;1240;blunder"). Throws if the
+/// move is illegal.
+#[wasm_bindgen]
+pub fn blunder_check(
+    current_position: &str,
+    user_move: &str,
+    depth: u32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let user_move = match notation::parse_coordinate_move(&current_position, user_move) {
+        Some(m) => m,
+        None => notation::parse_san(&current_position, user_move)
+            .map_err(|reason| InputError::InvalidMove(String::from(user_move), reason))?,
+    };
+
+    // Every move needs an exact eval to measure the loss against, so none can
+    // be cut off early.
+    let evals = searcher.evaluate_all_moves(&current_position, depth, halfmove_clock);
+    let sign = color_sign(current_position.side_to_move());
+    let (best_move, best_eval) = evals
+        .iter()
+        .map(|&(m, eval)| (m, eval.value()))
+        .min_by_key(|&(_, eval)| -sign * eval)
+        .unwrap();
+    let user_eval = evals
+        .iter()
+        .find(|&&(m, _)| m == user_move)
+        .map(|&(_, eval)| eval.value())
+        .unwrap();
+
+    let loss = sign * (best_eval - user_eval);
+    let classification = if user_move == best_move || loss <= 0 {
+        "best"
+    } else if loss < INACCURACY_LOSS {
+        "good"
+    } else if loss < MISTAKE_LOSS {
+        "inaccuracy"
+    } else if loss < BLUNDER_LOSS {
+        "mistake"
+    } else {
+        "blunder"
+    };
+
+    Ok(format!(
+        "{};{};{};{};{}",
+        user_eval,
+        notation::format_coordinate_move(&best_move),
+        best_eval,
+        loss,
+        classification
+    ))
+}
+
+/// How far behind (in centipawns) the side to move has to be for the
+/// engine to advise resigning.
+const RESIGN_THRESHOLD: i32 = 800;
+
+/// How close to even the eval has to be for the engine to advise offering a
+/// draw.
+const DRAW_OFFER_MARGIN: i32 = 30;
+
+/// The highest game phase (see `game_phase`) a draw is offered in. Before the
+/// endgame there is too much play left to call a position dead.
+const DRAW_OFFER_MAX_PHASE: i32 = 64;
+
+/// Exposed to javascript to advise the side to move on the state of the game,
+/// from the eval of a search to `depth` (as `evaluate` gives it). Returns
+/// "resign" when the side to move is facing a forced mate or is more than
+/// RESIGN_THRESHOLD behind, "offer_draw" when the eval is within
+/// DRAW_OFFER_MARGIN of even in an endgame where neither side has a passed
+/// pawn to play for, and "play" otherwise. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn game_advice(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let position = board_from_fen(current_position)?;
+    let eval = color_sign(position.side_to_move()) * evaluate(current_position, depth)?;
+
+    let no_passed_pawns = [Color::White, Color::Black]
+        .iter()
+        .all(|&color| pawns::passed_pawns(&position, color).popcnt() == 0);
+    // A forced mate against the side to move is far below the threshold.
+    let advice = if eval < -RESIGN_THRESHOLD {
+        "resign"
+    } else if eval.abs() <= DRAW_OFFER_MARGIN
+        && game_phase(&position) <= DRAW_OFFER_MAX_PHASE
+        && no_passed_pawns
+    {
+        "offer_draw"
+    } else {
+        "play"
+    };
+    Ok(String::from(advice))
+}
+
+/// The eval (in centipawns) by which a win or loss becomes the likelier
+/// result than a draw, in the logistic model of `win_probability`.
+const WIN_PROBABILITY_DRAW_MARGIN: f64 = 150.0;
+
+/// How quickly (in centipawns) the result probabilities of
+/// `win_probability` move with the eval: the odds of a win grow by a factor
+/// of e with each WIN_PROBABILITY_SCALE the eval gains.
+const WIN_PROBABILITY_SCALE: f64 = 100.0;
+
+/// Exposed to javascript to estimate the chances of each result, for UIs that
+/// show them rather than the eval. Searches to `depth` (as `evaluate` does)
+/// and maps the eval through logistic curves: White wins with probability
+/// 1 / (1 + e^((WIN_PROBABILITY_DRAW_MARGIN - eval) / WIN_PROBABILITY_SCALE)),
+/// Black with the same for the negated eval, and the rest is a draw. With
+/// these coefficients an even position is a draw about two times in three,
+/// and three pawns up wins over four times in five. A forced mate is a
+/// certain win. Returns the percentage chances of a White win, a draw and a
+/// Black win, separated by semicolons and summing to 100 (e.g. "18;64;18").
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn win_probability(current_position: &str, depth: u32) -> Result<String, InputError> {
+    let eval = evaluate(current_position, depth)?;
+    let (white, black) = if Score::eval(eval).is_mate() {
+        if eval > 0 {
+            (100, 0)
+        } else {
+            (0, 100)
+        }
+    } else {
+        let win_chance = |eval: f64| {
+            let odds = ((eval - WIN_PROBABILITY_DRAW_MARGIN) / WIN_PROBABILITY_SCALE).exp();
+            (100.0 * odds / (1.0 + odds)).round() as i32
+        };
+        (win_chance(eval as f64), win_chance(-eval as f64))
+    };
+    Ok(format!("{};{};{}", white, 100 - white - black, black))
+}
+
+/// Exposed to javascript to search within a caller supplied (White relative)
+/// window, for use with an aspiration loop driven from outside the engine.
+/// Returns the best move, its eval and how that eval relates to the window,
+/// separated by semicolons (e.g. "e2 e4;30;exact"). The relation is "exact"
+/// when the eval lies inside the window, "fail-high" when the true eval is at
+/// least the one returned and "fail-low" when it is at most the one returned.
+/// Returns "game-over" if there are no legal moves. Throws if the FEN is
+/// invalid.
+#[wasm_bindgen]
+pub fn get_best_move_window(
+    current_position: &str,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let (alpha, beta) = (Score::eval(alpha), Score::eval(beta));
+    let Some((best_move, eval)) =
+        searcher.search_root_window(&current_position, depth, halfmove_clock, alpha, beta)
+    else {
+        return Ok(String::from(GAME_OVER));
+    };
+
+    let bound = if eval <= alpha {
+        "fail-low"
+    } else if eval >= beta {
+        "fail-high"
+    } else {
+        "exact"
+    };
+    Ok(format!(
+        "{};{};{}",
+        notation::format_coordinate_move(&best_move),
+        eval.value(),
+        bound
+    ))
+}
+
+/// Exposed to javascript to search for as long as the given number of
+/// milliseconds allows, using iterative deepening. If the game is already over
+/// the best move is "game-over". Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_timed(current_position: &str, millis: u32) -> Result<MoveResult, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    searcher.deadline = Some(Instant::now() + Duration::from_millis(millis as u64));
+    Ok(searcher
+        .iterative_deepening(&current_position, halfmove_clock, MAX_DEPTH, |_| {})
+        .unwrap_or_else(|| MoveResult::game_over(&current_position)))
+}
+
+/// Split the remaining clock time into the normal time to spend on this move
+/// and the most it may take if the best move is unstable, both in
+/// milliseconds. Most of the increment is spent as it arrives, and neither
+/// allocation ever eats into the safety buffer at the end of the clock. The
+/// `overhead` the move takes to reach the clock is reserved before either is
+/// worked out, so both are that much shorter.
+fn allocate_time(remaining_millis: u32, increment_millis: u32, overhead: u32) -> (u32, u32) {
+    let available = remaining_millis.saturating_sub(CLOCK_SAFETY_BUFFER_MILLIS + overhead);
+    let normal = remaining_millis / CLOCK_MOVES_TO_GO + increment_millis * 3 / 4;
+    let normal = normal.saturating_sub(overhead);
+    let normal = cmp::min(normal, available);
+    let extended = cmp::min(normal * UNSTABLE_TIME_FACTOR, available / 2);
+    (normal, cmp::max(normal, extended))
+}
+
+/// Exposed to javascript to play against a clock with `remaining_millis` left
+/// and `increment_millis` added after each move. A slice of the remaining time
+/// is spent on the move, more of it if the best move changes as the search
+/// deepens, but never so much that the clock runs out. The time set with
+/// `set_move_overhead` is held back from every move. Returns "game-over" if
+/// there is no move to make. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_clock(
+    current_position: &str,
+    remaining_millis: u32,
+    increment_millis: u32,
+) -> Result<String, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+
+    let overhead = MOVE_OVERHEAD_MILLIS.with(Cell::get);
+    let (normal, extended) = allocate_time(remaining_millis, increment_millis, overhead);
+    let start = Instant::now();
+    searcher.deadline = Some(start + Duration::from_millis(normal as u64));
+    searcher.extended_deadline = Some(start + Duration::from_millis(extended as u64));
+    Ok(searcher
+        .iterative_deepening(&current_position, halfmove_clock, MAX_DEPTH, |_| {})
+        .map_or_else(|| String::from(GAME_OVER), |result| result.best_move))
+}
+
+/// Exposed to javascript to search to `depth` but take at least `min_millis`
+/// over it, as an instant reply feels jarring in a UI. Any of the floor left
+/// once `depth` is searched goes on searching deeper, keeping the best move of
+/// the deepest search to finish in time. The time is only ever spent
+/// searching, never waiting, so should the search reach MAX_DEPTH first the
+/// move comes back early and any further delay is up to the caller. Returns
+/// "game-over" at once if there are no legal moves. Throws if the FEN is
+/// invalid.
+#[wasm_bindgen]
+pub fn get_best_move_min_time(
+    current_position: &str,
+    depth: u32,
+    min_millis: u32,
+) -> Result<String, InputError> {
+    let floor = Instant::now() + Duration::from_millis(min_millis as u64);
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    let Some((mut best_move, _)) = searcher.search_root(&current_position, depth, halfmove_clock)
+    else {
+        return Ok(String::from(GAME_OVER));
+    };
+
+    searcher.deadline = Some(floor);
+    for deeper in depth + 1..=MAX_DEPTH {
+        match searcher.search_root(&current_position, deeper, halfmove_clock) {
+            Some((m, _)) if !searcher.stopped => best_move = m,
+            _ => break,
+        }
+    }
+
+    Ok(notation::format_coordinate_move(&best_move))
+}
+
+/// Exposed to javascript to search with iterative deepening up to `max_depth`,
+/// calling `callback(depth, best_move, score)` as each depth completes so a UI
+/// can show the search progressing. Returns the final best move, or
+/// "game-over" without calling `callback` if the game is already over.
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_with_progress(
+    current_position: &str,
+    max_depth: u32,
+    callback: &js_sys::Function,
+) -> Result<String, InputError> {
+    let report = |result: &MoveResult| {
+        // Errors thrown by the callback are the caller's concern, they
+        // shouldn't abort the search.
+        let _ = callback.call3(
+            &JsValue::NULL,
+            &JsValue::from(result.depth),
+            &JsValue::from_str(&result.best_move),
+            &JsValue::from(result.score.value()),
+        );
+    };
+    Ok(search_with_progress(current_position, max_depth, report)?.best_move)
+}
+
+/// Exposed to javascript to show how the engine's choice develops with depth.
+/// Returns one line per depth from 1 to `max_depth`, each giving the depth, the
+/// best move and its score separated by semicolons (e.g. "3;e2 e4;20").
+/// Returns an empty string if the game is already over. Throws if the FEN is
+/// invalid.
+#[wasm_bindgen]
+pub fn best_move_by_depth(current_position: &str, max_depth: u32) -> Result<String, InputError> {
+    let mut lines = Vec::new();
+    search_with_progress(current_position, max_depth, |result| {
+        if result.depth > 0 {
+            lines.push(format!(
+                "{};{};{}",
+                result.depth,
+                result.best_move,
+                result.score.value()
+            ));
+        }
+    })?;
+    Ok(lines.join("\n"))
+}
+
+/// Run an iterative deepening search up to `max_depth`, reporting each
+/// completed iteration to `on_iteration`. A position with no legal moves has
+/// no iterations, and gives `MoveResult::game_over`.
+fn search_with_progress(
+    current_position: &str,
+    max_depth: u32,
+    on_iteration: impl FnMut(&MoveResult),
+) -> Result<MoveResult, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    Ok(searcher
+        .iterative_deepening(&current_position, halfmove_clock, max_depth, on_iteration)
+        .unwrap_or_else(|| MoveResult::game_over(&current_position)))
+}
+
+/// Exposed to javascript to search with iterative deepening up to `depth` and
+/// report statistics on how the search went, rather than its result.
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn search_stats(current_position: &str, depth: u32) -> Result<SearchStats, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+
+    let mut iteration_nodes = Vec::new();
+    for depth in 0..=depth {
+        let nodes_before = searcher.nodes;
+        searcher.search_root(&current_position, depth, halfmove_clock);
+        iteration_nodes.push(searcher.nodes - nodes_before);
+    }
+
+    let branching_factor = match iteration_nodes[..] {
+        [.., previous, last] if previous > 0 => last as f64 / previous as f64,
+        _ => 0.0,
+    };
+    Ok(SearchStats {
+        nodes: searcher.nodes,
+        qnodes: searcher.qnodes,
+        tt_probes: searcher.tt.probes,
+        tt_hits: searcher.tt.hits,
+        cutoffs: searcher.cutoffs,
+        branching_factor,
+    })
+}
+
+/// Exposed to javascript to search until roughly the given number of nodes
+/// have been visited, using iterative deepening. If the game is already over
+/// the best move is "game-over". Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn get_best_move_nodes(current_position: &str, nodes: u32) -> Result<MoveResult, InputError> {
+    let (current_position, mut searcher, halfmove_clock) = prepare_search(current_position)?;
+    searcher.max_nodes = Some(nodes as u64);
+    Ok(searcher
+        .iterative_deepening(&current_position, halfmove_clock, MAX_DEPTH, |_| {})
+        .unwrap_or_else(|| MoveResult::game_over(&current_position)))
+}
+
+// Searching an invalid FEN is an error saying what is wrong, not a panic, and
+// so is searching a valid one with no moves left.
+#[test]
+fn search_rejects_invalid_fen() {
+    assert_eq!(
+        get_best_move_minimax_alpha_beta("8/8/8/8/8/8/8/4K3 w - - 0 1", 2)
+            .unwrap_err()
+            .to_string(),
+        "invalid FEN \"8/8/8/8/8/8/8/4K3 w - - 0 1\": missing black king"
+    );
+    assert!(evaluate("4k3/8/8/8/8/8/8/4K3 w - - x 1", 0).is_err());
+    assert!(get_best_move_with_history(START_FEN, "not a fen", 1).is_err());
+
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    assert_eq!(
+        get_best_move_minimax_alpha_beta(checkmate, 2).unwrap(),
+        GAME_OVER
+    );
+    assert_eq!(
+        get_best_move_with_history(stalemate, START_FEN, 2).unwrap(),
+        GAME_OVER
+    );
+}
+
+// With everything else equal, an extra pawn is worth about 100.
+#[test]
+fn pawn_is_worth_a_hundred() {
+    let extra_pawn = "rnbqkbnr/1ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let eval = evaluate(extra_pawn, 0).unwrap();
+    assert!((80..=120).contains(&eval), "{}", eval);
+    assert_eq!(PIECE_VALUES[Piece::Pawn.to_index()].0, 100);
+}
+
+// Initialising is safe to repeat.
+#[test]
+fn init_is_idempotent() {
+    init();
+    init();
+    assert_eq!(
+        get_best_move_minimax_alpha_beta("4k3/8/8/8/8/8/8/R3K3 w - - 0 30", 1).unwrap(),
+        "a1 a7"
+    );
+}
+
+// The version reported is the package's, followed by the features built in.
+#[test]
+fn reports_engine_info() {
+    let info = engine_info();
+    let (version, features) = info.split_once(';').unwrap();
+    assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        features
+            .split(',')
+            .any(|feature| feature == "console_error_panic_hook"),
+        cfg!(feature = "console_error_panic_hook")
+    );
+}
+
+// Without the rook on d1 there is no queen to take, and a removed corner rook
+// takes its castling right with it. Empty squares and removing a king are
+// refused.
+#[test]
+fn searches_handicap_positions() {
+    let fen = "3qk3/8/8/8/8/8/8/3RK3 w - - 0 1";
+    assert_eq!(get_best_move_minimax_alpha_beta(fen, 1).unwrap(), "d1 d8");
+    let without_rook = get_best_move_handicap(fen, 1, "d1").unwrap();
+    let position = parse_fen("3qk3/8/8/8/8/8/8/4K3 w - - 0 1");
+    assert!(
+        MoveGen::new_legal(&position).any(|m| notation::format_coordinate_move(&m) == without_rook)
+    );
+
+    // Keeping the rights to castle with the rooks gone would be an invalid FEN.
+    let castling = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+    assert!(get_best_move_handicap(castling, 0, "a1, h8").is_ok());
+
+    assert_eq!(
+        get_best_move_handicap(fen, 1, "e4")
+            .unwrap_err()
+            .to_string(),
+        "\"e4\" isn't a square with a piece on it"
+    );
+    assert_eq!(
+        get_best_move_handicap(fen, 1, "e1")
+            .unwrap_err()
+            .to_string(),
+        "invalid FEN \"3qk3/8/8/8/8/8/8/3R4 w - - 0 1\": missing white king"
+    );
+}
+
+// Simple functionality test.
+#[test]
+fn mate_in_one() {
+    let mate_in_one_fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1";
+    let best_move = get_best_move_minimax_alpha_beta(mate_in_one_fen, 3).unwrap();
+    assert_eq!(best_move, "h5 f7");
+}
+
+// A winning side shouldn't walk into a threefold repetition at the root, even
+// when the position it leads to is already at the search's horizon.
+#[test]
+fn root_avoids_threefold_repetition() {
+    let fen = "7k/8/8/8/8/3Q4/8/2K5 w - - 10 60";
+    let position = parse_fen(fen);
+
+    for depth in 0..=2 {
+        // Find the move the engine prefers without any history, then pretend
+        // the position it leads to has already occurred twice.
+        let preferred = get_best_move_minimax_alpha_beta(fen, depth).unwrap();
+        let repeated_position = MoveGen::new_legal(&position)
+            .find(|m| notation::format_coordinate_move(m) == preferred)
+            .map(|m| position.make_move_new(m))
+            .unwrap();
+        let history = format!("{}\n{}\n{}", repeated_position, fen, repeated_position);
+
+        let best_move = get_best_move_with_history(fen, &history, depth).unwrap();
+        assert_ne!(best_move, preferred, "depth {}", depth);
+    }
+}
+
+// A bigger time budget should never result in a shallower search.
+#[test]
+fn timed_search_reports_depth() {
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    let short = get_best_move_timed(fen, 20).unwrap();
+    let long = get_best_move_timed(fen, 400).unwrap();
+    assert!(long.depth() >= short.depth());
+    assert!(long.depth() >= 1);
+}
+
+// Node budgets are deterministic, so the same comparison holds exactly.
+#[test]
+fn node_limited_search_reports_depth() {
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    let small = get_best_move_nodes(fen, 500).unwrap();
+    let large = get_best_move_nodes(fen, 50000).unwrap();
+    assert!(large.depth() > small.depth());
+    assert_eq!(
+        large.best_move(),
+        get_best_move_minimax_alpha_beta(fen, large.depth()).unwrap()
+    );
+}
+
+// With no legal moves left the budgeted searches report the game as over
+// rather than a move, scoring checkmate as a mate and stalemate as a draw.
+#[test]
+fn budgeted_search_of_finished_game() {
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    let mated = get_best_move_nodes(checkmate, 1000).unwrap();
+    assert_eq!(mated.best_move(), GAME_OVER);
+    assert_eq!(mated.score_text(), "mate 0");
+    let stalemated = get_best_move_timed(stalemate, 20).unwrap();
+    assert_eq!(
+        (stalemated.best_move(), stalemated.score()),
+        (String::from(GAME_OVER), 0)
+    );
+    assert_eq!(get_best_move_clock(checkmate, 60000, 0).unwrap(), GAME_OVER);
+    assert_eq!(best_move_by_depth(stalemate, 3).unwrap(), "");
+}
+
+// Search every root move independently with the full window, as the root used
+// to, returning the best move along with the number of nodes visited.
+#[cfg(test)]
+fn full_window_root(position: &Board, depth: u32) -> (Option<(ChessMove, Score)>, u64) {
+    let maximising = position.side_to_move() == Color::White;
+    let mut searcher = Searcher::new(vec![position_key(position)]);
+    let mut best_move: Option<(ChessMove, Score)> = None;
+    for (candidate_move, eval) in searcher.evaluate_all_moves(position, depth, 0) {
+        if best_move.is_none_or(|(_, top_eval)| {
+            (maximising && eval > top_eval) || (!maximising && eval < top_eval)
+        }) {
+            best_move = Some((candidate_move, eval));
+        }
+    }
+    (best_move, searcher.nodes)
+}
+
+// Reusing the window across root moves shouldn't change the chosen move or its
+// evaluation, for either side.
+#[test]
+fn root_window_reuse_matches_full_window() {
+    for fen in [
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+    ] {
+        let position = parse_fen(fen);
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        let (expected, _) = full_window_root(&position, 2);
+        assert_eq!(searcher.search_root(&position, 2, 0), expected);
+    }
+}
+
+// Reusing the window across root moves should visit fewer nodes than
+// searching every move with the full window.
+#[test]
+fn root_window_reuse_saves_nodes() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let position = parse_fen(fen);
+    let mut searcher = Searcher::new(vec![position_key(&position)]);
+    searcher.search_root(&position, 2, 0);
+    let (_, full_window_nodes) = full_window_root(&position, 2);
+    assert!(searcher.nodes < full_window_nodes);
+}
+
+// Capturing the checking rook wins a queen's worth of material but stalemates
+// Black, so it scores no better than losing the queen. A winning engine should
+// keep playing rather than take the draw, even though stalemate is still a draw.
+#[test]
+fn winning_side_avoids_stalemate() {
+    let fen = "8/4R3/8/8/1Q5r/8/7K/5k2 w - - 0 1";
+    let stalemate = parse_fen(fen).make_move_new(ChessMove::new(Square::B4, Square::H4, None));
+    assert_eq!(stalemate.status(), BoardStatus::Stalemate);
+    assert_eq!(position_evaluation(&stalemate, &EvalWeights::default()), 0);
+
+    let best_move = get_best_move_minimax_alpha_beta(fen, 1).unwrap();
+    assert_ne!(best_move, "b4 h4");
+}
+
+// The progress callback fires once for every depth, shallowest first, and the
+// last report matches the final result.
+#[test]
+fn progress_callback_reports_each_depth() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1";
+    let mut reports: Vec<(u32, String, Score)> = Vec::new();
+    let result = search_with_progress(fen, 3, |result| {
+        reports.push((result.depth, result.best_move.clone(), result.score))
+    })
+    .unwrap();
+
+    let depths: Vec<u32> = reports.iter().map(|(depth, _, _)| *depth).collect();
+    assert_eq!(depths, vec![0, 1, 2, 3]);
+    assert_eq!(
+        reports.last(),
+        Some(&(result.depth, result.best_move, result.score))
+    );
+}
+
+// Recapturing the knight is the only move that doesn't lose material. Once
+// the game is over there is no move at all.
+#[test]
+fn only_move_recapture() {
+    let fen = "r1bqkbnr/pppp1ppp/8/8/3nP3/8/PPP2PPP/RNBQKB1R w KQkq - 0 5";
+    assert_eq!(only_move(fen, 1, 20).unwrap(), "d1 d4;true");
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    assert_eq!(only_move(checkmate, 1, 20).unwrap(), GAME_OVER);
+}
+
+// No move stands out in the starting position.
+#[test]
+fn no_only_move_in_quiet_position() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert!(only_move(fen, 1, 20).unwrap().ends_with(";false"));
+}
+
+// A rook is worth a little less once the pawns have come off.
+#[test]
+fn rook_value_depends_on_pawns() {
+    let pawnless = parse_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+    let pawn_heavy = parse_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K3 w - - 0 1");
+    let rook = phased_value(Piece::Rook, game_phase(&pawnless));
+    assert!(piece_score(&pawnless) < piece_score(&pawn_heavy));
+    assert!((piece_score(&pawnless) - rook).abs() <= ROOK_PAWN_ADJUSTMENT);
+    assert!((piece_score(&pawn_heavy) - rook).abs() <= ROOK_PAWN_ADJUSTMENT);
+}
+
+// A window containing the true eval reproduces the full search exactly, while
+// windows either side of it report the matching bound. A finished game has no
+// move to report.
+#[test]
+fn caller_supplied_window() {
+    let fen = "r1bqkbnr/pppp1ppp/8/8/3nP3/8/PPP2PPP/RNBQKB1R w KQkq - 0 5";
+    let position = parse_fen(fen);
+    let (best_move, eval) = Searcher::new(vec![position_key(&position)])
+        .search_root(&position, 1, 0)
+        .unwrap();
+    let eval = eval.value();
+
+    assert_eq!(
+        get_best_move_window(fen, 1, eval - 5, eval + 5).unwrap(),
+        format!(
+            "{};{};exact",
+            notation::format_coordinate_move(&best_move),
+            eval
+        )
+    );
+
+    // The window is entirely below the true eval.
+    let fail_high = get_best_move_window(fen, 1, eval - 20, eval - 10).unwrap();
+    let fields: Vec<&str> = fail_high.split(';').collect();
+    assert_eq!(fields[2], "fail-high");
+    assert!(fields[1].parse::<i32>().unwrap() <= eval);
+
+    // The window is entirely above the true eval.
+    let fail_low = get_best_move_window(fen, 1, eval + 10, eval + 20).unwrap();
+    let fields: Vec<&str> = fail_low.split(';').collect();
+    assert_eq!(fields[2], "fail-low");
+    assert!(fields[1].parse::<i32>().unwrap() >= eval);
+
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    assert_eq!(
+        get_best_move_window(stalemate, 1, -10, 10).unwrap(),
+        GAME_OVER
+    );
+}
+
+// The expected line for the mate in one ends with the mate, marked "#".
+#[test]
+fn pv_san_shows_mate() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 4";
+    assert_eq!(get_pv_san(fen, 0).unwrap(), "4. Qxf7#");
+    assert_eq!(get_pv_san(fen, 2).unwrap(), "4. Qxf7#");
+}
+
+// The mate in one is reported as such.
+#[test]
+fn reports_mate_in_one() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1";
+    let result = search_with_progress(fen, 2, |_| {}).unwrap();
+    assert_eq!(result.best_move(), "h5 f7");
+    assert_eq!(result.score_text(), "mate 1");
+}
+
+// A rook ladder takes two moves, and mates by Black are reported as negative.
+#[test]
+fn reports_mate_in_two() {
+    let white_mates = search_with_progress("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 3, |_| {}).unwrap();
+    assert_eq!(white_mates.score_text(), "mate 2");
+
+    let black_mates = search_with_progress("1r4k1/r7/8/8/8/8/8/7K b - - 0 1", 3, |_| {}).unwrap();
+    assert_eq!(black_mates.score_text(), "mate -2");
+}
+
+// At a high aggression the knight jumps towards the castled king at the cost
+// of a pawn, where the normal weights pin the knight with the bishop. A side
+// with no moves left still has none however it is weighted.
+#[test]
+fn aggression_prefers_attacking_sacrifice() {
+    let fen = "rnbq1rk1/pp2bppp/4pn2/2pp4/2PP2P1/2N2N2/PP2PP1P/R1BQKB1R w KQ - 0 8";
+    let safe_move = get_best_move_minimax_alpha_beta(fen, 1).unwrap();
+    let attacking_move = get_best_move_weighted(fen, 1, 1000).unwrap();
+    assert_eq!(safe_move, "c1 g5");
+    assert_eq!(attacking_move, "f3 g5");
+
+    // Judged normally, the knight move costs a pawn.
+    let position = parse_fen(fen);
+    let evals = Searcher::new(vec![position_key(&position)]).evaluate_all_moves(&position, 1, 0);
+    let eval_of = |text: &str| {
+        evals
+            .iter()
+            .find(|(m, _)| notation::format_coordinate_move(m) == text)
+            .map(|&(_, eval)| eval.value())
+            .unwrap()
+    };
+    assert!(eval_of(&attacking_move) <= eval_of(&safe_move) - 10);
+
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    assert_eq!(
+        get_best_move_weighted(checkmate, 1, 1000).unwrap(),
+        GAME_OVER
+    );
+}
+
+// Development matters early on, so the same position is played differently at
+// move 2, where a knight comes out, than at move 30.
+#[test]
+fn development_fades_with_move_number() {
+    let opening = "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+    let late = "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 30";
+    assert_eq!(
+        get_best_move_minimax_alpha_beta(opening, 2).unwrap(),
+        "b1 c3"
+    );
+    assert_eq!(get_best_move_minimax_alpha_beta(late, 2).unwrap(), "d1 f3");
+
+    assert_eq!(EvalWeights::for_move_number(2).development, 100);
+    assert_eq!(EvalWeights::for_move_number(15).development, 50);
+    assert_eq!(EvalWeights::for_move_number(30).development, 0);
+}
+
+// Noise is reproducible for a given seed, but different seeds vary the play.
+// No amount of noise finds a move in a finished game.
+#[test]
+fn seeded_noise_is_reproducible() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let first = get_best_move_noisy(start_fen, 2, 1, 200).unwrap();
+    assert_eq!(get_best_move_noisy(start_fen, 2, 1, 200).unwrap(), first);
+    assert!((2..20).any(|seed| get_best_move_noisy(start_fen, 2, seed, 200).unwrap() != first));
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    assert_eq!(
+        get_best_move_noisy(stalemate, 2, 1, 200).unwrap(),
+        GAME_OVER
+    );
+
+    let position = parse_fen(start_fen);
+    let weights = EvalWeights {
+        noise_amplitude: 5,
+        ..EvalWeights::default()
+    };
+    for seed in 0..100 {
+        let noise = eval_noise(
+            &position,
+            &EvalWeights {
+                noise_seed: seed,
+                ..weights
+            },
+        );
+        assert!(noise.abs() <= 5);
+    }
+}
+
+// Different seeds open differently, but once the opening is over the seed no
+// longer matters and the engine plays its best move. A game lost in the
+// opening has no move to vary.
+#[test]
+fn opening_variety_only_in_opening() {
+    let first = get_best_move_opening_variety(START_FEN, 1, 4, 0).unwrap();
+    assert_eq!(
+        get_best_move_opening_variety(START_FEN, 1, 4, 0).unwrap(),
+        first
+    );
+    assert!(
+        (1..10).any(|seed| get_best_move_opening_variety(START_FEN, 1, 4, seed).unwrap() != first)
+    );
+
+    let middlegame = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let best = get_best_move_minimax_alpha_beta(middlegame, 1).unwrap();
+    for seed in 0..10 {
+        assert_eq!(
+            get_best_move_opening_variety(middlegame, 1, 4, seed).unwrap(),
+            best
+        );
+    }
+
+    let fools_mate = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+    assert_eq!(
+        get_best_move_opening_variety(fools_mate, 1, 10, 0).unwrap(),
+        GAME_OVER
+    );
+}
+
+// A piece up is a clear win for White, an even position most likely a draw,
+// and a forced mate a certain win.
+#[test]
+fn win_probability_from_eval() {
+    let knight_up = "rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let chances: Vec<i32> = win_probability(knight_up, 1)
+        .unwrap()
+        .split(';')
+        .map(|chance| chance.parse().unwrap())
+        .collect();
+    assert_eq!(chances.iter().sum::<i32>(), 100);
+    assert!(chances[0] > 60 && chances[2] < 5);
+
+    // The starting position's static eval is exactly even.
+    let even: Vec<i32> = win_probability(START_FEN, 0)
+        .unwrap()
+        .split(';')
+        .map(|chance| chance.parse().unwrap())
+        .collect();
+    assert_eq!(even[0], even[2]);
+    assert!(even[1] > 50);
+
+    assert_eq!(
+        win_probability("k7/8/1K6/8/8/8/8/7R w - - 0 1", 1).unwrap(),
+        "100;0;0"
+    );
+}
+
+// The best move here takes the queen, but the best quiet move develops a
+// piece. A king in check with only a capture to escape has no quiet move.
+#[test]
+fn best_quiet_move_skips_forcing_moves() {
+    let fen = "rnb1kbnr/pppp1ppp/8/4p3/3qP3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 4";
+    assert_eq!(get_best_move_minimax_alpha_beta(fen, 2).unwrap(), "f3 d4");
+    assert_eq!(best_quiet_move(fen, 2).unwrap(), "f1 b5");
+
+    assert_eq!(
+        best_quiet_move("7k/8/8/8/8/8/6q1/7K w - - 0 1", 1).unwrap(),
+        "none"
+    );
+}
+
+// Razoring skips most of the quiet moves of a side that is hopelessly behind,
+// without changing the result.
+#[test]
+fn razoring_saves_nodes() {
+    let fen = "4k3/pp3ppp/8/8/8/8/PPQ2PPP/R3K2R w - - 0 1";
+    let search = |razoring| {
+        let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+        searcher.razoring = razoring;
+        let (best_move, eval) = searcher.search_root(&position, 2, halfmove_clock).unwrap();
+        (
+            notation::format_coordinate_move(&best_move),
+            eval,
+            searcher.nodes,
+        )
+    };
+    let (razored_move, razored_eval, razored_nodes) = search(true);
+    let (full_move, full_eval, full_nodes) = search(false);
+    assert_eq!(razored_move, full_move);
+    assert_eq!(razored_eval, full_eval);
+    assert!(razored_nodes < full_nodes);
+}
+
+// The cheap test for checks never misses one that playing out the moves finds,
+// discovered checks included, and rules them out where no piece is near.
+#[test]
+fn may_give_check_never_misses_one() {
+    let gives_check = |position: &Board| {
+        MoveGen::new_legal(position).any(|m| position.make_move_new(m).checkers().popcnt() > 0)
+    };
+    for seed in 0..40 {
+        for plies in [4, 20, 60] {
+            let position = parse_fen(&random_position(seed, plies));
+            assert!(!gives_check(&position) || may_give_check(&position));
+        }
+    }
+    assert!(may_give_check(&parse_fen(
+        "4k3/8/8/8/8/4N3/8/4RK2 w - - 0 1"
+    )));
+    assert!(!may_give_check(&parse_fen("k7/8/8/8/8/8/PP6/K7 w - - 0 1")));
+}
+
+// Promoting to a knight with check forks the king and queen, where a queen
+// promotion only keeps the material level. The queen promotion is still
+// searched first.
+#[test]
+fn finds_knight_underpromotion() {
+    let fen = "8/3q1P2/6k1/8/8/8/8/7K w - - 0 1";
+    let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+    let (best_move, eval) = searcher.search_root(&position, 2, halfmove_clock).unwrap();
+    assert_eq!(best_move.get_promotion(), Some(Piece::Knight));
+    assert!(eval.value() > 0);
+
+    let ordered = root_moves(&position, None, DEFAULT_PROMOTION_ORDERING);
+    assert_eq!(ordered[0].get_promotion(), Some(Piece::Queen));
+    assert!(ordered[..4].iter().all(|m| m.get_promotion().is_some()));
+}
+
+// The best move names the piece promoted to, so a UI playing it makes the
+// knight that forks king and queen rather than a queen that is taken at once.
+#[test]
+fn best_move_names_underpromotion() {
+    let fen = "8/3q1P2/6k1/8/8/8/8/7K w - - 0 1";
+    assert_eq!(get_best_move_minimax_alpha_beta(fen, 2).unwrap(), "f7 f8n");
+}
+
+// After the bishop takes the knight, taking back is searched before the
+// bigger but losing capture of the rook, and is the move played. Quiescence
+// tries it first too, and the leaves of an unrazored search find it there.
+#[test]
+fn recapture_searched_first() {
+    let before = "r5k1/1p3ppp/2n5/1B6/6q1/8/5PPP/3RK3 w - - 0 20";
+    let fen = "r5k1/1p3ppp/2B5/8/6q1/8/5PPP/3RK3 b - - 0 20";
+    let position = parse_fen(fen);
+    assert_eq!(
+        notation::format_coordinate_move(
+            &root_moves(&position, None, DEFAULT_PROMOTION_ORDERING)[0]
+        ),
+        "g4 d1"
+    );
+    assert_eq!(
+        notation::format_coordinate_move(
+            &root_moves(&position, Some(Square::C6), DEFAULT_PROMOTION_ORDERING)[0]
+        ),
+        "b7 c6"
+    );
+    assert_eq!(get_best_move_with_history(fen, before, 2).unwrap(), "b7 c6");
+
+    let quiescence_nodes = |recapture_square| {
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        let window = (Score::eval(-1060), Score::eval(-1059));
+        let eval = searcher.quiescence(position, window.0, window.1, recapture_square);
+        assert!(eval <= window.0);
+        searcher.nodes
+    };
+    assert!(quiescence_nodes(Some(Square::C6)) < quiescence_nodes(None));
+
+    let mut searcher = Searcher::new(vec![position_key(&position)]);
+    searcher.razoring = false;
+    searcher
+        .moves
+        .push(ChessMove::new(Square::B5, Square::C6, None));
+    let (best_move, _) = searcher.search_root(&position, 0, 0).unwrap();
+    assert_eq!(notation::format_coordinate_move(&best_move), "b7 c6");
+}
+
+// In check the best evasion is returned with its eval, and the precondition is
+// reported when it doesn't hold.
+#[test]
+fn check_responses() {
+    let fen = "4k3/8/8/8/8/8/3q4/4K2R w K - 0 1";
+    let response = best_check_response(fen, 1).unwrap();
+    let (evasion, eval) = response.split_once(';').unwrap();
+    assert_eq!(evasion, "e1 d2");
+    assert!(eval.parse::<i32>().unwrap() > 0);
+
+    let position = parse_fen(fen);
+    assert!(notation::parse_coordinate_move(&position, evasion).is_some());
+
+    assert_eq!(best_check_response(START_FEN, 1).unwrap(), "not-in-check");
+    let mated = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+    assert_eq!(best_check_response(mated, 1).unwrap(), "checkmate");
+}
+
+// Excluding the best move gives the next best, scored as well as any other
+// move that is left.
+#[test]
+fn best_move_excluding_the_top_move() {
+    let fen = "rnb1kbnr/pppp1ppp/8/4p3/3qP3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 4";
+    let best = get_best_move_minimax_alpha_beta(fen, 1).unwrap();
+    let next = best_move_excluding(fen, 1, &best).unwrap();
+    assert_ne!(next, best);
+
+    let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+    let evals = searcher.evaluate_all_moves(&position, 1, halfmove_clock);
+    let eval_of = |text: &str| {
+        evals
+            .iter()
+            .find(|(m, _)| notation::format_coordinate_move(m) == text)
+            .map(|&(_, eval)| eval)
+            .unwrap()
+    };
+    let next_best = evals
+        .iter()
+        .filter(|(m, _)| notation::format_coordinate_move(m) != best)
+        .map(|&(_, eval)| eval)
+        .max()
+        .unwrap();
+    assert_eq!(eval_of(&next), next_best);
+
+    // Illegal moves are ignored, malformed ones are errors.
+    assert_eq!(
+        best_move_excluding(fen, 1, &format!("{}\na1 a8", best)).unwrap(),
+        next
+    );
+    assert!(best_move_excluding(fen, 1, "e2").is_err());
+}
+
+// Every move of a lone king scores the same at depth 1, so the choice comes
+// down to the documented tie-break: the capture with the most valuable victim
+// first, then the smallest coordinates.
+#[test]
+fn deterministic_tie_break() {
+    let quiet = "4k3/8/8/8/8/8/8/4K3 w - - 0 30";
+    let position = parse_fen(quiet);
+    let evals = Searcher::new(vec![position_key(&position)]).evaluate_all_moves(&position, 1, 0);
+    assert!(
+        evals
+            .iter()
+            .filter(|&&(_, eval)| eval == evals[0].1)
+            .count()
+            > 1
+    );
+    assert_eq!(
+        get_best_move_minimax_alpha_beta(quiet, 1).unwrap(),
+        notation::format_coordinate_move(&evals[0].0)
+    );
+
+    let position = parse_fen("4k3/8/8/3r1n2/4P3/8/8/4K3 w - - 0 30");
+    let order: Vec<String> = root_moves(&position, None, DEFAULT_PROMOTION_ORDERING)
+        .iter()
+        .map(notation::format_coordinate_move)
+        .collect();
+    assert_eq!(order[0], "e4 d5");
+    assert_eq!(order[1], "e4 f5");
+    assert!(order[2..].windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+// One line per depth, the last agreeing with a fixed depth search.
+#[test]
+fn best_move_at_each_depth() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let report = best_move_by_depth(fen, 3).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for (i, line) in lines.iter().enumerate() {
+        assert!(line.starts_with(&format!("{};", i + 1)));
+    }
+
+    let last: Vec<&str> = lines[2].split(';').collect();
+    assert_eq!(last[1], get_best_move_minimax_alpha_beta(fen, 3).unwrap());
+}
+
+/// The FEN of the position with the board flipped top to bottom and the
+/// colors swapped, so the same position arises with the roles reversed.
+#[cfg(test)]
+fn mirror_fen(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let swap_case = |text: &str| -> String {
+        text.chars()
+            .map(|c| {
+                if c.is_ascii_uppercase() {
+                    c.to_ascii_lowercase()
+                } else {
+                    c.to_ascii_uppercase()
+                }
+            })
+            .collect()
+    };
+
+    let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+    let side = if fields[1] == "w" { "b" } else { "w" };
+    let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+    castling.sort_by_key(|c| (c.is_ascii_lowercase(), *c != 'K' && *c != 'k'));
+    let en_passant: String = fields[3]
+        .chars()
+        .map(|c| match c {
+            '3' => '6',
+            '6' => '3',
+            _ => c,
+        })
+        .collect();
+    format!(
+        "{} {} {} {} {}",
+        placement.join("/"),
+        side,
+        castling.into_iter().collect::<String>(),
+        en_passant,
+        fields[4..].join(" ")
+    )
+}
+
+// Evals are White-relative, so swapping the colors of a position has to flip
+// the sign of its eval and nothing else.
+#[test]
+fn evaluation_is_color_symmetric() {
+    let weights = EvalWeights::default();
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "rnbq1rk1/pp2bppp/4pn2/2pp4/2PP2P1/2N2N2/PP2PP1P/R1BQKB1R w KQ - 0 8",
+        "r2q2rk/5pp1/8/8/8/8/5P1P/R2Q1RK1 w - - 0 1",
+        "8/4k3/8/P7/8/8/8/R3K3 w - - 0 1",
+        "6k1/RR6/8/8/8/8/5PPP/6K1 w - - 0 1",
+        "4k3/p1p5/p7/4p3/2P5/3P3P/7P/4K3 w - - 0 1",
+    ] {
+        let position = parse_fen(fen);
+        let mirrored = parse_fen(&mirror_fen(fen));
+        assert_eq!(
+            position_evaluation(&position, &weights),
+            -position_evaluation(&mirrored, &weights),
+            "{}",
+            fen
+        );
+    }
+}
+
+// Allocations stay inside the clock, however little is left.
+#[test]
+fn clock_allocation() {
+    assert_eq!(allocate_time(60_000, 0, 0), (2_000, 6_000));
+    assert_eq!(allocate_time(60_000, 1_000, 0), (2_750, 8_250));
+    for remaining in [0, 10, 50, 100, 1_000] {
+        for increment in [0, 100, 5_000] {
+            for overhead in [0, 30, 500] {
+                let (normal, extended) = allocate_time(remaining, increment, overhead);
+                assert!(normal <= extended && extended <= remaining);
+            }
+        }
+    }
+}
+
+// The move overhead comes straight off the time searched: with 15 seconds
+// left, a 200ms overhead cuts the 500ms slice to 300ms and the longest the
+// search may be extended to from 1.5s to 900ms, and the search keeps within
+// them.
+#[test]
+fn move_overhead_shortens_search() {
+    assert_eq!(allocate_time(60_000, 0, 100), (1_900, 5_700));
+    assert_eq!(allocate_time(60_000, 1_000, 100), (2_650, 7_950));
+    assert_eq!(allocate_time(15_000, 0, 0), (500, 1_500));
+    assert_eq!(allocate_time(15_000, 0, 200), (300, 900));
+
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    set_move_overhead(200);
+    let start = Instant::now();
+    let best_move = get_best_move_clock(fen, 15_000, 0);
+    let elapsed = start.elapsed();
+    set_move_overhead(DEFAULT_MOVE_OVERHEAD_MILLIS);
+
+    assert!(best_move.is_ok());
+    assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1_200), "{:?}", elapsed);
+}
+
+// A clocked search finishes inside its allocation (give or take the time
+// between clock checks), and still finds a legal move with almost no time.
+#[test]
+fn clock_search_respects_allocation() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let position = parse_fen(fen);
+    let is_legal = |text: &str| {
+        MoveGen::new_legal(&position).any(|m| notation::format_coordinate_move(&m) == text)
+    };
+
+    let (_, extended) = allocate_time(3_000, 0, DEFAULT_MOVE_OVERHEAD_MILLIS);
+    let start = Instant::now();
+    let best_move = get_best_move_clock(fen, 3_000, 0).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(extended as u64 + 250));
+    assert!(is_legal(&best_move));
+
+    let start = Instant::now();
+    let best_move = get_best_move_clock(fen, 20, 0).unwrap();
+    assert!(start.elapsed() < Duration::from_millis(250));
+    assert!(is_legal(&best_move));
+}
+
+// Knowing the knight has already moved, the engine develops a bishop rather
+// than moving the knight again to hit the queen. Without the moves it can't
+// tell, and the knight move comes out ahead by less than the penalty.
+#[test]
+fn prefers_developing_a_new_piece() {
+    let history = "rnbqkbnr/pppp1ppp/8/4p3/8/1P6/P1PPPPPP/RNBQKBNR w KQkq - 0 2\n\
+                   rnbqkbnr/pppp1ppp/8/4p3/8/1PN5/P1PPPPPP/R1BQKBNR b KQkq - 1 2";
+    let fen = "rnb1kbnr/pppp1ppp/5q2/4p3/8/1PN5/P1PPPPPP/R1BQKBNR w KQkq - 2 3";
+    assert_eq!(get_best_move_minimax_alpha_beta(fen, 2).unwrap(), "c3 d5");
+    assert_eq!(
+        get_best_move_with_history(fen, history, 2).unwrap(),
+        "c1 b2"
+    );
+
+    let position = parse_fen(fen);
+    let evals = Searcher::new(vec![position_key(&position)]).evaluate_all_moves(&position, 2, 0);
+    let eval_of = |text: &str| {
+        evals
+            .iter()
+            .find(|(m, _)| notation::format_coordinate_move(m) == text)
+            .map(|&(_, eval)| eval.value())
+            .unwrap()
+    };
+    let lead = eval_of("c3 d5") - eval_of("c1 b2");
+    assert!(lead > 0 && lead < REPEATED_MINOR_MOVE_PENALTY, "{}", lead);
+}
+
+// The penalty for moving the knight again depends on knowing it has moved, so
+// the table keeps none of it for a later search that doesn't know.
+#[test]
+fn repeated_minor_penalty_stays_out_of_table() {
+    let fen = "rnbqkbnr/1ppp1ppp/8/p3p3/8/NP6/P1PPPPPP/R1BQKBNR w KQkq - 0 3";
+    let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+    let fresh = searcher.search_root(&position, 3, halfmove_clock).unwrap();
+
+    let (_, mut searcher, _) = prepare_search(fen).unwrap();
+    searcher.moves = vec![
+        ChessMove::new(Square::A7, Square::A5, None),
+        ChessMove::new(Square::B1, Square::A3, None),
+        ChessMove::new(Square::E7, Square::E5, None),
+    ];
+    searcher.search_root(&position, 3, halfmove_clock).unwrap();
+    searcher.moves.clear();
+    assert_eq!(
+        searcher.search_root(&position, 3, halfmove_clock).unwrap(),
+        fresh
+    );
+}
+
+// A search with a thinking time floor takes at least that long, and still
+// plays the move the fixed depth search would. A finished game doesn't wait.
+#[test]
+fn min_time_search_waits_for_floor() {
+    let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+    let start = Instant::now();
+    let best_move = get_best_move_min_time(fen, 1, 300).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert_eq!(best_move, "d1 d8");
+
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    let start = Instant::now();
+    assert_eq!(
+        get_best_move_min_time(checkmate, 1, 300).unwrap(),
+        GAME_OVER
+    );
+    assert!(start.elapsed() < Duration::from_millis(300));
+}
+
+// A batch gives one line per FEN, in order, each the same as searching that
+// FEN alone, with invalid FENs and finished games marked in their place.
+#[test]
+fn batch_lines_up_with_input() {
+    let fens = [
+        "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+        "not a fen",
+        "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+        "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1",
+        START_FEN,
+    ];
+    let results = get_best_moves_batch(&fens.join("\n"), 2);
+    let lines: Vec<&str> = results.lines().collect();
+    assert_eq!(lines.len(), fens.len());
+    assert_eq!(lines[0], "d1 d8");
+    assert_eq!(lines[1], "error: invalid FEN \"not a fen\": missing fields");
+    assert_eq!(lines[2], "h1 h8");
+    assert_eq!(lines[3], "none");
+    assert_eq!(
+        lines[4],
+        get_best_move_minimax_alpha_beta(START_FEN, 2).unwrap()
+    );
+}
+
+// Searching a position that follows on from the last one reuses the entries
+// the last search left behind, which a fresh search can't.
+#[test]
+fn persistent_table_is_reused() {
+    let play = |fen: &str, text: &str| {
+        let position = parse_fen(fen);
+        let m = notation::parse_coordinate_move(&position, text).unwrap();
+        notation::fen_after_move(fen, &position, m)
+    };
+
+    // Follow the line the engine expects, so the new position was searched.
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    reset_engine();
+    let after_move = play(fen, &get_best_move_persistent(fen, 3).unwrap());
+    let next_fen = play(
+        &after_move,
+        &get_best_move_minimax_alpha_beta(&after_move, 2).unwrap(),
+    );
+    let best_move = get_best_move_persistent(&next_fen, 3).unwrap();
+    let persistent_hits = PERSISTENT_TABLE.with(|persistent| persistent.borrow().1.hits);
+
+    let (position, mut searcher, halfmove_clock) = prepare_search(&next_fen).unwrap();
+    let (fresh_move, _) = searcher.search_root(&position, 3, halfmove_clock).unwrap();
+    assert!(persistent_hits > searcher.tt.hits);
+    assert_eq!(best_move, notation::format_coordinate_move(&fresh_move));
+
+    reset_engine();
+    assert_eq!(
+        PERSISTENT_TABLE.with(|persistent| persistent.borrow().1.hits),
+        0
+    );
+}
+
+// Draws are scored against the side searched for, so the table is started
+// afresh when the engine searches for the other side: the reply finds no more
+// than a fresh search would. A finished game has no move.
+#[test]
+fn persistent_table_is_kept_per_side() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    reset_engine();
+    let best_move = get_best_move_persistent(fen, 3).unwrap();
+    let position = parse_fen(fen);
+    let m = notation::parse_coordinate_move(&position, &best_move).unwrap();
+    let reply_fen = notation::fen_after_move(fen, &position, m);
+    get_best_move_persistent(&reply_fen, 3).unwrap();
+    let persistent_hits = PERSISTENT_TABLE.with(|persistent| persistent.borrow().1.hits);
+
+    let (position, mut searcher, halfmove_clock) = prepare_search(&reply_fen).unwrap();
+    searcher.search_root(&position, 3, halfmove_clock);
+    assert_eq!(persistent_hits, searcher.tt.hits);
+
+    let checkmate = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+    assert_eq!(get_best_move_persistent(checkmate, 3).unwrap(), GAME_OVER);
+    reset_engine();
+}
+
+// White should take the hanging queen, while Black, given the move, would have
+// grabbed the e4 pawn with check. A side in check can't pass the move, and a
+// side with no moves has nothing to threaten.
+#[test]
+fn threats_for_both_sides() {
+    let fen = "r1b1kbnr/pppp1ppp/2n5/4p3/2B1P2q/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let threats = threats_both_sides(fen, 2).unwrap();
+    let fields: Vec<&str> = threats.split(';').collect();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[0], "f3 h4");
+    assert_eq!(fields[2], "h4 e4");
+
+    let in_check = "rnbqkbnr/ppp2ppp/8/1B1pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 3";
+    assert_eq!(
+        threats_both_sides(in_check, 1).unwrap().split(';').count(),
+        2
+    );
+    let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+    assert_eq!(threats_both_sides(stalemate, 1).unwrap(), GAME_OVER);
+}
+
+// A smaller table gives the same answers, but remembers less of a deep search.
+#[test]
+fn hash_size_affects_hits() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let position = parse_fen(fen);
+    let mut results = Vec::new();
+    for mb in [0, 16] {
+        set_hash_size_mb(mb);
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        let best_move = searcher.search_root(&position, 3, 4).unwrap();
+        results.push((best_move, searcher.tt.hits));
+    }
+    set_hash_size_mb(DEFAULT_HASH_SIZE_MB);
+
+    assert_eq!(results[0].0, results[1].0);
+    assert!(results[1].1 > results[0].1);
+}
+
+// The score reported with the best move is the evaluation of the position the
+// move leads to, not of the position before it.
+#[test]
+fn score_is_of_position_after_best_move() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let position = parse_fen(fen);
+    let result = search_with_progress(fen, 2, |_| {}).unwrap();
+    let best_move = notation::parse_coordinate_move(&position, &result.best_move).unwrap();
+    let child_fen = notation::fen_after_move(fen, &position, best_move);
+    assert_eq!(result.score(), evaluate(&child_fen, result.depth).unwrap());
+
+    assert_eq!(
+        evaluate(fen, 0).unwrap(),
+        position_evaluation(&position, &EvalWeights::for_move_number(4))
+    );
+    assert_eq!(evaluate("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3).unwrap(), 0);
+}
+
+// Leaving the queen en prise instead of taking Black's is a blunder, while the
+// capture itself is the best move.
+#[test]
+fn blunder_check_classifies_moves() {
+    let fen = "r1b1kbnr/pppp1ppp/2n5/4p3/2B1P2q/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    let best = blunder_check(fen, "f3 h4", 2).unwrap();
+    let fields: Vec<&str> = best.split(';').collect();
+    assert_eq!(fields[0], fields[2]);
+    assert_eq!(fields[1..], ["f3 h4", fields[2], "0", "best"]);
+
+    let blunder = blunder_check(fen, "a2a3", 2).unwrap();
+    let fields: Vec<&str> = blunder.split(';').collect();
+    assert_eq!(fields[1], "f3 h4");
+    assert!(fields[3].parse::<i32>().unwrap() >= BLUNDER_LOSS);
+    assert_eq!(fields[4], "blunder");
+
+    assert_eq!(blunder_check(fen, "Nxh4", 2).unwrap(), best);
+    assert!(blunder_check(fen, "e1 g3", 2).is_err());
+}
+
+// A pawn is worth more once the pieces have come off than in the opening.
+#[test]
+fn pawn_value_grows_in_endgame() {
+    let opening = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let opening_down = parse_fen("rnbqkbnr/1ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let ending = parse_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1");
+    let ending_down = parse_fen("4k3/1ppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1");
+
+    let opening_pawn = piece_score(&opening_down) - piece_score(&opening);
+    let ending_pawn = piece_score(&ending_down) - piece_score(&ending);
+    assert_eq!(opening_pawn, PIECE_VALUES[Piece::Pawn.to_index()].0);
+    assert_eq!(ending_pawn, PIECE_VALUES[Piece::Pawn.to_index()].1);
+    assert!(ending_pawn > opening_pawn);
+}
+
+// Playing the mate in one gives the mated position, from which there is no
+// move left to play.
+#[test]
+fn play_best_move_until_game_over() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1";
+    let mated = play_best_move(fen, 2).unwrap();
+    assert_eq!(
+        mated,
+        "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 1"
+    );
+    assert_eq!(parse_fen(&mated).status(), BoardStatus::Checkmate);
+    assert_eq!(
+        play_best_move(&mated, 2).unwrap(),
+        format!("{};game-over", mated)
+    );
+
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let position = parse_fen(start);
+    let best_move = notation::parse_coordinate_move(
+        &position,
+        &get_best_move_minimax_alpha_beta(start, 2).unwrap(),
+    )
+    .unwrap();
+    let after = play_best_move(start, 2).unwrap();
+    assert_eq!(after, notation::fen_after_move(start, &position, best_move));
+    assert_eq!(parse_fen(&after).side_to_move(), Color::Black);
+}
+
+// The side to move's eval is the White-relative one with the sign flipped when
+// Black is to move, and an even position is even for either side.
+#[test]
+fn side_to_move_evaluation() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let start_black = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+    assert_eq!(evaluate_position_stm(start).unwrap(), 0);
+    assert_eq!(evaluate_position_stm(start_black).unwrap(), 0);
+
+    let white_to_move = "4k3/8/8/8/8/8/8/R3K3 w - - 0 30";
+    let black_to_move = "4k3/8/8/8/8/8/8/R3K3 b - - 0 30";
+    assert!(evaluate(white_to_move, 0).unwrap() > 0);
+    assert_eq!(
+        evaluate_position_stm(white_to_move).unwrap(),
+        evaluate(white_to_move, 0).unwrap()
+    );
+    assert_eq!(
+        evaluate_position_stm(black_to_move).unwrap(),
+        -evaluate(black_to_move, 0).unwrap()
+    );
+}
+
+// A forced mate plays out to the end well within the move limit, with an eval
+// for every move, while a long game is cut off at the limit.
+#[test]
+fn self_play_reports_each_move() {
+    let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+    let game = self_play_with_evals(fen, 3, 20).unwrap();
+    let lines: Vec<&str> = game.lines().collect();
+    assert!(lines.len() < 20);
+
+    let mut position = String::from(fen);
+    for line in &lines {
+        let fields: Vec<&str> = line.split(';').collect();
+        assert_eq!(fields.len(), 2);
+        fields[1].parse::<i32>().unwrap();
+        position = notation::apply_san(&position, fields[0]).unwrap();
+    }
+    assert_eq!(parse_fen(&position).status(), BoardStatus::Checkmate);
+    assert!(lines
+        .last()
+        .unwrap()
+        .ends_with(&format!("#;{}", MATE_SCORE - 1)));
+
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(
+        self_play_with_evals(start, 1, 4).unwrap().lines().count(),
+        4
+    );
+}
+
+// Evaluating a leaf from the move list the search already generated gives the
+// same scores as evaluating it from scratch, mates and stalemates included.
+#[test]
+fn leaf_evaluation_reuses_move_list() {
+    let weights = EvalWeights::default();
+    for fen in [
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "8/5k2/3b4/8/3P4/4K3/4B3/8 b - - 0 1",
+        "7k/7p/8/P7/8/8/8/7K w - - 0 1",
+    ] {
+        let position = parse_fen(fen);
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        let leaf = searcher.minimax_alpha_beta(position, 0, Score::MIN, Score::MAX, 0);
+        assert_eq!(leaf.value(), position_evaluation(&position, &weights));
+        assert_eq!(
+            ongoing_evaluation(&position, &weights, -MATE_SCORE, MATE_SCORE),
+            position_evaluation(&position, &weights)
+        );
+    }
+
+    let mated = parse_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 1");
+    let mut searcher = Searcher::new(vec![position_key(&mated)]);
+    let score = searcher.minimax_alpha_beta(mated, 0, Score::MIN, Score::MAX, 0);
+    assert_eq!(score, Score::mate(Color::White, 0));
+    assert_eq!(score.value(), position_evaluation(&mated, &weights));
+}
+
+// A double step next to a pinned pawn leaves an en passant square nobody can
+// use, so the position is the same as without it, and counts towards the same
+// repetition. A usable en passant square still tells positions apart.
+#[test]
+fn phantom_en_passant_square_is_ignored() {
+    let phantom = parse_fen("4r2k/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+    let plain = parse_fen("4r2k/8/8/3pP3/8/8/8/4K3 w - - 0 2");
+    assert_ne!(phantom.get_hash(), plain.get_hash());
+    assert_eq!(position_key(&phantom), position_key(&plain));
+
+    let searcher = Searcher::new(vec![
+        position_key(&phantom),
+        position_key(&plain),
+        position_key(&plain),
+    ]);
+    assert!(searcher.is_draw(0));
+
+    let capturable = parse_fen("7k/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+    let plain = parse_fen("7k/8/8/3pP3/8/8/8/4K3 w - - 0 2");
+    assert_ne!(position_key(&capturable), position_key(&plain));
+}
+
+// As the fifty move rule draws near an extra rook counts for less and less.
+#[test]
+fn material_fades_towards_fifty_moves() {
+    let evals: Vec<i32> = [0, 70, 85, 90, 95]
+        .iter()
+        .map(|halfmoves| {
+            evaluate(&format!("4k3/8/8/8/8/8/8/R3K3 w - - {} 60", halfmoves), 2).unwrap()
+        })
+        .collect();
+    assert_eq!(evals[0], evals[1]);
+    assert!(evals.windows(2).skip(1).all(|pair| pair[0] > pair[1]));
+    assert!(evals[4] > 0);
+}
+
+// Every counter moves during a real search, with the quiescence search's
+// nodes a share of the total, and the branching factor sits between a
+// perfectly ordered tree's and a full minimax tree's.
+#[test]
+fn search_stats_are_populated() {
+    let fen = "r4rk1/pp1b1ppp/4p3/2ppP3/3P4/2P5/PP1B1PPP/R4RK1 w - - 0 16";
+    let stats = search_stats(fen, 5).unwrap();
+    assert!(stats.nodes() > 0);
+    assert!(stats.qnodes() > 0 && stats.qnodes() < stats.nodes());
+    assert!(stats.tt_probes() > 0);
+    assert!(stats.tt_hits() > 0 && stats.tt_hits() <= stats.tt_probes());
+    assert!(stats.cutoffs() > 0);
+    assert!(stats.branching_factor() > 1.0);
+    assert!(stats.branching_factor() < 35.0);
+}
+
+// Far behind is a resignation, a locked pawn ending is a draw offer, and the
+// opening is still to be played.
+#[test]
+fn advice_follows_eval() {
+    assert_eq!(
+        game_advice("4k3/8/8/8/8/q7/1q6/4K3 w - - 0 40", 2).unwrap(),
+        "resign"
+    );
+    assert_eq!(
+        game_advice("4k3/4p3/4P3/8/8/8/8/4K3 w - - 0 40", 2).unwrap(),
+        "offer_draw"
+    );
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(game_advice(start, 2).unwrap(), "play");
+}
+
+// A knight fork checks the king, which has to move, and then takes the rook,
+// after which Black has nothing forcing left.
+#[test]
+fn forcing_line_wins_material() {
+    let fen = "r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1";
+    let line = forcing_line(fen, 10).unwrap();
+    let moves: Vec<&str> = line.lines().collect();
+    assert_eq!(moves.len(), 3);
+    assert_eq!(moves[0], "b5 c7");
+    assert_eq!(moves[2], "c7 a8");
+
+    let mut position = parse_fen(fen);
+    for text in &moves {
+        let m = notation::parse_coordinate_move(&position, text).unwrap();
+        position = position.make_move_new(m);
+    }
+    assert!(piece_score(&parse_fen(fen)) < 0);
+    assert!(piece_score(&position) > 0);
+}
+
+// Far outside the window the lazy eval gives a bound on the full eval on the
+// window's side, and the searches it speeds up find the same scores as a plain
+// minimax over full evals, with captures and promotions played out at the
+// leaves.
+#[test]
+fn lazy_evaluation_is_sound() {
+    let weights = EvalWeights::default();
+    let position = parse_fen("2r3k1/pp3ppp/2n5/3p4/3P4/2N2Q2/PP3PPP/R5K1 w - - 0 20");
+    let full = position_evaluation(&position, &weights);
+    let below = ongoing_evaluation(&position, &weights, full - 200, full - 190);
+    let above = ongoing_evaluation(&position, &weights, full + 190, full + 200);
+    assert!(below >= full - 190 && below <= full);
+    assert!(above <= full + 190 && above >= full);
+
+    // Alpha-beta still gives the exact score for a full window, and it only
+    // ever looks at full evals.
+    fn plain_quiescence(position: &Board, ply: u32, mut alpha: i32, mut beta: i32) -> i32 {
+        let eval = plain_eval(position, ply);
+        let in_check = position.checkers().popcnt() > 0;
+        let maximising = position.side_to_move() == Color::White;
+        let mut best = None;
+        if !in_check {
+            best = Some(eval);
+            if maximising {
+                alpha = cmp::max(alpha, eval);
+            } else {
+                beta = cmp::min(beta, eval);
+            }
+        }
+        let moves = MoveGen::new_legal(position).filter(|m| {
+            in_check || m.get_promotion().is_some() || position.piece_on(m.get_dest()).is_some()
+        });
+        for m in moves {
+            if beta <= alpha {
+                break;
+            }
+            let score = plain_quiescence(&position.make_move_new(m), ply + 1, alpha, beta);
+            if maximising {
+                best = Some(best.map_or(score, |b| cmp::max(b, score)));
+                alpha = cmp::max(alpha, score);
+            } else {
+                best = Some(best.map_or(score, |b| cmp::min(b, score)));
+                beta = cmp::min(beta, score);
+            }
+        }
+        best.unwrap_or(eval)
+    }
+
+    // Mates are counted from the root, as the searcher counts them.
+    fn plain_eval(position: &Board, ply: u32) -> i32 {
+        if position.status() == BoardStatus::Checkmate {
+            return Score::mate(!position.side_to_move(), ply).value();
+        }
+        position_evaluation(position, &EvalWeights::default())
+    }
+
+    fn plain_minimax(position: &Board, depth: u32, ply: u32) -> i32 {
+        let moves: Vec<ChessMove> = MoveGen::new_legal(position).collect();
+        if moves.is_empty() {
+            return plain_eval(position, ply);
+        }
+        if depth == 0 {
+            return plain_quiescence(position, ply, i32::MIN, i32::MAX);
+        }
+        let evals = moves
+            .into_iter()
+            .map(|m| plain_minimax(&position.make_move_new(m), depth - 1, ply + 1));
+        match position.side_to_move() {
+            Color::White => evals.max().unwrap(),
+            Color::Black => evals.min().unwrap(),
+        }
+    }
+
+    for fen in [
+        "r1bqkbnr/pppp1ppp/8/8/3nP3/8/PPP2PPP/RNBQKB1R w KQkq - 0 5",
+        "2r3k1/pp3ppp/2n5/3p4/3P4/2N2Q2/PP3PPP/R5K1 w - - 0 20",
+        "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R b KQkq - 1 5",
+        "4r1k1/5ppp/8/8/1q6/8/5PPP/3R2K1 b - - 0 30",
+    ] {
+        let position = parse_fen(fen);
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        let (_, eval) = searcher.search_root(&position, 1, 0).unwrap();
+        assert_eq!(eval.value(), plain_minimax(&position, 2, 0));
+    }
+}
+
+// Random positions always parse back into legal positions, and the same seed
+// always gives the same one.
+#[test]
+fn random_positions_are_legal() {
+    assert_eq!(random_position(7, 0), START_FEN);
+    for seed in 0..20 {
+        for plies in [1, 10, 40, 120] {
+            let fen = random_position(seed, plies);
+            let position = Board::from_str(&fen).unwrap();
+            assert!(position.is_sane());
+            assert_eq!(fen, random_position(seed, plies));
+        }
+    }
+    assert_ne!(random_position(1, 10), random_position(2, 10));
+}
+
+// A searcher reusing the move lists left over from earlier searches searches
+// exactly the same tree as a fresh one.
+#[test]
+fn reused_move_buffers_search_identically() {
+    let fens = [
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPpP/R3K2R w KQkq - 0 1",
+        START_FEN,
+    ];
+    let mut reused = Searcher::new(Vec::new());
+    for fen in fens {
+        let position = parse_fen(fen);
+        let mut fresh = Searcher::new(vec![position_key(&position)]);
+        let expected = fresh.search_root(&position, 3, 0);
+
+        reused.history = vec![position_key(&position)];
+        reused.nodes = 0;
+        reused.tt.clear();
+        assert_eq!(reused.search_root(&position, 3, 0), expected, "{}", fen);
+        assert_eq!(reused.nodes, fresh.nodes, "{}", fen);
+    }
+    assert!(reused
+        .move_buffers
+        .iter()
+        .any(|buffer| buffer.capacity() > 0));
+}
+
+// In a pawn ending, where the side to move may be in zugzwang, no null move
+// is tried and the only winning move is found. In a quiet middlegame null
+// moves still cut the tree down without changing the move.
+#[test]
+fn null_moves_skip_zugzwang() {
+    let search = |fen: &str, depth, pruning| {
+        let position = parse_fen(fen);
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        searcher.null_move_pruning = pruning;
+        let (best_move, eval) = searcher.search_root(&position, depth, 0).unwrap();
+        (
+            notation::format_coordinate_move(&best_move),
+            eval,
+            searcher.nodes,
+        )
+    };
+
+    let ending = "8/4k3/8/2K5/3P4/8/8/8 w - - 0 1";
+    let position = parse_fen(ending);
+    assert!(endgames::in_zugzwang_risk(&position, Color::White));
+    assert!(endgames::in_zugzwang_risk(&position, Color::Black));
+    let pruned = search(ending, 5, true);
+    assert_eq!(pruned, search(ending, 5, false));
+    assert_eq!(pruned.0, "c5 c6");
+
+    let middlegame = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    assert!(!endgames::in_zugzwang_risk(
+        &parse_fen(middlegame),
+        Color::White
+    ));
+    let (pruned_move, _, pruned_nodes) = search(middlegame, 3, true);
+    let (full_move, _, full_nodes) = search(middlegame, 3, false);
+    assert_eq!(pruned_move, full_move);
+    assert!(pruned_nodes < full_nodes);
+}
+
+// Promoting to a queen mates, and with promotions ordered by their gain it is
+// the first move tried and the tree is smaller than when only the captures
+// are ordered early. Either way the promotion is played.
+#[test]
+fn promotions_searched_early() {
+    let fen = "7k/1P4pp/8/4n3/8/2b5/5PPP/4R1K1 w - - 0 1";
+    let position = parse_fen(fen);
+    let first =
+        |ordering| notation::format_coordinate_move(&root_moves(&position, None, ordering)[0]);
+    assert_eq!(first(DEFAULT_PROMOTION_ORDERING), "b7 b8q");
+    assert_eq!(first(0), "e1 e5");
+
+    let search = |ordering| {
+        let mut searcher = Searcher::new(vec![position_key(&position)]);
+        searcher.promotion_ordering = ordering;
+        let (best_move, eval) = searcher.search_root(&position, 4, 0).unwrap();
+        (best_move, eval, searcher.nodes)
+    };
+    let (ordered_move, ordered_eval, ordered_nodes) = search(DEFAULT_PROMOTION_ORDERING);
+    let (unordered_move, unordered_eval, unordered_nodes) = search(0);
+    assert_eq!(
+        ordered_move,
+        ChessMove::new(Square::B7, Square::B8, Some(Piece::Queen))
+    );
+    assert_eq!(
+        (ordered_move, ordered_eval),
+        (unordered_move, unordered_eval)
+    );
+    assert!(ordered_eval.is_mate());
+    assert!(ordered_nodes < unordered_nodes);
+}
+
+// Late move pruning skips the quiet moves searched after the first few near
+// the leaves, saving effort in a busy middlegame.
+#[test]
+fn late_move_pruning_saves_nodes() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let search = |pruning| {
+        let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+        searcher.late_move_pruning = pruning;
+        let (best_move, eval) = searcher.search_root(&position, 3, halfmove_clock).unwrap();
+        (
+            notation::format_coordinate_move(&best_move),
+            eval,
+            searcher.nodes,
+        )
+    };
+    let (pruned_move, pruned_eval, pruned_nodes) = search(true);
+    let (full_move, full_eval, full_nodes) = search(false);
+    assert_eq!(pruned_move, full_move);
+    assert_eq!(pruned_eval, full_eval);
+    assert!(pruned_nodes < full_nodes);
+}
+
+// Captures, checks and promotions are never pruned, so tactics are found
+// just the same: a back rank mate, a mate starting with a knight check and
+// a queen check going on to win a pawn.
+#[test]
+fn late_move_pruning_keeps_tactics() {
+    let tactics = [
+        ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "d1 d8"),
+        (
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            "d5 f6",
+        ),
+        ("1k6/1pp5/p7/8/8/8/5PPP/3Q2K1 w - - 0 1", "d1 d8"),
+    ];
+    for (fen, expected) in tactics {
+        for pruning in [true, false] {
+            let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+            searcher.late_move_pruning = pruning;
+            let (best_move, _) = searcher.search_root(&position, 3, halfmove_clock).unwrap();
+            assert_eq!(
+                notation::format_coordinate_move(&best_move),
+                expected,
+                "{}",
+                fen
+            );
+        }
+    }
 }
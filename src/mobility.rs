@@ -0,0 +1,225 @@
+//! Evaluation of piece mobility. Scores are White-relative.
+
+use chess::{
+    get_file, get_pawn_attacks, get_pawn_quiets, get_rank, BitBoard, Board, Color, File, MoveGen,
+    Piece, ALL_PIECES,
+};
+
+use crate::color_sign;
+use crate::endgames::LIGHT_SQUARES;
+use crate::king_safety::piece_attacks;
+
+/// The pieces whose moves count towards mobility. Pawn moves are about pawn
+/// structure and king moves about king safety, so neither is counted.
+const MOBILE_PIECES: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// Bonus for each move a piece has, in tenths of a centipawn,
+/// indexed knight, bishop, rook, queen. The long range pieces get less for
+/// each move, as they have so many more of them, and the queen least of all so
+/// that it isn't drawn out early just to stretch its legs.
+const MOBILITY_WEIGHTS: [i32; 4] = [40, 40, 20, 5];
+
+/// Penalty for a knight on the a- or h-file, where it covers at most four
+/// squares: a knight on the rim is dim.
+const RIM_KNIGHT_PENALTY: i32 = 20;
+
+/// Extra penalty for a knight in a corner, where it covers just two squares.
+const CORNER_KNIGHT_PENALTY: i32 = 30;
+
+/// Penalty for each of a bishop's own pawns standing on the bishop's square
+/// color, where it blocks the bishop's diagonals.
+const BAD_BISHOP_PAWN_PENALTY: i32 = 3;
+
+/// Extra penalty for each such pawn blocked by a pawn in front of it, as it is
+/// fixed on the bishop's color.
+const BLOCKED_BAD_BISHOP_PAWN_PENALTY: i32 = 5;
+
+/// The weight of each move made by a piece of the given type, or zero if it
+/// isn't one of MOBILE_PIECES.
+fn move_weight(piece: Piece) -> i32 {
+    MOBILE_PIECES
+        .iter()
+        .position(|&mobile| mobile == piece)
+        .map_or(0, |index| MOBILITY_WEIGHTS[index])
+}
+
+/// The weighted count of the squares the given color's pieces attack that
+/// aren't occupied by its own pieces. This is every pseudo-legal move, read
+/// straight off the attack bitboards, so it is cheap but also counts moves of
+/// pinned pieces and moves that leave the king in check.
+pub fn pseudo_legal_mobility(position: &Board, color: Color) -> i32 {
+    let own = position.color_combined(color);
+    let mut mobility = 0;
+    for piece in MOBILE_PIECES {
+        for square in position.pieces(piece) & own {
+            let targets = piece_attacks(position, piece, color, square) & !own;
+            mobility += targets.popcnt() as i32 * move_weight(piece);
+        }
+    }
+    mobility
+}
+
+/// The weighted count of the given color's legal moves. Exact, but it needs the
+/// full legal move generation. The side not to move has its moves generated
+/// as if it were its turn, falling back to the pseudo-legal count when that
+/// isn't possible because the side to move is in check.
+pub fn legal_mobility(position: &Board, color: Color) -> i32 {
+    let board = if position.side_to_move() == color {
+        *position
+    } else {
+        match position.null_move() {
+            Some(board) => board,
+            None => return pseudo_legal_mobility(position, color),
+        }
+    };
+
+    MoveGen::new_legal(&board)
+        .filter_map(|m| board.piece_on(m.get_source()))
+        .map(move_weight)
+        .sum()
+}
+
+/// Count the given color's pseudo-legal moves: every move of every piece,
+/// pawns and king included, whether or not it leaves the king in check. A
+/// promotion counts four times, once for each piece, as in the legal moves.
+/// Castling and en passant aren't counted.
+pub fn pseudo_legal_move_count(position: &Board, color: Color) -> u32 {
+    let own = position.color_combined(color);
+    let last_rank = get_rank((!color).to_my_backrank());
+    let mut count = 0;
+    for piece in ALL_PIECES {
+        for square in position.pieces(piece) & own {
+            if piece == Piece::Pawn {
+                let targets = get_pawn_attacks(square, color, *position.color_combined(!color))
+                    | get_pawn_quiets(square, color, *position.combined());
+                count += targets.popcnt() + 3 * (targets & last_rank).popcnt();
+            } else {
+                count += (piece_attacks(position, piece, color, square) & !own).popcnt();
+            }
+        }
+    }
+    count
+}
+
+/// Generate a value for how freely each side's pieces can move, counted from
+/// the attack bitboards or, if `exact`, from the legal moves.
+pub fn mobility(position: &Board, exact: bool) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let mobility = if exact {
+            legal_mobility(position, color)
+        } else {
+            pseudo_legal_mobility(position, color)
+        };
+        score += color_sign(color) * mobility;
+    }
+    score / 10
+}
+
+/// Generate a value penalising knights on the edge files, and more so in the
+/// corners. Mobility already counts their lost moves, but this keeps the
+/// maxim as a term of its own that can be weighted separately.
+pub fn rim_knights(position: &Board) -> i32 {
+    let rim = get_file(File::A) | get_file(File::H);
+    let corners = BitBoard::new(0x8100_0000_0000_0081);
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let knights = position.pieces(Piece::Knight) & position.color_combined(color);
+        let penalty = (knights & rim).popcnt() as i32 * RIM_KNIGHT_PENALTY
+            + (knights & corners).popcnt() as i32 * CORNER_KNIGHT_PENALTY;
+        score -= color_sign(color) * penalty;
+    }
+    score
+}
+
+/// Generate a value penalising bad bishops: bishops hemmed in by their own
+/// pawns on squares of their color, more so by pawns that are blocked by
+/// another pawn and will stay there.
+pub fn bad_bishops(position: &Board) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let own = position.color_combined(color);
+        let pawns = position.pieces(Piece::Pawn) & own;
+        for bishop in position.pieces(Piece::Bishop) & own {
+            let bishop_color = if (BitBoard::from_square(bishop) & LIGHT_SQUARES).popcnt() > 0 {
+                LIGHT_SQUARES
+            } else {
+                !LIGHT_SQUARES
+            };
+            for pawn in pawns & bishop_color {
+                let blocked = pawn
+                    .forward(color)
+                    .is_some_and(|ahead| position.piece_on(ahead) == Some(Piece::Pawn));
+                let penalty =
+                    BAD_BISHOP_PAWN_PENALTY + blocked as i32 * BLOCKED_BAD_BISHOP_PAWN_PENALTY;
+                score -= color_sign(color) * penalty;
+            }
+        }
+    }
+    score
+}
+
+// With nothing pinned and neither king in check the two counts agree, but the
+// pseudo-legal count also includes the moves of a pinned piece.
+#[test]
+fn pseudo_legal_matches_legal_without_pins() {
+    use std::str::FromStr;
+
+    let quiet =
+        Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+            .unwrap();
+    for color in [Color::White, Color::Black] {
+        assert_eq!(
+            pseudo_legal_mobility(&quiet, color),
+            legal_mobility(&quiet, color)
+        );
+    }
+    assert_eq!(mobility(&quiet, false), mobility(&quiet, true));
+
+    let pinned = Board::from_str("4k3/8/8/b7/8/2N5/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(legal_mobility(&pinned, Color::White), 0);
+    assert_eq!(
+        pseudo_legal_mobility(&pinned, Color::White),
+        8 * MOBILITY_WEIGHTS[0]
+    );
+}
+
+// A knight on the rim scores lower than one in the centre, and lower still in
+// the corner.
+#[test]
+fn knight_on_the_rim() {
+    use std::str::FromStr;
+
+    let rim = Board::from_str("4k3/8/8/8/N7/8/8/4K3 w - - 0 1").unwrap();
+    let centre = Board::from_str("4k3/8/8/8/2N5/8/8/4K3 w - - 0 1").unwrap();
+    let corner = Board::from_str("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+    assert_eq!(rim_knights(&centre), 0);
+    assert!(rim_knights(&rim) < rim_knights(&centre));
+    assert!(rim_knights(&corner) < rim_knights(&rim));
+    assert!(
+        crate::position_evaluation(&rim, &Default::default())
+            < crate::position_evaluation(&centre, &Default::default())
+    );
+
+    let black_rim = Board::from_str("4k3/8/8/n7/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(rim_knights(&black_rim), RIM_KNIGHT_PENALTY);
+}
+
+// A bishop behind a blocked chain of pawns on its own color is worse than one
+// on the other color, which the pawns don't get in the way of.
+#[test]
+fn bad_bishop_behind_own_pawns() {
+    use std::str::FromStr;
+
+    let bad = Board::from_str("4k3/8/4p3/3pP3/2pP4/2P5/3B4/6K1 w - - 0 1").unwrap();
+    let free = Board::from_str("4k3/8/4p3/3pP3/2pP4/2P5/4B3/6K1 w - - 0 1").unwrap();
+    assert_eq!(
+        bad_bishops(&bad),
+        -3 * (BAD_BISHOP_PAWN_PENALTY + BLOCKED_BAD_BISHOP_PAWN_PENALTY)
+    );
+    assert_eq!(bad_bishops(&free), 0);
+    assert!(
+        crate::position_evaluation(&bad, &Default::default())
+            < crate::position_evaluation(&free, &Default::default())
+    );
+}
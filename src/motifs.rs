@@ -0,0 +1,174 @@
+//! Spotting the common tactical motifs a move creates, for a tactics trainer.
+//! Only the pattern is checked, not whether the tactic actually wins: a fork
+//! by a piece that is simply taken is still reported.
+
+use chess::{between, line, BitBoard, Board, ChessMove, Color, MoveGen, Piece, Square, EMPTY};
+use wasm_bindgen::prelude::*;
+
+use crate::exchange::attackers_to;
+use crate::input::{board_from_fen, InputError};
+use crate::king_safety::piece_attacks;
+use crate::notation::format_coordinate_move;
+use crate::piece_value;
+
+/// The sliding pieces, whose attacks can be uncovered or run through a piece.
+const SLIDERS: [Piece; 3] = [Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// Whether an attack by a piece worth `attacker_value` on `target` is a real
+/// threat: the target is the king, is worth more than the attacker, or has
+/// nothing defending it.
+fn is_threat(position: &Board, target: Square, attacker_value: i32) -> bool {
+    let (Some(piece), Some(color)) = (position.piece_on(target), position.color_on(target)) else {
+        return false;
+    };
+    let defended =
+        attackers_to(position, target, *position.combined()) & position.color_combined(color);
+    piece == Piece::King || piece_value(piece) > attacker_value || defended == EMPTY
+}
+
+/// The enemy pieces the piece on `square` (belonging to `color`) attacks in
+/// `position` that are real threats (see `is_threat`). Pawns aren't counted
+/// as targets.
+fn threatened(position: &Board, square: Square, color: Color) -> Vec<Square> {
+    let Some(piece) = position.piece_on(square) else {
+        return Vec::new();
+    };
+    let targets = piece_attacks(position, piece, color, square)
+        & position.color_combined(!color)
+        & !position.pieces(Piece::Pawn);
+    targets
+        .filter(|&target| is_threat(position, target, piece_value(piece)))
+        .collect()
+}
+
+/// Whether the moved piece, now on `dest`, threatens two or more pieces.
+fn is_fork(after: &Board, dest: Square, color: Color) -> bool {
+    threatened(after, dest, color).len() >= 2
+}
+
+/// Whether moving away from `source` lets another of the mover's sliders
+/// threaten a piece it couldn't attack before.
+fn is_discovered_attack(before: &Board, after: &Board, source: Square, dest: Square) -> bool {
+    let color = before.side_to_move();
+    SLIDERS.iter().any(|&slider| {
+        (after.pieces(slider) & after.color_combined(color))
+            .filter(|&square| square != dest && line(square, source) != EMPTY)
+            .any(|square| {
+                let old = piece_attacks(before, slider, color, square);
+                threatened(after, square, color)
+                    .into_iter()
+                    .any(|target| old & BitBoard::from_square(target) == EMPTY)
+            })
+    })
+}
+
+/// Whether the moved piece, now on `dest`, is a slider attacking a piece that
+/// has a less valuable piece behind it on the same line, which is left to be
+/// taken once the front piece steps aside.
+fn is_skewer(after: &Board, dest: Square, color: Color) -> bool {
+    let Some(piece) = after.piece_on(dest) else {
+        return false;
+    };
+    if !SLIDERS.contains(&piece) {
+        return false;
+    }
+
+    let enemy = after.color_combined(!color);
+    let attacks = piece_attacks(after, piece, color, dest);
+    (attacks & enemy).any(|front| {
+        let front_value = piece_value(after.piece_on(front).unwrap());
+        // The squares on the same line beyond the front piece, up to and
+        // including the first piece behind it.
+        let beyond = line(dest, front)
+            & !between(dest, front)
+            & !BitBoard::from_square(front)
+            & !BitBoard::from_square(dest);
+        let behind = (beyond & *after.combined())
+            .filter(|&square| between(front, square) & after.combined() == EMPTY)
+            .find(|&square| between(dest, square) & BitBoard::from_square(front) != EMPTY);
+        behind.is_some_and(|square| {
+            after.color_on(square) == Some(!color)
+                && piece_value(after.piece_on(square).unwrap()) < front_value
+                && after.piece_on(square) != Some(Piece::Pawn)
+        })
+    })
+}
+
+/// The motifs a legal move creates, by name.
+pub fn move_motifs(position: &Board, m: ChessMove) -> Vec<&'static str> {
+    let color = position.side_to_move();
+    let after = position.make_move_new(m);
+    let (source, dest) = (m.get_source(), m.get_dest());
+
+    let mut motifs = Vec::new();
+    if is_fork(&after, dest, color) {
+        motifs.push("fork");
+    }
+    if is_skewer(&after, dest, color) {
+        motifs.push("skewer");
+    }
+    if is_discovered_attack(position, &after, source, dest) {
+        motifs.push("discovered-attack");
+    }
+    motifs
+}
+
+/// Exposed to javascript to point out the tactics available to the side to
+/// move, for a tactics trainer. Returns one line for each legal move creating
+/// a fork (the moved piece threatening two pieces at once), a skewer (a
+/// slider attacking a piece with a lesser one behind it) or a discovered
+/// attack (moving a piece out of another's line), giving the move in
+/// coordinates and its motifs separated by commas (e.g. "e4 f6;fork"). A
+/// threat is an attack on the king, on a more valuable piece or on an
+/// undefended one. Moves are listed in coordinate order. Throws if the FEN is
+/// invalid.
+#[wasm_bindgen]
+pub fn tactical_motifs(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let mut lines: Vec<String> = MoveGen::new_legal(&position)
+        .filter_map(|m| {
+            let motifs = move_motifs(&position, m);
+            (!motifs.is_empty())
+                .then(|| format!("{};{}", format_coordinate_move(&m), motifs.join(",")))
+        })
+        .collect();
+    lines.sort();
+    Ok(lines.join("\n"))
+}
+
+// The knight jumping to f6 forks the king and queen.
+#[test]
+fn finds_knight_fork() {
+    let motifs = tactical_motifs("6k1/3q1p1p/8/8/4N3/8/5PPP/6K1 w - - 0 1").unwrap();
+    assert!(
+        motifs.lines().any(|line| line == "e4 f6;fork"),
+        "{}",
+        motifs
+    );
+}
+
+// The rook checking along the fourth rank skewers the king to the queen.
+#[test]
+fn finds_skewer() {
+    let motifs = tactical_motifs("8/8/8/8/3k3q/8/8/R5K1 w - - 0 1").unwrap();
+    assert!(
+        motifs
+            .lines()
+            .any(|line| line.starts_with("a1 a4;") && line.contains("skewer")),
+        "{}",
+        motifs
+    );
+}
+
+// Moving the knight off the e-file uncovers the rook's check on the king.
+#[test]
+fn finds_discovered_attack() {
+    let motifs = tactical_motifs("4k3/8/8/8/8/8/4N3/4R1K1 w - - 0 1").unwrap();
+    assert!(
+        motifs.lines().any(|line| line == "e2 c3;discovered-attack"),
+        "{}",
+        motifs
+    );
+    // Quiet piece moves elsewhere create nothing.
+    assert!(!motifs.contains("g1 "));
+}
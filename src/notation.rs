@@ -0,0 +1,534 @@
+//! Reading and writing moves in SAN and coordinate notation, and working out
+//! the move between two positions.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chess::{Board, BoardStatus, ChessMove, Color, File, MoveGen, Piece, Rank, Square};
+use wasm_bindgen::prelude::*;
+
+use crate::input::{board_from_fen, InputError};
+use crate::position_key;
+
+/// The reasons a SAN string can fail to resolve to a single legal move.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// The string isn't shaped like a SAN move at all.
+    Malformed,
+    /// The string is well formed but no legal move matches it.
+    Illegal,
+    /// More than one legal move matches, so the SAN needs disambiguating.
+    Ambiguous,
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Malformed => write!(f, "malformed SAN"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+/// Map a SAN piece letter onto the piece it names.
+fn piece_from_letter(letter: char) -> Option<Piece> {
+    match letter {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+/// Resolve a SAN move (e.g. "Nf3", "exd5", "O-O", "e8=Q+") against the legal
+/// moves available in the given position.
+pub fn parse_san(position: &Board, san: &str) -> Result<ChessMove, SanError> {
+    // Check, mate and annotation suffixes carry no information we need.
+    let san = san
+        .trim()
+        .trim_end_matches(" e.p.")
+        .trim_end_matches(['+', '#', '!', '?']);
+
+    // Castling is the only SAN form that doesn't name a destination square, so
+    // translate it into the king's two square move.
+    let castle_file = match san {
+        "O-O" | "0-0" => Some(File::G),
+        "O-O-O" | "0-0-0" => Some(File::C),
+        _ => None,
+    };
+    if let Some(dest_file) = castle_file {
+        let back_rank = position.side_to_move().to_my_backrank();
+        let castle = ChessMove::new(
+            Square::make_square(back_rank, File::E),
+            Square::make_square(back_rank, dest_file),
+            None,
+        );
+        if position.piece_on(castle.get_source()) == Some(Piece::King)
+            && MoveGen::new_legal(position).any(|m| m == castle)
+        {
+            return Ok(castle);
+        }
+        return Err(SanError::Illegal);
+    }
+
+    let mut chars: Vec<char> = san.chars().collect();
+
+    // The piece letter is always first, pawn moves simply omit it.
+    let piece = match chars.first().copied().and_then(piece_from_letter) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+
+    // Promotions are normally written "e8=Q", but "e8Q" is common enough to
+    // accept as well.
+    let mut promotion = None;
+    if let Some(promotion_piece) = chars.last().copied().and_then(piece_from_letter) {
+        if piece != Piece::Pawn || promotion_piece == Piece::King {
+            return Err(SanError::Malformed);
+        }
+        promotion = Some(promotion_piece);
+        chars.pop();
+        if chars.last() == Some(&'=') {
+            chars.pop();
+        }
+    }
+
+    // The destination square is always the last two characters left.
+    if chars.len() < 2 {
+        return Err(SanError::Malformed);
+    }
+    let dest_text: String = chars.split_off(chars.len() - 2).into_iter().collect();
+    let dest = Square::from_str(&dest_text).map_err(|_| SanError::Malformed)?;
+
+    // The capture marker is optional for our purposes, legality is decided by
+    // the move list rather than by the notation.
+    if chars.last() == Some(&'x') {
+        chars.pop();
+    }
+
+    // Anything left over is a (possibly partial) source square used to
+    // disambiguate between pieces of the same type.
+    let mut source_file = None;
+    let mut source_rank = None;
+    for c in chars {
+        match c {
+            'a'..='h' if source_file.is_none() => {
+                source_file = Some(File::from_index(c as usize - 'a' as usize))
+            }
+            '1'..='8' if source_rank.is_none() => {
+                source_rank = Some(Rank::from_index(c as usize - '1' as usize))
+            }
+            _ => return Err(SanError::Malformed),
+        }
+    }
+
+    let mut candidates = MoveGen::new_legal(position).filter(|m| {
+        m.get_dest() == dest
+            && m.get_promotion() == promotion
+            && position.piece_on(m.get_source()) == Some(piece)
+            && source_file.is_none_or(|file| m.get_source().get_file() == file)
+            && source_rank.is_none_or(|rank| m.get_source().get_rank() == rank)
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(m), None) => Ok(m),
+        (Some(_), Some(_)) => Err(SanError::Ambiguous),
+        (None, _) => Err(SanError::Illegal),
+    }
+}
+
+/// Read a coordinate move, as produced by `format_coordinate_move` (e.g.
+/// "e2 e4"), without checking it is legal anywhere. The space is optional and
+/// a promotion is given by appending the piece letter (e.g. "e7 e8q"). Returns
+/// None if the text is malformed.
+pub fn read_coordinate_move(text: &str) -> Option<ChessMove> {
+    let text: String = text.split_whitespace().collect();
+    let source = Square::from_str(text.get(0..2)?).ok()?;
+    let dest = Square::from_str(text.get(2..4)?).ok()?;
+    let promotion = match text.get(4..)? {
+        "" => None,
+        letter => Some(piece_from_letter(letter.to_uppercase().chars().next()?)?),
+    };
+    if text.len() > 5 {
+        return None;
+    }
+    Some(ChessMove::new(source, dest, promotion))
+}
+
+/// Resolve a coordinate move, as read by `read_coordinate_move`, against the
+/// legal moves in the given position. Returns None if the text is malformed or
+/// the move illegal.
+pub fn parse_coordinate_move(position: &Board, text: &str) -> Option<ChessMove> {
+    let m = read_coordinate_move(text)?;
+    MoveGen::new_legal(position).find(|&legal| legal == m)
+}
+
+/// Format a move in coordinates, its source and destination squares separated
+/// by a space (e.g. "e2 e4"), with the piece promoted to appended to
+/// promotions (e.g. "e7 d8q"). Every best move the engine reports is written
+/// this way, and it is the reverse of `parse_coordinate_move`.
+pub fn format_coordinate_move(m: &ChessMove) -> String {
+    let squares = format!("{} {}", m.get_source(), m.get_dest());
+    match m.get_promotion() {
+        Some(piece) => format!("{}{}", squares, piece.to_string(Color::Black)),
+        None => squares,
+    }
+}
+
+/// Format a legal move in SAN (e.g. "Nbd2", "exd5", "O-O", "e8=Q+"), the
+/// reverse of `parse_san`. A piece's source file or rank is only given when
+/// another piece of the same type could also reach the destination.
+pub fn format_san(position: &Board, m: ChessMove) -> String {
+    let (source, dest) = (m.get_source(), m.get_dest());
+    let piece = position.piece_on(source).unwrap();
+    let file_letter = |square: Square| (b'a' + square.get_file().to_index() as u8) as char;
+    let rank_digit = |square: Square| (b'1' + square.get_rank().to_index() as u8) as char;
+
+    let mut san = String::new();
+    let file_change = source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index());
+    if piece == Piece::King && file_change == 2 {
+        san.push_str(if dest.get_file() == File::G {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        // A pawn changing file is always capturing, en passant included.
+        let capture =
+            position.piece_on(dest).is_some() || (piece == Piece::Pawn && file_change > 0);
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(file_letter(source));
+            }
+        } else {
+            san.push_str(&piece.to_string(Color::White));
+            let rivals: Vec<Square> = MoveGen::new_legal(position)
+                .filter(|other| other.get_dest() == dest && other.get_source() != source)
+                .map(|other| other.get_source())
+                .filter(|&other| position.piece_on(other) == Some(piece))
+                .collect();
+            if !rivals.is_empty() {
+                if rivals.iter().all(|r| r.get_file() != source.get_file()) {
+                    san.push(file_letter(source));
+                } else if rivals.iter().all(|r| r.get_rank() != source.get_rank()) {
+                    san.push(rank_digit(source));
+                } else {
+                    san.push(file_letter(source));
+                    san.push(rank_digit(source));
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = m.get_promotion() {
+            san.push('=');
+            san.push_str(&promotion.to_string(Color::White));
+        }
+    }
+
+    let after = position.make_move_new(m);
+    if after.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if after.checkers().popcnt() > 0 {
+        san.push('+');
+    }
+    san
+}
+
+/// Format a line of moves played from `position` in SAN with move numbers
+/// (e.g. "1. e4 e5 2. Nf3"), counting from `fullmove_number`. A line starting
+/// with Black's move opens with an ellipsis (e.g. "1... e5 2. Nf3").
+pub fn format_san_line(position: &Board, fullmove_number: u32, moves: &[ChessMove]) -> String {
+    let mut board = *position;
+    let mut move_number = fullmove_number;
+    let mut parts = Vec::new();
+    for (i, &m) in moves.iter().enumerate() {
+        if board.side_to_move() == Color::White {
+            parts.push(format!("{}.", move_number));
+        } else if i == 0 {
+            parts.push(format!("{}...", move_number));
+        }
+        parts.push(format_san(&board, m));
+        if board.side_to_move() == Color::Black {
+            move_number += 1;
+        }
+        board = board.make_move_new(m);
+    }
+    parts.join(" ")
+}
+
+/// Read the halfmove clock and fullmove number from a FEN, falling back to the
+/// defaults when the (optional) counter fields are missing.
+pub fn fen_counters(fen: &str) -> (u32, u32) {
+    let mut fields = fen.split_whitespace().skip(4);
+    let halfmove_clock = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+    let fullmove_number = fields.next().and_then(|f| f.parse().ok()).unwrap_or(1);
+    (halfmove_clock, fullmove_number)
+}
+
+/// Produce the FEN of the position after `m`, carrying the move counters of
+/// `fen` forward (the `chess` crate always writes them as "0 1").
+pub fn fen_after_move(fen: &str, position: &Board, m: ChessMove) -> String {
+    let (halfmove_clock, fullmove_number) = fen_counters(fen);
+
+    let halfmove_clock = if crate::is_irreversible(position, m) {
+        0
+    } else {
+        halfmove_clock + 1
+    };
+    let fullmove_number = if position.side_to_move() == Color::Black {
+        fullmove_number + 1
+    } else {
+        fullmove_number
+    };
+
+    let new_position = position.make_move_new(m);
+    let placement = new_position.to_string();
+    let placement = placement.trim_end_matches(" 0 1");
+    format!("{} {} {}", placement, halfmove_clock, fullmove_number)
+}
+
+/// Exposed to javascript to apply a SAN move to a position. Returns the
+/// resulting FEN, or throws if the FEN is invalid or the SAN is malformed,
+/// illegal or ambiguous.
+#[wasm_bindgen]
+pub fn apply_san(fen: &str, san: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let m = parse_san(&position, san)
+        .map_err(|reason| InputError::InvalidMove(String::from(san), reason))?;
+    Ok(fen_after_move(fen, &position, m))
+}
+
+/// Exposed to javascript to step back a move. Since a FEN alone can't say what
+/// was captured or which castling rights were held before a move, the caller
+/// supplies the earlier FEN, which is returned once it has been checked that
+/// `move_played` (in coordinates, e.g. "e2 e4") really leads from it to
+/// `resulting_fen`. Only the positions are compared, not the move counters.
+/// Throws if the check fails.
+#[wasm_bindgen]
+pub fn undo_move(
+    prev_fen: &str,
+    move_played: &str,
+    resulting_fen: &str,
+) -> Result<String, InputError> {
+    let prev_position = board_from_fen(prev_fen)?;
+    let resulting_position = board_from_fen(resulting_fen)?;
+    let m = parse_coordinate_move(&prev_position, move_played)
+        .ok_or_else(|| InputError::InvalidMove(String::from(move_played), SanError::Illegal))?;
+
+    if prev_position.make_move_new(m) == resulting_position {
+        Ok(String::from(prev_fen))
+    } else {
+        Err(InputError::MoveMismatch(String::from(move_played)))
+    }
+}
+
+/// The legal move leading from one position to another, if there is one.
+/// Only the positions are compared, not the move counters.
+pub fn move_between(from_position: &Board, to_position: &Board) -> Option<ChessMove> {
+    let to_key = position_key(to_position);
+    MoveGen::new_legal(from_position)
+        .find(|&m| position_key(&from_position.make_move_new(m)) == to_key)
+}
+
+/// Exposed to javascript to work out the move between two position snapshots.
+/// Returns the legal move leading from `from_fen` to `to_fen` in coordinates,
+/// with the piece promoted to appended to promotions (e.g. "e7 d8q"), or an
+/// empty string if no single move connects them. Only the positions are
+/// compared, not the move counters. Throws if either FEN is invalid.
+#[wasm_bindgen]
+pub fn diff_move(from_fen: &str, to_fen: &str) -> Result<String, InputError> {
+    let from_position = board_from_fen(from_fen)?;
+    let to_position = board_from_fen(to_fen)?;
+    Ok(move_between(&from_position, &to_position)
+        .map(|m| format_coordinate_move(&m))
+        .unwrap_or_default())
+}
+
+// Plain pawn push from the starting position.
+#[test]
+fn san_pawn_move() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(
+        apply_san(start_fen, "e4").unwrap(),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+}
+
+// Both knights can reach d2, so the source file must be given.
+#[test]
+fn san_disambiguated_knight_move() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/5N2/PPP1PPPP/RNBQKB1R w KQkq - 2 3";
+    assert_eq!(
+        apply_san(fen, "Nbd2").unwrap(),
+        "rnbqkbnr/pppppppp/8/8/8/5N2/PPPNPPPP/R1BQKB1R b KQkq - 3 3"
+    );
+    assert_eq!(
+        apply_san(fen, "Nfd2").unwrap(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPNPPPP/RNBQKB1R b KQkq - 3 3"
+    );
+    assert_eq!(
+        apply_san(fen, "Nd2"),
+        Err(InputError::InvalidMove(
+            String::from("Nd2"),
+            SanError::Ambiguous
+        ))
+    );
+}
+
+// Pawn capture, which also resets the halfmove clock.
+#[test]
+fn san_capture() {
+    let fen = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+    assert_eq!(
+        apply_san(fen, "exd5").unwrap(),
+        "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2"
+    );
+    assert_eq!(
+        apply_san(fen, "exf5").unwrap_err().to_string(),
+        "illegal move \"exf5\""
+    );
+}
+
+// Kingside castling once the squares between king and rook are clear.
+#[test]
+fn san_castling() {
+    let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+    assert_eq!(
+        apply_san(fen, "O-O").unwrap(),
+        "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4"
+    );
+    assert!(apply_san(fen, "O-O-O").is_err());
+}
+
+// Promotion must name the piece being promoted to.
+#[test]
+fn san_promotion() {
+    let fen = "8/4P3/8/8/8/8/k7/4K3 w - - 0 60";
+    assert_eq!(
+        apply_san(fen, "e8=Q").unwrap(),
+        "4Q3/8/8/8/8/8/k7/4K3 b - - 0 60"
+    );
+    assert!(apply_san(fen, "e8").is_err());
+    assert_eq!(
+        apply_san(fen, "e8=K").unwrap_err().to_string(),
+        "malformed SAN \"e8=K\""
+    );
+}
+
+// Each White move is numbered, and a line starting with Black's move opens
+// with an ellipsis.
+#[test]
+fn san_line_numbering() {
+    let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    let position = Board::from_str(fen).unwrap();
+    let mut board = position;
+    let moves: Vec<ChessMove> = ["e7 e5", "g1 f3", "b8 c6"]
+        .iter()
+        .map(|text| {
+            let m = parse_coordinate_move(&board, text).unwrap();
+            board = board.make_move_new(m);
+            m
+        })
+        .collect();
+    assert_eq!(format_san_line(&position, 1, &moves), "1... e5 2. Nf3 Nc6");
+    assert_eq!(format_san_line(&position, 1, &[]), "");
+}
+
+// The FEN after a capture doesn't say what was captured, but the verified undo
+// hands back whichever earlier position the move really came from.
+#[test]
+fn undo_capture() {
+    use crate::input::FenError;
+
+    let took_pawn = "4k3/5p2/8/8/2B5/8/8/4K3 w - - 0 40";
+    let took_knight = "4k3/5n2/8/8/2B5/8/8/4K3 w - - 0 40";
+    let after = apply_san(took_pawn, "Bxf7+").unwrap();
+    assert_eq!(after, apply_san(took_knight, "Bxf7+").unwrap());
+
+    assert_eq!(undo_move(took_pawn, "c4 f7", &after).unwrap(), took_pawn);
+    assert_eq!(undo_move(took_knight, "c4f7", &after).unwrap(), took_knight);
+    assert_eq!(
+        undo_move(took_pawn, "c4 d5", &after),
+        Err(InputError::MoveMismatch(String::from("c4 d5")))
+    );
+    assert!(undo_move(took_pawn, "c4 c5", &after).is_err());
+    assert!(undo_move(took_pawn, "c4", &after).is_err());
+    assert_eq!(
+        undo_move("not a fen", "c4 f7", &after),
+        Err(InputError::InvalidFen(
+            String::from("not a fen"),
+            FenError::MissingFields
+        ))
+    );
+}
+
+// Formatting a move and parsing it back gives the same move, with the source
+// square only given where it is needed.
+#[test]
+fn san_formatting() {
+    let cases = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "e2e4",
+            "e4",
+        ),
+        (
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "f3e5",
+            "Nxe5",
+        ),
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+        ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "O-O-O"),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q", "a8=Q+"),
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+        ("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "b1d2", "Nbd2"),
+        ("4k3/8/8/8/8/R7/8/R3K3 w - - 0 1", "a3a2", "R3a2"),
+        (
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+            "h5f7",
+            "Qxf7#",
+        ),
+    ];
+    for (fen, coordinates, san) in cases {
+        let position = Board::from_str(fen).unwrap();
+        let m = parse_coordinate_move(&position, coordinates).unwrap();
+        assert_eq!(format_san(&position, m), san);
+        assert_eq!(parse_san(&position, san), Ok(m));
+    }
+}
+
+// Each kind of move is recovered from the positions either side of it, and
+// positions a move apart in neither direction give nothing.
+#[test]
+fn diff_move_between_snapshots() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+    assert_eq!(diff_move(start, after_e4).unwrap(), "e2 e4");
+    assert_eq!(diff_move(after_e4, start).unwrap(), "");
+
+    let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    let castled = "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1";
+    assert_eq!(diff_move(castling, castled).unwrap(), "e1 g1");
+
+    let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+    let captured = "4k3/8/3P4/8/8/8/8/4K3 b - - 0 2";
+    assert_eq!(diff_move(en_passant, captured).unwrap(), "e5 d6");
+
+    let promoting = "3rk3/2P5/8/8/8/8/8/4K3 w - - 0 1";
+    let promoted = "3Nk3/8/8/8/8/8/8/4K3 b - - 0 1";
+    assert_eq!(diff_move(promoting, promoted).unwrap(), "c7 d8n");
+
+    assert!(diff_move(start, "not a fen").is_err());
+}
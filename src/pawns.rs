@@ -0,0 +1,202 @@
+//! Pawn structure and passed pawn evaluation. Scores are White-relative.
+
+use chess::{
+    between, get_adjacent_files, get_file, get_pawn_attacks, BitBoard, Board, Color, File, Piece,
+    Square,
+};
+
+use crate::{color_sign, distance, game_phase, PHASE_MAX};
+
+/// Weight given to each square of difference between the two kings' distances
+/// from a passed pawn, at full endgame phase.
+const PASSER_KING_DISTANCE_WEIGHT: i32 = 20;
+
+/// Bonus for a rook standing behind a passed pawn, either supporting its own
+/// pawn's advance or chasing down the opponent's from behind.
+const ROOK_BEHIND_PASSER_BONUS: i32 = 30;
+
+/// Bitboard of every rank strictly in front of the square, from the point of
+/// view of the given color.
+pub fn ranks_ahead(square: Square, color: Color) -> BitBoard {
+    // Squares are indexed rank by rank from a1, so the ranks in front of a
+    // square are a contiguous run of bits.
+    let rank = square.get_rank().to_index() as u32;
+    let mask = match color {
+        Color::White => u64::MAX.checked_shl(8 * (rank + 1)).unwrap_or(0),
+        Color::Black => (1u64 << (8 * rank)) - 1,
+    };
+    BitBoard::new(mask)
+}
+
+/// Whether the pawn of the given color on `square` has no enemy pawns in front
+/// of it on its own or the adjacent files.
+pub fn is_passed_pawn(position: &Board, square: Square, color: Color) -> bool {
+    let file = square.get_file();
+    let front_span = (get_file(file) | get_adjacent_files(file)) & ranks_ahead(square, color);
+    let enemy_pawns = position.pieces(Piece::Pawn) & position.color_combined(!color);
+    (front_span & enemy_pawns).popcnt() == 0
+}
+
+/// Bitboard of the passed pawns belonging to the given color.
+pub fn passed_pawns(position: &Board, color: Color) -> BitBoard {
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    pawns
+        .filter(|&square| is_passed_pawn(position, square, color))
+        .fold(BitBoard::new(0), |mask, square| {
+            mask | BitBoard::from_square(square)
+        })
+}
+
+/// Count the given color's doubled pawns: every pawn after the first on each
+/// file.
+pub fn doubled_pawns(position: &Board, color: Color) -> u32 {
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    (0..8)
+        .map(|file| {
+            (get_file(File::from_index(file)) & pawns)
+                .popcnt()
+                .saturating_sub(1)
+        })
+        .sum()
+}
+
+/// Whether the pawn of the given color on `square` has no friendly pawns on
+/// the adjacent files to support it.
+pub fn is_isolated_pawn(position: &Board, square: Square, color: Color) -> bool {
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    (get_adjacent_files(square.get_file()) & pawns).popcnt() == 0
+}
+
+/// Whether the pawn of the given color on `square` is backward: it has
+/// friendly pawns on the adjacent files, but all of them are further advanced
+/// so none can ever defend it, and the square in front of it is attacked by an
+/// enemy pawn so it can't safely advance to join them either.
+pub fn is_backward_pawn(position: &Board, square: Square, color: Color) -> bool {
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    let enemy_pawns = position.pieces(Piece::Pawn) & position.color_combined(!color);
+    let neighbours = get_adjacent_files(square.get_file()) & pawns;
+    let supporters = neighbours & !ranks_ahead(square, color);
+    let stop_attacked = square
+        .forward(color)
+        .is_some_and(|stop| get_pawn_attacks(stop, color, enemy_pawns).popcnt() > 0);
+
+    neighbours.popcnt() > 0 && supporters.popcnt() == 0 && stop_attacked
+}
+
+/// Generate a value rewarding kings that escort their own passed pawns and
+/// blockade their opponent's. Only matters once the pieces come off, so it is
+/// scaled by how far into the endgame the position is.
+pub fn passed_pawn_king_support(position: &Board) -> i32 {
+    let mut score = 0;
+    for color in [Color::White, Color::Black] {
+        let sign = color_sign(color);
+        for pawn in passed_pawns(position, color) {
+            let own_distance = distance(position.king_square(color), pawn);
+            let enemy_distance = distance(position.king_square(!color), pawn);
+            score += sign * (enemy_distance - own_distance) * PASSER_KING_DISTANCE_WEIGHT;
+        }
+    }
+
+    score * (PHASE_MAX - game_phase(position)) / PHASE_MAX
+}
+
+/// Generate a value rewarding rooks that stand behind a passed pawn (their own
+/// or the opponent's) on the same file, with nothing in between.
+pub fn rooks_behind_passed_pawns(position: &Board) -> i32 {
+    let mut score = 0;
+    for pawn_color in [Color::White, Color::Black] {
+        for pawn in passed_pawns(position, pawn_color) {
+            // Behind is the direction the pawn is moving away from.
+            let behind = get_file(pawn.get_file()) & !ranks_ahead(pawn, pawn_color);
+            let rooks = position.pieces(Piece::Rook) & behind;
+            for rook in rooks {
+                if rook == pawn || (between(rook, pawn) & position.combined()).popcnt() > 0 {
+                    continue;
+                }
+                if let Some(rook_color) = position.color_on(rook) {
+                    score += color_sign(rook_color) * ROOK_BEHIND_PASSER_BONUS;
+                }
+            }
+        }
+    }
+
+    score
+}
+
+// Only pawns with a clear run past enemy pawns count as passed.
+#[test]
+fn passed_pawn_detection() {
+    use std::str::FromStr;
+
+    let position = Board::from_str("4k3/8/1p6/8/P2P3P/8/6p1/4K3 w - - 0 1").unwrap();
+    let white_passers = passed_pawns(&position, Color::White);
+    assert_eq!(white_passers.popcnt(), 2);
+    assert!(white_passers & BitBoard::from_square(Square::D4) != BitBoard::new(0));
+    assert!(white_passers & BitBoard::from_square(Square::H4) != BitBoard::new(0));
+    assert_eq!(
+        passed_pawns(&position, Color::Black),
+        BitBoard::from_square(Square::G2)
+    );
+}
+
+// Two pawns on one file make one doubled pawn, three make two.
+#[test]
+fn doubled_pawn_count() {
+    use std::str::FromStr;
+
+    let position = Board::from_str("4k3/8/2p5/2p5/2p5/P7/P5P1/4K3 w - - 0 1").unwrap();
+    assert_eq!(doubled_pawns(&position, Color::White), 1);
+    assert_eq!(doubled_pawns(&position, Color::Black), 2);
+}
+
+// A pawn with no neighbours is isolated, one whose neighbours have left it
+// behind and can't catch up is backward.
+#[test]
+fn isolated_and_backward_pawns() {
+    use std::str::FromStr;
+
+    let position = Board::from_str("4k3/8/8/4p3/2P5/3P3P/8/4K3 w - - 0 1").unwrap();
+    assert!(is_isolated_pawn(&position, Square::H3, Color::White));
+    assert!(!is_isolated_pawn(&position, Square::D3, Color::White));
+    assert!(is_backward_pawn(&position, Square::D3, Color::White));
+    assert!(!is_backward_pawn(&position, Square::C4, Color::White));
+
+    // The same pawn isn't backward when its advance is safe.
+    let safe = Board::from_str("4k3/8/8/8/2P5/3P3P/8/4K3 w - - 0 1").unwrap();
+    assert!(!is_backward_pawn(&safe, Square::D3, Color::White));
+}
+
+// With nothing else to do, the king should walk towards its passed pawn.
+#[test]
+fn king_escorts_passed_pawn() {
+    use std::str::FromStr;
+
+    let fen = "7k/8/8/8/P7/8/8/4K3 w - - 0 1";
+    let best_move = crate::get_best_move_minimax_alpha_beta(fen, 1).unwrap();
+    assert!(best_move.starts_with("e1"));
+
+    let position = Board::from_str(fen).unwrap();
+    let dest = Square::from_str(&best_move[3..]).unwrap();
+    assert!(distance(dest, Square::A4) < distance(position.king_square(Color::White), Square::A4));
+}
+
+// A rook supporting its passed pawn from behind beats one standing in front of
+// it, and the same goes for a rook chasing the opponent's passer.
+#[test]
+fn rook_behind_passed_pawn() {
+    use std::str::FromStr;
+
+    let behind = Board::from_str("8/4k3/8/P7/8/8/8/R3K3 w - - 0 1").unwrap();
+    let in_front = Board::from_str("R7/4k3/8/P7/8/8/8/4K3 w - - 0 1").unwrap();
+    assert!(rooks_behind_passed_pawns(&behind) > rooks_behind_passed_pawns(&in_front));
+    assert!(
+        crate::position_evaluation(&behind, &Default::default())
+            > crate::position_evaluation(&in_front, &Default::default())
+    );
+
+    let chasing = Board::from_str("R7/p3k3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(
+        rooks_behind_passed_pawns(&chasing),
+        ROOK_BEHIND_PASSER_BONUS
+    );
+}
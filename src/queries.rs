@@ -0,0 +1,962 @@
+//! Questions about a position exposed to javascript, which answer from the
+//! board alone without searching: checks, pins, captures, castling and the
+//! like.
+
+use std::str::FromStr;
+
+use chess::{
+    between, get_bishop_rays, get_rook_rays, BitBoard, Board, BoardBuilder, BoardStatus, ChessMove,
+    Color, File, MoveGen, Piece, Square, ALL_SQUARES,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::exchange::static_exchange;
+use crate::input::{board_from_fen, check_fen, color_from_name, InputError};
+use crate::mobility::pseudo_legal_move_count;
+use crate::notation::{
+    fen_counters, format_coordinate_move, parse_coordinate_move, read_coordinate_move, SanError,
+};
+use crate::pawns::{doubled_pawns, is_backward_pawn, is_isolated_pawn};
+use crate::{game_phase, position_key, FIFTY_MOVE_HALFMOVES};
+
+/// Exposed to javascript to tell whether the side to move is in check. Returns
+/// false for an invalid FEN.
+#[wasm_bindgen]
+pub fn is_in_check(fen: &str) -> bool {
+    board_from_fen(fen).is_ok_and(|position| position.checkers().popcnt() > 0)
+}
+
+/// Exposed to javascript to find the given color's ("white" or "black") king,
+/// for highlighting it. Returns its square (e.g. "e1"), or an empty string for
+/// an invalid FEN or color.
+#[wasm_bindgen]
+pub fn king_square(fen: &str, color: &str) -> String {
+    match (board_from_fen(fen), color_from_name(color)) {
+        (Ok(position), Ok(color)) => position.king_square(color).to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Exposed to javascript to list where the pieces stand, for UIs and tests
+/// that would rather not read the FEN's placement field. Returns one entry
+/// per occupied square from a1 to h8, separated by commas, each the square,
+/// a colon, "W" or "B" for the color and the piece's letter (e.g.
+/// "e1:WK,e8:BK"). Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn piece_placement(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    Ok(ALL_SQUARES
+        .iter()
+        .filter_map(|&square| {
+            let (piece, color) = (position.piece_on(square)?, position.color_on(square)?);
+            let side = if color == Color::White { 'W' } else { 'B' };
+            Some(format!(
+                "{}:{}{}",
+                square,
+                side,
+                piece.to_string(Color::White)
+            ))
+        })
+        .collect::<Vec<String>>()
+        .join(","))
+}
+
+/// Exposed to javascript to describe the material on the board, for routing
+/// endgames to the knowledge that covers them. Returns White's pieces, a "v",
+/// then Black's, each side listed king first and then from queens down to
+/// pawns, one letter per piece (e.g. "KRPvKR"). White always comes first,
+/// whichever side is stronger. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn material_signature(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let side = |color: Color| {
+        [
+            Piece::King,
+            Piece::Queen,
+            Piece::Rook,
+            Piece::Bishop,
+            Piece::Knight,
+            Piece::Pawn,
+        ]
+        .iter()
+        .map(|&piece| {
+            let count = (position.pieces(piece) & position.color_combined(color)).popcnt();
+            piece.to_string(Color::White).repeat(count as usize)
+        })
+        .collect::<String>()
+    };
+    Ok(format!("{}v{}", side(Color::White), side(Color::Black)))
+}
+
+/// Exposed to javascript to explain why a FEN is rejected, for showing to
+/// whoever typed it in. Returns a short diagnostic such as "missing black
+/// king", "too many white pieces" or "side not to move is in check", or an
+/// empty string if the FEN is valid.
+#[wasm_bindgen]
+pub fn fen_error(fen: &str) -> String {
+    match check_fen(fen) {
+        Ok(_) => String::new(),
+        Err(reason) => reason.to_string(),
+    }
+}
+
+/// Exposed to javascript to report how far the game has progressed, from 256
+/// with every piece on the board down to 0 with only kings and pawns left.
+/// Positions above 192 (queens and most of the pieces still on) are best
+/// labelled as the opening or early middlegame and those below 64 (a rook and
+/// a minor piece or less each) as the endgame, with the middlegame in between.
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn position_phase(fen: &str) -> Result<u32, InputError> {
+    let position = board_from_fen(fen)?;
+    Ok(game_phase(&position) as u32)
+}
+
+/// The halfmove clock at which the game is drawn by the seventy-five move rule,
+/// without either side having to claim it.
+const SEVENTY_FIVE_MOVE_HALFMOVES: u32 = 150;
+
+/// How many times a position has to occur for the game to be drawn without
+/// either side having to claim it.
+const FIVEFOLD_REPETITIONS: usize = 5;
+
+/// Exposed to javascript to tell whether the game is over, so a game between
+/// two programs ends even if neither claims a draw. The history is a newline
+/// separated list of the FENs of earlier positions in the game, as for
+/// `get_best_move_with_history`. Returns "checkmate" or "stalemate", the
+/// automatic draws "seventy-five-move" and "fivefold-repetition", the draws
+/// either side may claim "fifty-move" and "threefold-repetition", or
+/// "ongoing". A checkmate on the move that reaches the seventy-five move limit
+/// still stands. Throws if any of the FENs are invalid.
+#[wasm_bindgen]
+pub fn game_status(fen: &str, history: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let (halfmove_clock, _) = fen_counters(fen);
+
+    let key = position_key(&position);
+    let mut repetitions = 1;
+    for earlier in history.lines().filter(|fen| !fen.trim().is_empty()) {
+        if position_key(&board_from_fen(earlier)?) == key {
+            repetitions += 1;
+        }
+    }
+
+    let status = match position.status() {
+        BoardStatus::Checkmate => "checkmate",
+        BoardStatus::Stalemate => "stalemate",
+        BoardStatus::Ongoing if halfmove_clock >= SEVENTY_FIVE_MOVE_HALFMOVES => {
+            "seventy-five-move"
+        }
+        BoardStatus::Ongoing if repetitions >= FIVEFOLD_REPETITIONS => "fivefold-repetition",
+        BoardStatus::Ongoing if halfmove_clock >= FIFTY_MOVE_HALFMOVES => "fifty-move",
+        BoardStatus::Ongoing if repetitions >= 3 => "threefold-repetition",
+        BoardStatus::Ongoing => "ongoing",
+    };
+    Ok(String::from(status))
+}
+
+/// The state a FEN records besides the piece placement, exposed to javascript
+/// so it needn't pick the FEN apart itself.
+#[wasm_bindgen]
+pub struct PositionInfo {
+    side_to_move: Color,
+    castling: [bool; 4],
+    en_passant: Option<String>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+#[wasm_bindgen]
+impl PositionInfo {
+    /// "white" or "black".
+    #[wasm_bindgen(getter)]
+    pub fn side_to_move(&self) -> String {
+        String::from(match self.side_to_move {
+            Color::White => "white",
+            Color::Black => "black",
+        })
+    }
+
+    /// Whether White may still castle kingside.
+    #[wasm_bindgen(getter)]
+    pub fn white_kingside(&self) -> bool {
+        self.castling[0]
+    }
+
+    /// Whether White may still castle queenside.
+    #[wasm_bindgen(getter)]
+    pub fn white_queenside(&self) -> bool {
+        self.castling[1]
+    }
+
+    /// Whether Black may still castle kingside.
+    #[wasm_bindgen(getter)]
+    pub fn black_kingside(&self) -> bool {
+        self.castling[2]
+    }
+
+    /// Whether Black may still castle queenside.
+    #[wasm_bindgen(getter)]
+    pub fn black_queenside(&self) -> bool {
+        self.castling[3]
+    }
+
+    /// The square a pawn can capture en passant onto (e.g. "d6"), if any. The
+    /// FEN's square is dropped when no pawn stands ready to make the capture.
+    #[wasm_bindgen(getter)]
+    pub fn en_passant(&self) -> Option<String> {
+        self.en_passant.clone()
+    }
+
+    /// Halfmoves since the last pawn move or capture.
+    #[wasm_bindgen(getter)]
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// The number of the current move, starting at 1 and counting up after
+    /// each of Black's moves.
+    #[wasm_bindgen(getter)]
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+}
+
+/// Exposed to javascript to read the side to move, castling rights, en passant
+/// square and move counters out of a FEN. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn parse_position(fen: &str) -> Result<PositionInfo, InputError> {
+    let position = board_from_fen(fen)?;
+    let (halfmove_clock, fullmove_number) = fen_counters(fen);
+    let white = position.castle_rights(Color::White);
+    let black = position.castle_rights(Color::Black);
+
+    Ok(PositionInfo {
+        side_to_move: position.side_to_move(),
+        castling: [
+            white.has_kingside(),
+            white.has_queenside(),
+            black.has_kingside(),
+            black.has_queenside(),
+        ],
+        // The board records the pawn that can be taken, not the square the
+        // capturing pawn lands on behind it.
+        en_passant: position
+            .en_passant()
+            .and_then(|pawn| pawn.forward(position.side_to_move()))
+            .map(|square| square.to_string()),
+        halfmove_clock,
+        fullmove_number,
+    })
+}
+
+/// Whether the given color could castle kingside and queenside right now if it
+/// were its move: it still has the right, nothing stands between king and
+/// rook, and the king isn't in check and doesn't pass through or land on an
+/// attacked square.
+fn legal_castling(position: &Board, color: Color) -> (bool, bool) {
+    let board = if position.side_to_move() == color {
+        *position
+    } else {
+        match position.null_move() {
+            Some(board) => board,
+            // The side to move is in check, so passing the move to `color`
+            // can't happen and neither can its castling.
+            None => return (false, false),
+        }
+    };
+
+    // A king with castling rights is still on its starting square, so any
+    // king move two files over is a castling move.
+    let rights = board.castle_rights(color);
+    let king = board.king_square(color);
+    let back_rank = color.to_my_backrank();
+    let can_move_to = |file: File| {
+        let castle = ChessMove::new(king, Square::make_square(back_rank, file), None);
+        MoveGen::new_legal(&board).any(|m| m == castle)
+    };
+    (
+        rights.has_kingside() && can_move_to(File::G),
+        rights.has_queenside() && can_move_to(File::C),
+    )
+}
+
+/// Exposed to javascript to tell which castling moves are legal right now, as
+/// opposed to the rights a FEN records, which allow castling again once the
+/// squares between king and rook are clear and safe. Returns whether White can
+/// castle kingside and queenside and whether Black can, separated by
+/// semicolons (e.g. "true;false;false;false"). The side not to move is judged
+/// as if it were its move. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn castling_options(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let (white_kingside, white_queenside) = legal_castling(&position, Color::White);
+    let (black_kingside, black_queenside) = legal_castling(&position, Color::Black);
+    Ok(format!(
+        "{};{};{};{}",
+        white_kingside, white_queenside, black_kingside, black_queenside
+    ))
+}
+
+/// Exposed to javascript to drop the castling rights a FEN can't have: a right
+/// only stands while the king is on its starting square and the rook it
+/// castles with is in its corner. Returns the FEN with the rights that remain
+/// in the usual "KQkq" order, or "-" if none do, and its fields separated by
+/// single spaces. A FEN whose placement or fields can't be read is returned
+/// unchanged.
+#[wasm_bindgen]
+pub fn canonicalize_castling(fen: &str) -> String {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    let builder = match BoardBuilder::from_str(fen) {
+        Ok(builder) if fields.len() >= 4 => builder,
+        _ => return String::from(fen),
+    };
+
+    let rights = [
+        ('K', Color::White, File::H),
+        ('Q', Color::White, File::A),
+        ('k', Color::Black, File::H),
+        ('q', Color::Black, File::A),
+    ];
+    let castling: String = rights
+        .iter()
+        .filter(|&&(right, color, file)| {
+            let back_rank = color.to_my_backrank();
+            fields[2].contains(right)
+                && builder[Square::make_square(back_rank, File::E)] == Some((Piece::King, color))
+                && builder[Square::make_square(back_rank, file)] == Some((Piece::Rook, color))
+        })
+        .map(|&(right, _, _)| right)
+        .collect();
+    fields[2] = if castling.is_empty() { "-" } else { &castling };
+    fields.join(" ")
+}
+
+/// The pieces of the given color pinned to their king: each is the only piece
+/// between the king and an enemy slider that could otherwise attack it. Unlike
+/// `Board::pinned`, this works for either side, not just the side to move.
+pub fn pinned(position: &Board, color: Color) -> BitBoard {
+    let king = position.king_square(color);
+    let enemy = position.color_combined(!color);
+    let queens = position.pieces(Piece::Queen);
+    let pinners = (get_bishop_rays(king) & (position.pieces(Piece::Bishop) | queens) & enemy)
+        | (get_rook_rays(king) & (position.pieces(Piece::Rook) | queens) & enemy);
+
+    let mut pinned = BitBoard::new(0);
+    for pinner in pinners {
+        let blockers = between(king, pinner) & position.combined();
+        if blockers.popcnt() == 1 && (blockers & position.color_combined(color)).popcnt() == 1 {
+            pinned |= blockers;
+        }
+    }
+    pinned
+}
+
+/// Exposed to javascript to list the squares of the given color's pinned
+/// pieces, one per line. `color` is "white" or "black". Throws if the FEN or
+/// color is invalid.
+#[wasm_bindgen]
+pub fn pinned_pieces(fen: &str, color: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let color = color_from_name(color)?;
+
+    Ok(pinned(&position, color)
+        .map(|square| square.to_string())
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Exposed to javascript to count the weaknesses in one side's pawn structure.
+/// `color` is "white" or "black". Returns the number of doubled, isolated and
+/// backward pawns separated by semicolons (e.g. "1;2;0"), and throws if the
+/// FEN or color is invalid. See `is_backward_pawn` for exactly what makes a
+/// pawn backward.
+#[wasm_bindgen]
+pub fn pawn_weaknesses(fen: &str, color: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let color = color_from_name(color)?;
+
+    let pawns = position.pieces(Piece::Pawn) & position.color_combined(color);
+    let isolated = pawns
+        .filter(|&square| is_isolated_pawn(&position, square, color))
+        .count();
+    let backward = pawns
+        .filter(|&square| is_backward_pawn(&position, square, color))
+        .count();
+    Ok(format!(
+        "{};{};{}",
+        doubled_pawns(&position, color),
+        isolated,
+        backward
+    ))
+}
+
+/// Every legal capture in the position, en passant included.
+pub fn captures(position: &Board) -> Vec<ChessMove> {
+    let mut targets = *position.color_combined(!position.side_to_move());
+
+    // An en passant capture lands on an empty square, so add it to the mask
+    // and make sure only pawns are allowed to move there below.
+    let en_passant_dest = position
+        .en_passant()
+        .and_then(|square| square.forward(position.side_to_move()));
+    if let Some(dest) = en_passant_dest {
+        targets |= BitBoard::from_square(dest);
+    }
+
+    let mut moves = MoveGen::new_legal(position);
+    moves.set_iterator_mask(targets);
+    moves
+        .filter(|m| {
+            Some(m.get_dest()) != en_passant_dest
+                || position.piece_on(m.get_source()) == Some(Piece::Pawn)
+        })
+        .collect()
+}
+
+/// Every legal move in the position that gives check.
+pub fn checks(position: &Board) -> Vec<ChessMove> {
+    MoveGen::new_legal(position)
+        .filter(|&m| position.make_move_new(m).checkers().popcnt() > 0)
+        .collect()
+}
+
+/// Count the leaf nodes of the legal move tree `depth` plies deep, for
+/// checking move generation against known counts.
+pub fn perft(position: &Board, depth: u32) -> u64 {
+    let moves = MoveGen::new_legal(position);
+    match depth {
+        0 => 1,
+        // The moves at the last ply needn't be played to be counted.
+        1 => moves.len() as u64,
+        _ => moves
+            .map(|m| perft(&position.make_move_new(m), depth - 1))
+            .sum(),
+    }
+}
+
+/// Exposed to javascript to break a perft count down by root move, for
+/// tracking down move generation bugs against another engine's counts.
+/// Returns one line per legal move in the usual format of the move in UCI
+/// notation and its count (e.g. "e2e4: 600"), sorted by move, followed by the
+/// total (e.g. "total: 8902"). Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn perft_divide(fen: &str, depth: u32) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    if depth == 0 {
+        return Ok(String::from("total: 1"));
+    }
+
+    let mut counts: Vec<(String, u64)> = MoveGen::new_legal(&position)
+        .map(|m| (m.to_string(), perft(&position.make_move_new(m), depth - 1)))
+        .collect();
+    counts.sort_unstable();
+    let total: u64 = counts.iter().map(|(_, count)| count).sum();
+
+    let mut lines: Vec<String> = counts
+        .iter()
+        .map(|(m, count)| format!("{}: {}", m, count))
+        .collect();
+    lines.push(format!("total: {}", total));
+    Ok(lines.join("\n"))
+}
+
+/// Format a list of moves one per line, as by `format_coordinate_move` (e.g.
+/// "e7 d8q").
+fn format_move_list(moves: &[ChessMove]) -> String {
+    moves
+        .iter()
+        .map(format_coordinate_move)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Exposed to javascript to list the legal captures in a position, one per
+/// line. Promotions have the piece promoted to appended (e.g. "e7 d8q").
+/// Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn legal_captures(fen: &str) -> Result<String, InputError> {
+    Ok(format_move_list(&captures(&board_from_fen(fen)?)))
+}
+
+/// Exposed to javascript to tell what a capture wins or loses, for warning
+/// about captures that give away material. Runs a static exchange evaluation
+/// of the capture given as a coordinate move (e.g. "e4 d5" or "e4d5"),
+/// returning the material the side to move comes out with once the
+/// recaptures on the square are done, in centipawns: positive wins
+/// material, negative loses it. Throws if the FEN is invalid or the move is
+/// malformed, illegal or not a capture.
+#[wasm_bindgen]
+pub fn capture_value(fen: &str, capture: &str) -> Result<i32, InputError> {
+    let position = board_from_fen(fen)?;
+    let m = read_coordinate_move(capture)
+        .ok_or_else(|| InputError::MalformedMove(String::from(capture)))?;
+    if !MoveGen::new_legal(&position).any(|legal| legal == m) {
+        return Err(InputError::InvalidMove(
+            String::from(capture),
+            SanError::Illegal,
+        ));
+    }
+    if !captures(&position).contains(&m) {
+        return Err(InputError::NotACapture(String::from(capture)));
+    }
+    Ok(static_exchange(&position, m))
+}
+
+/// Exposed to javascript to show which captures are safe, for an overlay on
+/// the board. Lists every legal capture, en passant and capturing promotions
+/// included, one per line as the move and its static exchange value (as
+/// `capture_value` gives) separated by a semicolon (e.g. "e4 d5;300"),
+/// the best first. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn rank_captures(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let mut ranked: Vec<(String, i32)> = captures(&position)
+        .into_iter()
+        .map(|m| (format_coordinate_move(&m), static_exchange(&position, m)))
+        .collect();
+    ranked.sort_by(|(a, a_value), (b, b_value)| b_value.cmp(a_value).then_with(|| a.cmp(b)));
+    Ok(ranked
+        .iter()
+        .map(|(m, value)| format!("{};{}", m, value))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+/// Exposed to javascript to list the legal moves giving check in a position,
+/// in the same format as `legal_captures`.
+#[wasm_bindgen]
+pub fn legal_checks(fen: &str) -> Result<String, InputError> {
+    Ok(format_move_list(&checks(&board_from_fen(fen)?)))
+}
+
+/// Exposed to javascript to compare how many moves each side has, Shannon's
+/// mobility. Returns White's count, Black's and White's minus Black's,
+/// separated by semicolons (e.g. "20;20;0"). The side not to move has its
+/// moves generated as if it were its turn, unless the side to move is in
+/// check; it can't be given the move then, so its pseudo-legal moves are
+/// counted instead. Throws if the FEN is invalid.
+#[wasm_bindgen]
+pub fn mobility(fen: &str) -> Result<String, InputError> {
+    let position = board_from_fen(fen)?;
+    let count = |color: Color| {
+        if position.side_to_move() == color {
+            MoveGen::new_legal(&position).len() as i32
+        } else {
+            match position.null_move() {
+                Some(passed) => MoveGen::new_legal(&passed).len() as i32,
+                None => pseudo_legal_move_count(&position, color) as i32,
+            }
+        }
+    };
+    let (white, black) = (count(Color::White), count(Color::Black));
+    Ok(format!("{};{};{}", white, black, white - black))
+}
+
+/// Exposed to javascript to tell whether a move gives check, for marking it
+/// with a "+" in a move list. The move is in coordinates (e.g. "e2 e4", or
+/// "e7e8q" for a promotion). Returns false for an invalid FEN or a malformed
+/// or illegal move.
+#[wasm_bindgen]
+pub fn move_gives_check(fen: &str, uci_move: &str) -> bool {
+    let Ok(position) = board_from_fen(fen) else {
+        return false;
+    };
+    parse_coordinate_move(&position, uci_move)
+        .is_some_and(|m| position.make_move_new(m).checkers().popcnt() > 0)
+}
+
+// The king is attacked by the queen.
+#[test]
+fn in_check() {
+    assert!(is_in_check(
+        "rnb1kbnr/pppp1ppp/8/4p3/5PPq/8/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+    ));
+}
+
+// The king is attacked by both the knight and the discovering rook.
+#[test]
+fn in_double_check() {
+    let fen = "4r1k1/8/8/8/8/5n2/8/4K3 w - - 0 1";
+    assert_eq!(Board::from_str(fen).unwrap().checkers().popcnt(), 2);
+    assert!(is_in_check(fen));
+}
+
+// Neither a quiet position nor an invalid FEN is in check.
+#[test]
+fn not_in_check() {
+    assert!(!is_in_check(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    ));
+    assert!(!is_in_check("not a fen"));
+}
+
+// Both kings are found on their starting squares, and nothing is found in an
+// invalid FEN.
+#[test]
+fn finds_kings() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(king_square(start, "white"), "e1");
+    assert_eq!(king_square(start, "black"), "e8");
+    assert_eq!(king_square(start, "red"), "");
+    assert_eq!(king_square("not a fen", "white"), "");
+}
+
+// The starting position lists all 32 pieces, each of the right type and
+// color, and an invalid FEN is rejected.
+#[test]
+fn lists_piece_placement() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let placement = piece_placement(start).unwrap();
+    let entries: Vec<&str> = placement.split(',').collect();
+    assert_eq!(entries.len(), 32);
+    assert_eq!(
+        entries[..8],
+        ["a1:WR", "b1:WN", "c1:WB", "d1:WQ", "e1:WK", "f1:WB", "g1:WN", "h1:WR"]
+    );
+    assert!(entries[8..16].iter().all(|entry| entry.ends_with(":WP")));
+    assert!(entries[16..24].iter().all(|entry| entry.ends_with(":BP")));
+    assert_eq!(
+        entries[24..],
+        ["a8:BR", "b8:BN", "c8:BB", "d8:BQ", "e8:BK", "f8:BB", "g8:BN", "h8:BR"]
+    );
+    assert!(piece_placement("not a fen").is_err());
+}
+
+// Signatures list White's pieces first, strongest first, then Black's.
+#[test]
+fn material_signatures() {
+    assert_eq!(
+        material_signature("8/8/8/4k3/8/8/8/3QK3 w - - 0 1").unwrap(),
+        "KQvK"
+    );
+    assert_eq!(
+        material_signature("8/8/4k3/8/3r4/4P3/4K3/R7 w - - 0 1").unwrap(),
+        "KRPvKR"
+    );
+    assert_eq!(
+        material_signature("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP"
+    );
+    assert!(material_signature("not a fen").is_err());
+}
+
+// Invalid FENs are explained, valid ones aren't.
+#[test]
+fn explains_invalid_fens() {
+    assert_eq!(
+        fen_error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+        "missing black king"
+    );
+    assert_eq!(
+        fen_error("4k3/pppppppp/pppppppp/pp6/8/8/8/4K3 w - - 0 1"),
+        "too many black pieces"
+    );
+    assert_eq!(
+        fen_error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+        "side not to move is in check"
+    );
+    assert_eq!(fen_error("not a fen"), "missing fields");
+    assert_eq!(
+        fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        ""
+    );
+}
+
+// The starting position is full middlegame, bare kings pure endgame. An
+// invalid FEN is an error rather than another endgame.
+#[test]
+fn phase_bounds() {
+    assert_eq!(
+        position_phase("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Ok(256)
+    );
+    assert_eq!(position_phase("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), Ok(0));
+    assert_eq!(
+        position_phase("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1"),
+        Ok(0)
+    );
+    assert!(position_phase("8/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+}
+
+// Each kind of weakness is counted for the requested side only.
+#[test]
+fn counts_pawn_weaknesses() {
+    let fen = "4k3/p1p5/p7/4p3/2P5/3P3P/7P/4K3 w - - 0 1";
+    assert_eq!(pawn_weaknesses(fen, "white").unwrap(), "1;2;1");
+    assert_eq!(pawn_weaknesses(fen, "black").unwrap(), "1;4;0");
+    assert_eq!(
+        pawn_weaknesses(fen, "green"),
+        Err(InputError::InvalidColor(String::from("green")))
+    );
+}
+
+// The bishop on b5 pins the knight on c6 to Black's king, which is reported
+// even though it isn't Black's move.
+#[test]
+fn reports_pinned_pieces() {
+    let fen = "r1bqkbnr/ppp2ppp/2np4/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4";
+    assert_eq!(pinned_pieces(fen, "black").unwrap(), "c6");
+    assert_eq!(pinned_pieces(fen, "white").unwrap(), "");
+
+    // Two pieces between king and slider means neither is pinned.
+    let shielded = "4k3/4q3/8/8/8/4N3/4B3/4K3 w - - 0 1";
+    assert_eq!(pinned_pieces(shielded, "white").unwrap(), "");
+    let position = Board::from_str(shielded).unwrap();
+    assert_eq!(pinned(&position, Color::White), *position.pinned());
+}
+
+// Ordinary captures, en passant and capturing promotions are all listed, while
+// quiet moves to the en passant square and quiet promotions are not.
+#[test]
+fn lists_all_captures() {
+    let fen = "2r1k3/1P6/8/3pP3/8/6p1/8/R3K2N w - d6 0 2";
+    let captures = legal_captures(fen).unwrap();
+    let mut listed: Vec<&str> = captures.lines().collect();
+    listed.sort_unstable();
+    assert_eq!(
+        listed,
+        vec!["b7 c8b", "b7 c8n", "b7 c8q", "b7 c8r", "e5 d6", "h1 g3"]
+    );
+}
+
+// Taking a free piece wins it, trading knights comes out even and taking a
+// defended pawn with the queen loses the queen for it.
+#[test]
+fn capture_values() {
+    let free = "4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1";
+    let position = board_from_fen(free).unwrap();
+    assert_eq!(
+        capture_value(free, "e4d5").unwrap(),
+        crate::phased_value(Piece::Knight, game_phase(&position))
+    );
+
+    let trade = "4k3/8/4p3/3n4/8/4N3/8/4K3 w - - 0 1";
+    assert_eq!(capture_value(trade, "e3 d5").unwrap(), 0);
+
+    let defended = "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1";
+    assert!(capture_value(defended, "d1 d5").unwrap() < 0);
+
+    assert!(capture_value(defended, "d1 d2").is_err());
+    assert!(capture_value(defended, "d1 a4").is_err());
+    assert!(capture_value(defended, "d1").is_err());
+}
+
+// Captures come best first: the promotions taking the rook, the free pawn,
+// the even trade en passant, then the queen taking a defended pawn.
+#[test]
+fn ranks_captures() {
+    let fen = "r6k/1Pp4p/8/3pP3/8/8/8/4K2Q w - d6 0 1";
+    let position = board_from_fen(fen).unwrap();
+    let value = |piece| crate::phased_value(piece, game_phase(&position));
+    let ranked = rank_captures(fen).unwrap();
+    let lines: Vec<(&str, i32)> = ranked
+        .lines()
+        .map(|line| {
+            let (m, value) = line.split_once(';').unwrap();
+            (m, value.parse().unwrap())
+        })
+        .collect();
+
+    let moves: Vec<&str> = lines.iter().map(|&(m, _)| m).collect();
+    assert_eq!(moves[..2], ["b7 a8q", "b7 a8r"]);
+    assert_eq!(moves[4..], ["h1 d5", "e5 d6", "h1 h7"]);
+    assert_eq!(lines[4].1, value(Piece::Pawn));
+    assert_eq!(lines[5].1, 0);
+    assert_eq!(lines[6].1, value(Piece::Pawn) - value(Piece::Queen));
+    assert!(lines.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert!(rank_captures("not a fen").is_err());
+}
+
+// A quiet position has no captures at all.
+#[test]
+fn no_captures_in_quiet_position() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(legal_captures(start_fen).unwrap(), "");
+    assert!(legal_captures("not a fen").is_err());
+}
+
+// Direct checks, knight checks and checking promotions are all listed.
+#[test]
+fn lists_all_checks() {
+    let checks = legal_checks("4k3/1P6/8/8/4N3/8/8/R5K1 w - - 0 1").unwrap();
+    let mut listed: Vec<&str> = checks.lines().collect();
+    listed.sort_unstable();
+    assert_eq!(listed, vec!["a1 a8", "b7 b8q", "b7 b8r", "e4 d6", "e4 f6"]);
+}
+
+// No move gives check from the starting position.
+#[test]
+fn no_checks_in_quiet_position() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(legal_checks(start_fen).unwrap(), "");
+}
+
+// The rook checks along the back rank but not from the seventh, and the
+// knight uncovers the rook's check on the king. Illegal and malformed moves
+// don't give check.
+#[test]
+fn detects_checking_moves() {
+    let rook = "4k3/8/8/8/8/8/8/R5K1 w - - 0 1";
+    assert!(move_gives_check(rook, "a1 a8"));
+    assert!(!move_gives_check(rook, "a1a7"));
+    assert!(!move_gives_check(rook, "a1 b2"));
+    assert!(!move_gives_check(rook, "nonsense"));
+
+    let discovered = "4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1";
+    assert!(move_gives_check(discovered, "e4 c5"));
+    assert!(!move_gives_check("not a fen", "e4 c5"));
+}
+
+// The fifty move rule can be claimed from halfmove 100, but only ends the game
+// by itself at halfmove 150, and even then a checkmate stands.
+#[test]
+fn seventy_five_move_rule() {
+    let fen = |clock: u32| format!("4k3/8/8/8/8/8/8/R3K3 w - - {} 120", clock);
+    assert_eq!(game_status(&fen(99), "").unwrap(), "ongoing");
+    assert_eq!(game_status(&fen(100), "").unwrap(), "fifty-move");
+    assert_eq!(game_status(&fen(149), "").unwrap(), "fifty-move");
+    assert_eq!(game_status(&fen(150), "").unwrap(), "seventy-five-move");
+
+    let mated = "R3k3/8/4K3/8/8/8/8/8 b - - 150 120";
+    assert_eq!(game_status(mated, "").unwrap(), "checkmate");
+}
+
+// Shuffling the knights out and back repeats the starting position every four
+// plies: a draw may be claimed on its third occurrence, and the game is over
+// on its fifth.
+#[test]
+fn fivefold_repetition() {
+    use crate::notation::fen_after_move;
+
+    let shuffle = ["g1 f3", "g8 f6", "f3 g1", "f6 g8"];
+    let mut fens = vec![String::from(crate::START_FEN)];
+    for ply in 0..16 {
+        let fen = fens.last().unwrap();
+        let position = Board::from_str(fen).unwrap();
+        let m = crate::notation::parse_coordinate_move(&position, shuffle[ply % 4]).unwrap();
+        fens.push(fen_after_move(fen, &position, m));
+    }
+
+    let status_after = |plies: usize| game_status(&fens[plies], &fens[..plies].join("\n")).unwrap();
+    assert_eq!(status_after(4), "ongoing");
+    assert_eq!(status_after(8), "threefold-repetition");
+    assert_eq!(status_after(12), "threefold-repetition");
+    assert_eq!(status_after(16), "fivefold-repetition");
+}
+
+// Every move from the start has 20 replies, and the known depth 3 counts come
+// out move by move.
+#[test]
+fn perft_divide_counts() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let divide = perft_divide(start_fen, 2).unwrap();
+    let lines: Vec<&str> = divide.lines().collect();
+    assert_eq!(lines.len(), 21);
+    assert_eq!(lines[0], "a2a3: 20");
+    assert!(lines[..20].iter().all(|line| line.ends_with(": 20")));
+    assert_eq!(lines[20], "total: 400");
+
+    let divide = perft_divide(start_fen, 3).unwrap();
+    assert!(divide.lines().any(|line| line == "e2e4: 600"));
+    assert!(divide.lines().any(|line| line == "g1f3: 440"));
+    assert!(divide.ends_with("total: 8902"));
+    assert_eq!(perft_divide(start_fen, 0).unwrap(), "total: 1");
+}
+
+// Every field of a mid-game FEN comes back as the FEN gives it.
+#[test]
+fn position_info_fields() {
+    let info = parse_position("r3k2r/ppp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R w Kq d6 0 12").unwrap();
+    assert_eq!(info.side_to_move(), "white");
+    assert!(info.white_kingside());
+    assert!(!info.white_queenside());
+    assert!(!info.black_kingside());
+    assert!(info.black_queenside());
+    assert_eq!(info.en_passant(), Some(String::from("d6")));
+    assert_eq!(info.halfmove_clock(), 0);
+    assert_eq!(info.fullmove_number(), 12);
+
+    let info = parse_position("r3k2r/ppp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R b - - 3 12").unwrap();
+    assert_eq!(info.side_to_move(), "black");
+    assert_eq!(info.en_passant(), None);
+    assert_eq!(info.halfmove_clock(), 3);
+    assert!(parse_position("not a fen").is_err());
+}
+
+// With clear, safe squares every castling move the FEN allows is legal.
+#[test]
+fn castling_fully_available() {
+    let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+    assert_eq!(castling_options(fen).unwrap(), "true;true;true;true");
+    let fen = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b Kq - 0 1";
+    assert_eq!(castling_options(fen).unwrap(), "true;false;false;true");
+}
+
+// The rights remain, but pieces in the way, an attacked square the king would
+// cross and a check each rule castling out for now. Black can't castle while
+// White is in check either, as the move can't pass to Black.
+#[test]
+fn castling_rights_but_blocked() {
+    let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(
+        castling_options(start_fen).unwrap(),
+        "false;false;false;false"
+    );
+
+    // The bishop on c4 covers f1, and the rook on d8 covers d1.
+    let attacked = "3rk2r/8/8/8/2b5/8/8/R3K2R w KQk - 0 1";
+    assert_eq!(
+        castling_options(attacked).unwrap(),
+        "false;false;true;false"
+    );
+
+    let in_check = "r3k2r/8/8/8/8/8/4r3/R3K2R w KQkq - 0 1";
+    assert_eq!(
+        castling_options(in_check).unwrap(),
+        "false;false;false;false"
+    );
+    assert!(castling_options("not a fen").is_err());
+}
+
+// Both sides start with twenty moves. A stalemated king has none against
+// the queen's and king's 26, and when Black is in check White's moves are
+// counted pseudo-legally.
+#[test]
+fn mobility_counts() {
+    assert_eq!(
+        mobility("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        "20;20;0"
+    );
+    assert_eq!(
+        mobility("7k/5Q2/8/8/8/8/8/K7 b - - 0 1").unwrap(),
+        "26;0;26"
+    );
+    assert_eq!(
+        mobility("k7/8/8/8/8/8/8/R5K1 b - - 0 1").unwrap(),
+        "17;2;15"
+    );
+    assert!(mobility("not a fen").is_err());
+}
+
+// Rights are dropped where the rook has left its corner or the king its
+// square, leaving a FEN the engine accepts. Rights that stand are kept as
+// they are.
+#[test]
+fn canonicalizes_castling() {
+    let spurious = "r3k3/8/8/8/8/8/8/R3K3 w KQkq - 0 1";
+    let canonical = canonicalize_castling(spurious);
+    assert_eq!(canonical, "r3k3/8/8/8/8/8/8/R3K3 w Qq - 0 1");
+    assert!(board_from_fen(spurious).is_err());
+    assert!(board_from_fen(&canonical).is_ok());
+    assert_eq!(
+        canonicalize_castling("r3k2r/8/8/8/8/8/8/R4K1R b KQk - 0 1"),
+        "r3k2r/8/8/8/8/8/8/R4K1R b k - 0 1"
+    );
+
+    let valid = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+    assert_eq!(canonicalize_castling(valid), valid);
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(canonicalize_castling(start), start);
+    assert_eq!(canonicalize_castling("not a fen"), "not a fen");
+}
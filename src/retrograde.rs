@@ -0,0 +1,374 @@
+//! Exact distances to mate for the endings of three pieces or fewer, worked
+//! out by retrograde analysis: every position of an ending is laid out in a
+//! table, and starting from the checkmates the search works backwards,
+//! un-making moves, until every position that can be won is known.
+//!
+//! The tables only hold positions with the extra piece on White's side.
+//! Positions where Black has it are flipped top to bottom and their colors
+//! swapped to fit.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chess::{
+    get_bishop_moves, get_king_moves, get_pawn_attacks, get_rook_moves, BitBoard, Color, Piece,
+    Square, ALL_SQUARES, EMPTY,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::input::{board_from_fen, InputError};
+
+/// The number of positions in a table: either side to move, with the strong
+/// king, the weak king and the piece each on any square.
+const TABLE_SIZE: usize = 2 * 64 * 64 * 64;
+
+/// A table entry for a position that isn't won, or can't occur at all.
+const UNRESOLVED: u8 = u8::MAX;
+
+thread_local! {
+    /// The tables built so far, with the piece each is for.
+    static TABLES: RefCell<Vec<(Piece, Rc<[u8]>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A position in a table, White being the side with the piece.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+    white_to_move: bool,
+    white_king: Square,
+    black_king: Square,
+    piece: Square,
+}
+
+impl Entry {
+    fn index(&self) -> usize {
+        let side = if self.white_to_move { 0 } else { 1 };
+        ((side * 64 + self.white_king.to_index()) * 64 + self.black_king.to_index()) * 64
+            + self.piece.to_index()
+    }
+
+    fn from_index(index: usize) -> Entry {
+        Entry {
+            white_to_move: index / (64 * 64 * 64) == 0,
+            white_king: ALL_SQUARES[index / (64 * 64) % 64],
+            black_king: ALL_SQUARES[index / 64 % 64],
+            piece: ALL_SQUARES[index % 64],
+        }
+    }
+
+    fn occupied(&self) -> BitBoard {
+        BitBoard::from_square(self.white_king)
+            | BitBoard::from_square(self.black_king)
+            | BitBoard::from_square(self.piece)
+    }
+}
+
+/// The squares White's piece attacks from `square`, sliders stopping at
+/// `occupied`.
+fn piece_attacks(piece: Piece, square: Square, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Queen => get_rook_moves(square, occupied) | get_bishop_moves(square, occupied),
+        Piece::Rook => get_rook_moves(square, occupied),
+        Piece::Pawn => get_pawn_attacks(square, Color::White, !EMPTY),
+        _ => EMPTY,
+    }
+}
+
+/// Whether a position can occur: the three pieces on different squares, the
+/// kings apart, a pawn off the first and last ranks, and Black's king out of
+/// check if it is White's move.
+fn is_legal(piece: Piece, entry: &Entry) -> bool {
+    let (white_king, black_king) = (entry.white_king, entry.black_king);
+    if white_king == black_king || entry.piece == white_king || entry.piece == black_king {
+        return false;
+    }
+    if get_king_moves(white_king) & BitBoard::from_square(black_king) != EMPTY {
+        return false;
+    }
+    if piece == Piece::Pawn && matches!(entry.piece.get_rank().to_index(), 0 | 7) {
+        return false;
+    }
+    let checked = piece_attacks(piece, entry.piece, entry.occupied())
+        & BitBoard::from_square(black_king)
+        != EMPTY;
+    !(entry.white_to_move && checked)
+}
+
+/// The squares Black's king can move to, the piece's own square included if
+/// White's king doesn't guard it.
+fn black_king_moves(piece: Piece, entry: &Entry) -> BitBoard {
+    // The king's own square no longer blocks a slider checking it.
+    let occupied = entry.occupied() ^ BitBoard::from_square(entry.black_king);
+    let guarded = get_king_moves(entry.white_king) | BitBoard::from_square(entry.white_king);
+    let attacked = guarded | piece_attacks(piece, entry.piece, occupied);
+    get_king_moves(entry.black_king) & !attacked
+}
+
+/// The positions with White to move from which one of White's moves leads to
+/// `entry`, with Black to move.
+fn white_unmoves(piece: Piece, entry: &Entry) -> Vec<Entry> {
+    let mut earlier = Vec::new();
+    let empty = !entry.occupied();
+    for from in get_king_moves(entry.white_king) & empty {
+        earlier.push(Entry {
+            white_king: from,
+            ..*entry
+        });
+    }
+
+    let froms = match piece {
+        Piece::Pawn => {
+            // A pawn comes from behind, or two squares behind from its
+            // starting square.
+            let mut froms = EMPTY;
+            if let Some(behind) = entry
+                .piece
+                .down()
+                .filter(|&s| empty & BitBoard::from_square(s) != EMPTY)
+            {
+                if behind.get_rank().to_index() > 0 {
+                    froms |= BitBoard::from_square(behind);
+                }
+                if entry.piece.get_rank().to_index() == 3 {
+                    froms |= behind.down().map_or(EMPTY, BitBoard::from_square) & empty;
+                }
+            }
+            froms
+        }
+        _ => piece_attacks(piece, entry.piece, entry.occupied()) & empty,
+    };
+    for from in froms {
+        earlier.push(Entry {
+            piece: from,
+            ..*entry
+        });
+    }
+
+    earlier
+        .into_iter()
+        .map(|earlier| Entry {
+            white_to_move: true,
+            ..earlier
+        })
+        .filter(|earlier| is_legal(piece, earlier))
+        .collect()
+}
+
+/// The positions with Black to move from which one of Black's king moves
+/// leads to `entry`, with White to move.
+fn black_unmoves(piece: Piece, entry: &Entry) -> Vec<Entry> {
+    (get_king_moves(entry.black_king) & !entry.occupied())
+        .map(|from| Entry {
+            white_to_move: false,
+            black_king: from,
+            ..*entry
+        })
+        .filter(|earlier| is_legal(piece, earlier))
+        .collect()
+}
+
+/// The table for the ending of the two kings and `piece`, building it the
+/// first time it is asked for.
+fn table(piece: Piece) -> Rc<[u8]> {
+    let cached = TABLES.with(|tables| {
+        tables
+            .borrow()
+            .iter()
+            .find(|(built, _)| *built == piece)
+            .map(|(_, table)| Rc::clone(table))
+    });
+    if let Some(table) = cached {
+        return table;
+    }
+    let table: Rc<[u8]> = build_table(piece).into();
+    TABLES.with(|tables| tables.borrow_mut().push((piece, Rc::clone(&table))));
+    table
+}
+
+/// Work out every position of the ending with `piece` (a queen, rook or
+/// pawn): how many plies White needs to mate from it, or UNRESOLVED if White
+/// can't force mate or the position can't occur.
+fn build_table(piece: Piece) -> Vec<u8> {
+    let mut plies = vec![UNRESOLVED; TABLE_SIZE];
+    // How many of Black's moves from each position aren't yet known to lose.
+    let mut remaining = vec![0u8; TABLE_SIZE];
+    // Positions waiting to be worked back from, by their plies to mate.
+    let mut queue: Vec<Vec<usize>> = vec![Vec::new(); UNRESOLVED as usize];
+    let promotions = match piece {
+        Piece::Pawn => vec![table(Piece::Queen), table(Piece::Rook)],
+        _ => Vec::new(),
+    };
+
+    for index in 0..TABLE_SIZE {
+        let entry = Entry::from_index(index);
+        if !is_legal(piece, &entry) {
+            continue;
+        }
+        if entry.white_to_move {
+            // A promotion leaves the table, for one worked out already.
+            if piece == Piece::Pawn && entry.piece.get_rank().to_index() == 6 {
+                let target = entry.piece.up().unwrap();
+                if entry.occupied() & BitBoard::from_square(target) == EMPTY {
+                    let after = Entry {
+                        white_to_move: false,
+                        piece: target,
+                        ..entry
+                    };
+                    let best = promotions
+                        .iter()
+                        .map(|promoted| promoted[after.index()])
+                        .min()
+                        .unwrap();
+                    if best < UNRESOLVED - 1 {
+                        plies[index] = best + 1;
+                        queue[best as usize + 1].push(index);
+                    }
+                }
+            }
+            continue;
+        }
+        let moves = black_king_moves(piece, &entry).popcnt() as u8;
+        let in_check = piece_attacks(piece, entry.piece, entry.occupied())
+            & BitBoard::from_square(entry.black_king)
+            != EMPTY;
+        if moves == 0 && in_check {
+            plies[index] = 0;
+            queue[0].push(index);
+        }
+        remaining[index] = moves;
+    }
+
+    for ply in 0..queue.len() {
+        let mut bucket = std::mem::take(&mut queue[ply]);
+        bucket.retain(|&index| plies[index] == ply as u8);
+        for index in bucket {
+            let entry = Entry::from_index(index);
+            if entry.white_to_move {
+                // Black loses once every one of its moves is known to.
+                for earlier in black_unmoves(piece, &entry) {
+                    let earlier = earlier.index();
+                    if remaining[earlier] == 0 || plies[earlier] != UNRESOLVED {
+                        continue;
+                    }
+                    remaining[earlier] -= 1;
+                    if remaining[earlier] == 0 && ply + 1 < queue.len() {
+                        plies[earlier] = ply as u8 + 1;
+                        queue[ply + 1].push(earlier);
+                    }
+                }
+            } else {
+                // White wins by playing into any position Black loses.
+                for earlier in white_unmoves(piece, &entry) {
+                    let earlier = earlier.index();
+                    if plies[earlier] > ply as u8 + 1 && ply + 1 < queue.len() {
+                        plies[earlier] = ply as u8 + 1;
+                        queue[ply + 1].push(earlier);
+                    }
+                }
+            }
+        }
+    }
+    plies
+}
+
+/// Flip a square top to bottom, so that a1 becomes a8.
+fn flip(square: Square) -> Square {
+    ALL_SQUARES[square.to_index() ^ 56]
+}
+
+/// Exposed to javascript to give the exact distance to mate in an ending of
+/// three pieces or fewer, for playing it perfectly. Returns how many moves the
+/// winning side needs to mate with best play on both sides, counting the
+/// mating move, positive if White is winning and negative if Black is. A
+/// drawn position returns 0, as do the endings that can't be won (the bare
+/// kings, or a lone bishop or knight) and a position that is already
+/// checkmate. Castling rights are ignored. Tables are built the first time
+/// each ending is asked for. Throws if the FEN is invalid or there are more
+/// than three pieces.
+#[wasm_bindgen]
+pub fn distance_to_mate(fen: &str) -> Result<i32, InputError> {
+    let position = board_from_fen(fen)?;
+    let occupied = *position.combined();
+    if occupied.popcnt() > 3 {
+        return Err(InputError::TooManyPieces(String::from(fen)));
+    }
+    let kings = *position.pieces(Piece::King);
+    let Some(square) = (occupied & !kings).into_iter().next() else {
+        return Ok(0);
+    };
+    let (piece, strong) = (
+        position.piece_on(square).unwrap(),
+        position.color_on(square).unwrap(),
+    );
+    if !matches!(piece, Piece::Queen | Piece::Rook | Piece::Pawn) {
+        return Ok(0);
+    }
+
+    let orient = |square: Square| match strong {
+        Color::White => square,
+        Color::Black => flip(square),
+    };
+    let entry = Entry {
+        white_to_move: position.side_to_move() == strong,
+        white_king: orient(position.king_square(strong)),
+        black_king: orient(position.king_square(!strong)),
+        piece: orient(square),
+    };
+    let plies = table(piece)[entry.index()];
+    if plies == UNRESOLVED {
+        return Ok(0);
+    }
+    let moves = (plies as i32 + 1) / 2;
+    Ok(match strong {
+        Color::White => moves,
+        Color::Black => -moves,
+    })
+}
+
+// A mate on the move is one move away, negative when Black gives it, and a
+// position already checkmate is 0.
+#[test]
+fn mates_in_one() {
+    assert_eq!(
+        distance_to_mate("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap(),
+        1
+    );
+    assert_eq!(
+        distance_to_mate("k7/8/1K6/8/8/8/7Q/8 w - - 0 1").unwrap(),
+        1
+    );
+    assert_eq!(
+        distance_to_mate("7r/8/8/8/8/1k6/8/K7 b - - 0 1").unwrap(),
+        -1
+    );
+    assert_eq!(
+        distance_to_mate("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1").unwrap(),
+        0
+    );
+}
+
+// The longest wins are the known ones: 10 moves with a queen, 16 with a rook.
+// A rook left en prise and a rook's pawn the defending king stands in front
+// of are draws, and more than three pieces are rejected.
+#[test]
+fn longest_mates_and_draws() {
+    let longest = |piece| {
+        table(piece)
+            .iter()
+            .filter(|&&plies| plies != UNRESOLVED)
+            .max()
+            .map(|&plies| (plies as i32 + 1) / 2)
+    };
+    assert_eq!(longest(Piece::Queen), Some(10));
+    assert_eq!(longest(Piece::Rook), Some(16));
+
+    assert_eq!(
+        distance_to_mate("8/8/8/8/8/2k5/1R6/7K b - - 0 1").unwrap(),
+        0
+    );
+    assert_eq!(
+        distance_to_mate("k7/8/8/8/8/8/P7/1K6 w - - 0 1").unwrap(),
+        0
+    );
+    assert!(distance_to_mate("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() > 0);
+    assert!(distance_to_mate("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1").is_err());
+}
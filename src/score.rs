@@ -0,0 +1,162 @@
+//! The scores passed around the search. Like every eval they are White-relative
+//! in centipawns, but mates are encoded as a distance from the root, so
+//! they are wrapped to keep stray arithmetic (adding a bonus, comparing against
+//! the wrong bound) from quietly corrupting one.
+
+use std::ops::Neg;
+
+use chess::Color;
+
+/// The evaluation of a checkmate, which bounds every other evaluation.
+pub const MATE_SCORE: i32 = 100000;
+
+/// Mates are scored as MATE_SCORE less the number of plies taken to deliver
+/// them, so any eval within this many plies of MATE_SCORE is a forced mate.
+const MAX_MATE_PLY: i32 = 256;
+
+/// A White-relative score found by the search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score(i32);
+
+impl Score {
+    /// An even position, and the score of a draw.
+    pub const DRAW: Score = Score(0);
+    /// White mated on the board, below every other score.
+    pub const MIN: Score = Score(-MATE_SCORE);
+    /// Black mated on the board, above every other score.
+    pub const MAX: Score = Score(MATE_SCORE);
+
+    /// Wrap a static evaluation.
+    pub fn eval(eval: i32) -> Score {
+        Score(eval)
+    }
+
+    /// A mate delivered by `winner`, `ply` plies from the root. Mates closer to
+    /// the root score further from zero, so the engine goes for the quickest
+    /// mate and holds out longest against one.
+    pub fn mate(winner: Color, ply: u32) -> Score {
+        let score = Score(MATE_SCORE - ply as i32);
+        match winner {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+
+    /// The raw White-relative value, as reported to javascript.
+    pub fn value(self) -> i32 {
+        self.0
+    }
+
+    /// Whether the score is a forced mate.
+    pub fn is_mate(self) -> bool {
+        self.0.abs() > MATE_SCORE - MAX_MATE_PLY
+    }
+
+    /// The number of moves the mating side needs to deliver a forced mate,
+    /// negative when Black is the one mating, or None if the score isn't a
+    /// mate.
+    pub fn mate_moves(self) -> Option<i32> {
+        if !self.is_mate() {
+            return None;
+        }
+        // The mating side plays every other ply, starting with the first.
+        let mate_ply = MATE_SCORE - self.0.abs();
+        Some(self.0.signum() * ((mate_ply + 1) / 2))
+    }
+
+    /// Add `bonus` to an eval, as for a term the search applies on top of the
+    /// static eval. Mates are left as they are.
+    pub fn with_bonus(self, bonus: i32) -> Score {
+        if self.is_mate() {
+            self
+        } else {
+            Score(self.0 + bonus)
+        }
+    }
+
+    /// The next score up, for the upper edge of a null window.
+    pub fn next_up(self) -> Score {
+        Score(self.0 + 1)
+    }
+
+    /// The next score down, for the lower edge of a null window.
+    pub fn next_down(self) -> Score {
+        Score(self.0 - 1)
+    }
+
+    /// Mate scores count plies from the root, but a stored position may be
+    /// reached at any ply, so convert one found `ply` plies from the root to
+    /// count from the position itself. Other scores are unchanged.
+    pub fn to_node_relative(self, ply: u32) -> Score {
+        if self.is_mate() {
+            Score(self.0 + self.0.signum() * ply as i32)
+        } else {
+            self
+        }
+    }
+
+    /// The reverse of `to_node_relative`.
+    pub fn to_root_relative(self, ply: u32) -> Score {
+        if self.is_mate() {
+            Score(self.0 - self.0.signum() * ply as i32)
+        } else {
+            self
+        }
+    }
+
+    /// Format the score for display. Forced mates are shown as the number of
+    /// moves the mating side needs, negative when Black is the one mating
+    /// (e.g. "mate 3", "mate -2"). Anything else is shown as the plain eval.
+    pub fn to_text(self) -> String {
+        match self.mate_moves() {
+            Some(moves) => format!("mate {}", moves),
+            None => self.0.to_string(),
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    /// The same score with the colors swapped.
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+// Mates by either side are told apart from ordinary evals, and a mate the same
+// distance away for the other side is the negation.
+#[test]
+fn mate_distances() {
+    assert_eq!(Score::mate(Color::White, 3).mate_moves(), Some(2));
+    assert_eq!(Score::mate(Color::Black, 1).mate_moves(), Some(-1));
+    assert_eq!(-Score::mate(Color::White, 5), Score::mate(Color::Black, 5));
+    assert!(Score::mate(Color::White, 1) > Score::mate(Color::White, 3));
+    assert!(Score::mate(Color::Black, 1) < Score::mate(Color::Black, 3));
+    assert_eq!(Score::eval(900).mate_moves(), None);
+    assert!(!Score::eval(-900).is_mate());
+}
+
+// Converting to node-relative and back restores the score, and only mates are
+// shifted.
+#[test]
+fn node_relative_round_trip() {
+    let mate = Score::mate(Color::Black, 6);
+    assert_eq!(mate.to_node_relative(4), Score::mate(Color::Black, 2));
+    assert_eq!(mate.to_node_relative(4).to_root_relative(4), mate);
+    assert_eq!(Score::eval(37).to_node_relative(4), Score::eval(37));
+}
+
+// Ordinary evals are left as they are.
+#[test]
+fn formats_plain_scores() {
+    assert_eq!(Score::eval(25).to_text(), "25");
+    assert_eq!(Score::eval(-130).to_text(), "-130");
+}
+
+// Mates are given in moves, negative when Black is the one mating.
+#[test]
+fn formats_mate_scores() {
+    assert_eq!(Score::mate(Color::White, 1).to_text(), "mate 1");
+    assert_eq!(Score::mate(Color::Black, 3).to_text(), "mate -2");
+}
@@ -0,0 +1,101 @@
+//! A suite of well-known tactical positions the engine must solve, run as a
+//! regression test for changes to the search and evaluation.
+
+use crate::notation::format_coordinate_move;
+use crate::{get_best_move_minimax_alpha_beta, prepare_search};
+
+/// How deep each position is searched. Every tactic in the suite, the mates in
+/// two included, is found within three plies.
+const TACTICS_DEPTH: u32 = 2;
+
+/// The positions in the suite, each with the theme it tests and the only move
+/// that solves it.
+const TACTICS: [(&str, &str, &str); 14] = [
+    (
+        "knight fork",
+        "6k1/3q1p1p/8/8/4N3/8/5PPP/6K1 w - - 0 1",
+        "e4 f6",
+    ),
+    ("pawn fork", "4k3/8/2n1r3/8/3P4/8/8/6K1 w - - 0 1", "d4 d5"),
+    (
+        "pin to the king",
+        "4k3/3q4/8/8/P7/8/8/4KB2 w - - 0 1",
+        "f1 b5",
+    ),
+    (
+        "attacking a pinned piece",
+        "4k3/1p6/2n5/1B6/3P4/8/5PPP/6K1 w - - 0 1",
+        "d4 d5",
+    ),
+    ("skewer", "8/8/8/8/3k3q/8/8/R5K1 w - - 0 1", "a1 a4"),
+    (
+        "discovered check",
+        "7k/8/8/1q6/8/2N5/8/B5K1 w - - 0 1",
+        "c3 b5",
+    ),
+    (
+        "hanging queen",
+        "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1",
+        "d2 d5",
+    ),
+    (
+        "back rank mate",
+        "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+        "d1 d8",
+    ),
+    (
+        "back rank mate for black",
+        "3r2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1",
+        "d8 d1",
+    ),
+    (
+        "scholar's mate",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        "f3 f7",
+    ),
+    (
+        "smothered mate",
+        "6rk/6pp/8/6N1/8/8/8/6K1 w - - 0 1",
+        "g5 f7",
+    ),
+    ("rook mate", "k7/8/1K6/8/8/8/8/7R w - - 0 1", "h1 h8"),
+    (
+        "queen sacrifice for a smothered mate",
+        "3r3k/6pp/7N/8/2Q5/8/6PP/6K1 w - - 0 1",
+        "c4 g8",
+    ),
+    (
+        "queen sacrifice for a back rank mate",
+        "3rr1k1/5ppp/8/8/8/8/4QPPP/4R1K1 w - - 0 1",
+        "e2 e8",
+    ),
+];
+
+// The engine finds the solution to every position in the suite. All the
+// failures are reported together, so one change breaking several tactics is
+// seen at once.
+#[test]
+fn solves_tactics_suite() {
+    let failures: Vec<String> = TACTICS
+        .iter()
+        .filter_map(|&(theme, fen, solution)| {
+            let found = get_best_move_minimax_alpha_beta(fen, TACTICS_DEPTH).unwrap();
+            (found != solution).then(|| format!("{}: played {}, not {}", theme, found, solution))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// Razoring never prunes away the solution: every position in the suite gets
+// the same best move with it turned off.
+#[test]
+fn razoring_keeps_tactics() {
+    for &(theme, fen, solution) in TACTICS.iter() {
+        let (position, mut searcher, halfmove_clock) = prepare_search(fen).unwrap();
+        searcher.razoring = false;
+        let (found, _) = searcher
+            .search_root(&position, TACTICS_DEPTH, halfmove_clock)
+            .unwrap();
+        assert_eq!(format_coordinate_move(&found), solution, "{}", theme);
+    }
+}
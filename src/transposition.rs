@@ -0,0 +1,183 @@
+//! A transposition table, caching search results by position hash so that a
+//! position reached again (by another move order, or in a later search) needn't
+//! be searched again. Scores are White-relative.
+
+use std::mem;
+
+use crate::score::Score;
+
+/// The memory a table takes unless configured otherwise, in megabytes.
+pub const DEFAULT_HASH_SIZE_MB: u32 = 2;
+
+/// How a stored score relates to the position's true eval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    /// The score is the true eval.
+    Exact,
+    /// The search failed high, the true eval is at least the score.
+    Lower,
+    /// The search failed low, the true eval is at most the score.
+    Upper,
+}
+
+/// A search result for a single position.
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    /// The full hash of the position, to tell it apart from others sharing
+    /// the slot.
+    hash: u64,
+    /// How many plies deep the position was searched.
+    pub depth: u32,
+    /// The score found, with mates counted from this position.
+    pub score: Score,
+    pub bound: Bound,
+    /// The search the entry was stored in.
+    generation: u8,
+}
+
+/// Search results for previously seen positions, indexed by hash. The entries
+/// are only allocated once something is stored, so an unused table is cheap.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    /// The number of entries once allocated, always a power of two.
+    size: usize,
+    /// Bumped for every new search, so entries left over from earlier ones
+    /// can be told apart and replaced first.
+    generation: u8,
+    /// Number of probes since the last new search.
+    pub probes: u64,
+    /// Number of probes that found an entry for the position since the last
+    /// new search.
+    pub hits: u64,
+}
+
+impl TranspositionTable {
+    /// A table using at most the given number of megabytes. Each entry takes
+    /// 24 bytes and the count is rounded down to a power of two, so a
+    /// megabyte holds 32768 entries and the default two hold 65536.
+    pub fn with_size_mb(mb: u32) -> TranspositionTable {
+        let bytes = mb as usize * 1024 * 1024;
+        TranspositionTable::with_entries(bytes / mem::size_of::<Option<Entry>>())
+    }
+
+    /// A table with room for the given number of entries, rounded down to a
+    /// power of two (and at least one).
+    fn with_entries(entries: usize) -> TranspositionTable {
+        let size = match entries {
+            0 => 1,
+            entries => 1 << entries.ilog2(),
+        };
+        TranspositionTable {
+            entries: Vec::new(),
+            size,
+            generation: 0,
+            probes: 0,
+            hits: 0,
+        }
+    }
+
+    /// Forget every entry.
+    pub fn clear(&mut self) {
+        *self = TranspositionTable::with_entries(self.size);
+    }
+
+    /// Start a new search. Existing entries are kept but age, so they give way
+    /// to anything stored from now on.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.probes = 0;
+        self.hits = 0;
+    }
+
+    /// The slot a hash maps to. The size is a power of two, so masking is the
+    /// same as taking the hash modulo the size.
+    fn index(&self, hash: u64) -> usize {
+        (hash as usize) & (self.size - 1)
+    }
+
+    /// Look up the entry for a position `ply` plies from the root, with any
+    /// mate score converted back to count from the root.
+    pub fn probe(&mut self, hash: u64, ply: u32) -> Option<Entry> {
+        self.probes += 1;
+        let entry = self.entries.get(self.index(hash)).copied().flatten()?;
+        if entry.hash != hash {
+            return None;
+        }
+
+        self.hits += 1;
+        Some(Entry {
+            score: entry.score.to_root_relative(ply),
+            ..entry
+        })
+    }
+
+    /// Store the result of searching a position `ply` plies from the root. An
+    /// existing entry for a different position is only replaced if it is
+    /// from an earlier search or was searched no deeper.
+    pub fn store(&mut self, hash: u64, depth: u32, score: Score, bound: Bound, ply: u32) {
+        if self.entries.is_empty() {
+            self.entries = vec![None; self.size];
+        }
+
+        let index = self.index(hash);
+        let slot = &mut self.entries[index];
+        let replace = slot.is_none_or(|existing| {
+            existing.hash == hash
+                || existing.generation != self.generation
+                || depth >= existing.depth
+        });
+        if replace {
+            *slot = Some(Entry {
+                hash,
+                depth,
+                score: score.to_node_relative(ply),
+                bound,
+                generation: self.generation,
+            });
+        }
+    }
+}
+
+// Mate scores come back out counted from wherever the position is reached.
+#[test]
+fn mate_scores_move_with_ply() {
+    use chess::Color;
+
+    let mut table = TranspositionTable::with_entries(1024);
+    table.store(42, 3, Score::mate(Color::White, 5), Bound::Exact, 2);
+    let entry = table.probe(42, 4).unwrap();
+    assert_eq!(entry.score, Score::mate(Color::White, 7));
+    assert_eq!(entry.bound, Bound::Exact);
+    assert!(table.probe(42 + 1024, 4).is_none());
+    assert_eq!(table.probes, 2);
+    assert_eq!(table.hits, 1);
+}
+
+// A deeper result for another position only displaces a shallower one, unless
+// the existing entry is left over from an earlier search.
+#[test]
+fn replacement_prefers_depth_then_age() {
+    let other = 7 + 1024;
+    let mut table = TranspositionTable::with_entries(1024);
+    table.store(7, 4, Score::eval(10), Bound::Exact, 0);
+    table.store(other, 2, Score::eval(20), Bound::Exact, 0);
+    assert_eq!(table.probe(7, 0).unwrap().score, Score::eval(10));
+
+    table.new_search();
+    table.store(other, 2, Score::eval(20), Bound::Exact, 0);
+    assert!(table.probe(7, 0).is_none());
+    assert_eq!(table.probe(other, 0).unwrap().score, Score::eval(20));
+}
+
+// Sizes are rounded down to a power of two entries.
+#[test]
+fn table_sizes() {
+    assert_eq!(mem::size_of::<Option<Entry>>(), 24);
+    assert_eq!(TranspositionTable::with_size_mb(1).size, 1 << 15);
+    assert_eq!(
+        TranspositionTable::with_size_mb(DEFAULT_HASH_SIZE_MB).size,
+        1 << 16
+    );
+    assert_eq!(TranspositionTable::with_size_mb(0).size, 1);
+    assert_eq!(TranspositionTable::with_entries(1000).size, 512);
+}
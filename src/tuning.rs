@@ -0,0 +1,312 @@
+//! Reading and writing the evaluation weights as JSON, so that an external
+//! tuner can adjust them between searches without recompiling.
+
+use std::cell::Cell;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use wasm_bindgen::prelude::*;
+
+use crate::input::InputError;
+use crate::EvalWeights;
+
+thread_local! {
+    /// The weights every search starts from, as last set by
+    /// `set_eval_weights`.
+    static TUNED_WEIGHTS: Cell<EvalWeights> = Cell::new(EvalWeights::default());
+}
+
+/// The weights every search starts from.
+pub fn tuned_weights() -> EvalWeights {
+    TUNED_WEIGHTS.with(Cell::get)
+}
+
+/// The reasons a weights JSON string can be rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WeightsError {
+    /// The text isn't valid JSON of the shape expected, failing at the given
+    /// character offset.
+    Malformed(usize),
+    /// A key that isn't one of the weights.
+    UnknownKey(String),
+    /// The same key given more than once.
+    DuplicateKey(String),
+    /// A weight missing from the object.
+    MissingKey(&'static str),
+    /// A weight given a value of the wrong type, or a negative scaling.
+    InvalidValue(String),
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeightsError::Malformed(offset) => write!(f, "malformed JSON at offset {}", offset),
+            WeightsError::UnknownKey(key) => write!(f, "unknown weight \"{}\"", key),
+            WeightsError::DuplicateKey(key) => write!(f, "weight \"{}\" given twice", key),
+            WeightsError::MissingKey(key) => write!(f, "missing weight \"{}\"", key),
+            WeightsError::InvalidValue(key) => write!(f, "invalid value for \"{}\"", key),
+        }
+    }
+}
+
+/// The weights' keys, in the order they are written.
+const KEYS: [&str; 5] = [
+    "king_shelter",
+    "king_pressure",
+    "development",
+    "rim_knights",
+    "exact_mobility",
+];
+
+/// A JSON value of one of the shapes the weights use.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Number(i32),
+    Bool(bool),
+    Array(Vec<i32>),
+}
+
+/// Write the tunable weights (every weight but the noise) as a JSON object.
+pub fn weights_to_json(weights: &EvalWeights) -> String {
+    format!(
+        "{{\"king_shelter\":[{},{}],\"king_pressure\":[{},{}],\"development\":{},\"rim_knights\":{},\"exact_mobility\":{}}}",
+        weights.king_shelter[0],
+        weights.king_shelter[1],
+        weights.king_pressure[0],
+        weights.king_pressure[1],
+        weights.development,
+        weights.rim_knights,
+        weights.exact_mobility,
+    )
+}
+
+/// Read the tunable weights from a JSON object as written by
+/// `weights_to_json`. Every key must be present exactly once. The noise is
+/// taken from `base`.
+pub fn weights_from_json(json: &str, base: EvalWeights) -> Result<EvalWeights, WeightsError> {
+    let mut parser = Parser {
+        chars: json.chars().peekable(),
+        offset: 0,
+    };
+    let fields = parser.object()?;
+
+    let mut weights = base;
+    for key in KEYS {
+        let value = fields
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .ok_or(WeightsError::MissingKey(key))?;
+        let invalid = || WeightsError::InvalidValue(String::from(key));
+        match (key, value) {
+            ("king_shelter", Value::Array(pair)) => {
+                weights.king_shelter = scaling_pair(pair).ok_or_else(invalid)?;
+            }
+            ("king_pressure", Value::Array(pair)) => {
+                weights.king_pressure = scaling_pair(pair).ok_or_else(invalid)?;
+            }
+            ("development", &Value::Number(development)) if development >= 0 => {
+                weights.development = development;
+            }
+            ("rim_knights", &Value::Number(rim_knights)) if rim_knights >= 0 => {
+                weights.rim_knights = rim_knights;
+            }
+            ("exact_mobility", &Value::Bool(exact)) => weights.exact_mobility = exact,
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(weights)
+}
+
+/// A per-color pair of scalings, neither of which may be negative.
+fn scaling_pair(values: &[i32]) -> Option<[i32; 2]> {
+    match *values {
+        [white, black] if white >= 0 && black >= 0 => Some([white, black]),
+        _ => None,
+    }
+}
+
+/// A reader for the small subset of JSON the weights are written in: a flat
+/// object whose values are integers, booleans or arrays of integers.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// How many characters have been read so far, for reporting errors.
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn malformed(&self) -> WeightsError {
+        WeightsError::Malformed(self.offset)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        self.offset += c.is_some() as usize;
+        c
+    }
+
+    /// Skip whitespace and return the next character without reading it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+        self.chars.peek().copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), WeightsError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            _ => Err(self.malformed()),
+        }
+    }
+
+    /// A whole object, with nothing but whitespace after it.
+    fn object(&mut self) -> Result<Vec<(String, Value)>, WeightsError> {
+        self.expect('{')?;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        if self.peek() != Some('}') {
+            loop {
+                let key = self.string()?;
+                if !KEYS.contains(&key.as_str()) {
+                    return Err(WeightsError::UnknownKey(key));
+                }
+                if fields.iter().any(|(name, _)| *name == key) {
+                    return Err(WeightsError::DuplicateKey(key));
+                }
+                self.expect(':')?;
+                let value = self.value()?;
+                fields.push((key, value));
+                if self.peek() != Some(',') {
+                    break;
+                }
+                self.next();
+            }
+        }
+        self.expect('}')?;
+        match self.peek() {
+            None => Ok(fields),
+            Some(_) => Err(self.malformed()),
+        }
+    }
+
+    /// A string without escapes, which none of the keys need.
+    fn string(&mut self) -> Result<String, WeightsError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') | None => return Err(self.malformed()),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, WeightsError> {
+        match self.peek() {
+            Some('[') => {
+                self.next();
+                let mut numbers = Vec::new();
+                if self.peek() != Some(']') {
+                    loop {
+                        numbers.push(self.number()?);
+                        if self.peek() != Some(',') {
+                            break;
+                        }
+                        self.next();
+                    }
+                }
+                self.expect(']')?;
+                Ok(Value::Array(numbers))
+            }
+            Some('t' | 'f') => {
+                let mut word = String::new();
+                while self.chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    word.extend(self.next());
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(self.malformed()),
+                }
+            }
+            _ => Ok(Value::Number(self.number()?)),
+        }
+    }
+
+    /// An integer, optionally negative.
+    fn number(&mut self) -> Result<i32, WeightsError> {
+        let mut digits = String::new();
+        if self.peek() == Some('-') {
+            digits.extend(self.next());
+        }
+        while self.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+            digits.extend(self.next());
+        }
+        digits.parse().map_err(|_| self.malformed())
+    }
+}
+
+/// Exposed to javascript to get the evaluation weights every search starts
+/// from, as a JSON object for a tuner to adjust and pass back to
+/// `set_eval_weights`. The weights are percentages of each term's normal
+/// weight: "king_shelter" and "king_pressure" (each a pair, White's then
+/// Black's), "development", "rim_knights", and "exact_mobility" for counting
+/// mobility from the legal moves.
+#[wasm_bindgen]
+pub fn get_eval_weights() -> String {
+    weights_to_json(&tuned_weights())
+}
+
+/// Exposed to javascript to set the evaluation weights every search starts
+/// from, given as JSON in the form `get_eval_weights` returns. Applies to every
+/// search from now on. Throws if the JSON is malformed, a weight is missing or
+/// unknown, or a value is invalid, leaving the weights unchanged.
+#[wasm_bindgen]
+pub fn set_eval_weights(json: &str) -> Result<(), InputError> {
+    let weights = weights_from_json(json, tuned_weights()).map_err(InputError::InvalidWeights)?;
+    TUNED_WEIGHTS.with(|tuned| tuned.set(weights));
+    Ok(())
+}
+
+// Writing the weights and reading them back gives the same weights, and
+// setting different ones changes the evaluation.
+#[test]
+fn weights_round_trip() {
+    let json = get_eval_weights();
+    set_eval_weights(&json).unwrap();
+    assert_eq!(get_eval_weights(), json);
+    assert_eq!(tuned_weights(), EvalWeights::default());
+
+    let fen = "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1";
+    let before = crate::evaluate(fen, 0).unwrap();
+    let heavier = json.replace("\"development\":100", "\"development\":300");
+    set_eval_weights(&heavier).unwrap();
+    assert_eq!(get_eval_weights(), heavier);
+    assert!(crate::evaluate(fen, 0).unwrap() > before);
+}
+
+// Malformed JSON and bad weights are rejected, leaving the weights unchanged.
+#[test]
+fn rejects_bad_weights() {
+    let json = get_eval_weights();
+    for bad in [
+        "",
+        "{",
+        "[]",
+        "{\"development\":100}",
+        "{\"development\":100,\"development\":100}",
+        "{\"king_shelter\":[100],\"king_pressure\":[100,100],\"development\":100,\"rim_knights\":100,\"exact_mobility\":false}",
+        "{\"king_shelter\":[100,100],\"king_pressure\":[100,100],\"development\":-1,\"rim_knights\":100,\"exact_mobility\":false}",
+        "{\"king_shelter\":[100,100],\"king_pressure\":[100,100],\"development\":100,\"rim_knights\":100,\"exact_mobility\":1}",
+        "{\"king_shelter\":[100,100],\"king_pressure\":[100,100],\"development\":100,\"rim_knights\":100,\"exact_mobility\":false,\"queen\":9}",
+        "{\"king_shelter\":[100,100],\"king_pressure\":[100,100],\"development\":100,\"rim_knights\":100,\"exact_mobility\":false} x",
+    ] {
+        assert!(set_eval_weights(bad).is_err(), "{}", bad);
+    }
+    assert_eq!(get_eval_weights(), json);
+}
@@ -0,0 +1,167 @@
+//! Zobrist keys telling positions apart, for repetitions and the
+//! transposition table. The search keeps the key up to date move by move,
+//! XORing out what a move changes and XORing in what replaces it, rather than
+//! hashing the whole board again at every node.
+
+use chess::{BitBoard, Board, ChessMove, Color, File, MoveGen, Piece, Square, ALL_PIECES, EMPTY};
+
+use crate::splitmix64;
+
+/// Fill a table with pseudo-random keys, each derived from the one before.
+const fn random_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut z = seed;
+    let mut i = 0;
+    while i < N {
+        z = splitmix64(z);
+        keys[i] = z;
+        i += 1;
+    }
+    keys
+}
+
+/// One key for each piece of each color on each square, indexed by the color,
+/// then the piece, then the square.
+const PIECE_KEYS: [u64; 768] = random_keys(1);
+
+/// One key for each color's castling rights, indexed by the color and then the
+/// rights' index.
+const CASTLING_KEYS: [u64; 8] = random_keys(2);
+
+/// One key for each file a usable en passant square can be on.
+const EN_PASSANT_KEYS: [u64; 8] = random_keys(3);
+
+/// The key hashed in when Black is to move.
+const BLACK_TO_MOVE: u64 = 0x5a17_bd3c_e4f0_9e61;
+
+fn piece_key(color: Color, piece: Piece, square: Square) -> u64 {
+    PIECE_KEYS[(color.to_index() * 6 + piece.to_index()) * 64 + square.to_index()]
+}
+
+fn castling_key(position: &Board) -> u64 {
+    [Color::White, Color::Black]
+        .iter()
+        .map(|&color| {
+            CASTLING_KEYS[color.to_index() * 4 + position.castle_rights(color).to_index()]
+        })
+        .fold(0, |key, part| key ^ part)
+}
+
+/// The key of the en passant square, or 0 when there is none or no en passant
+/// capture is actually legal (say the capturing pawn is pinned), as under the
+/// FIDE rules such a position is the same as one without it. Legality is only
+/// checked when a pawn stands next to the one that just moved two squares.
+fn en_passant_key(position: &Board) -> u64 {
+    let Some(pawn) = position.en_passant() else {
+        return 0;
+    };
+    let side = position.side_to_move();
+    let own_pawns = position.pieces(Piece::Pawn) & position.color_combined(side);
+    let beside = [pawn.left(), pawn.right()];
+    if !beside
+        .iter()
+        .flatten()
+        .any(|&square| own_pawns & BitBoard::from_square(square) != EMPTY)
+    {
+        return 0;
+    }
+
+    // Only an en passant capture takes a pawn to the square behind the pawn.
+    let Some(target) = pawn.forward(side) else {
+        return 0;
+    };
+    let mut moves = MoveGen::new_legal(position);
+    moves.set_iterator_mask(BitBoard::from_square(target));
+    if moves.any(|m| position.piece_on(m.get_source()) == Some(Piece::Pawn)) {
+        EN_PASSANT_KEYS[pawn.get_file().to_index()]
+    } else {
+        0
+    }
+}
+
+/// The key of a position, hashed from scratch.
+pub fn full_key(position: &Board) -> u64 {
+    let mut key = castling_key(position) ^ en_passant_key(position);
+    for color in [Color::White, Color::Black] {
+        for piece in ALL_PIECES {
+            for square in position.pieces(piece) & position.color_combined(color) {
+                key ^= piece_key(color, piece, square);
+            }
+        }
+    }
+    if position.side_to_move() == Color::Black {
+        key ^= BLACK_TO_MOVE;
+    }
+    key
+}
+
+/// The key of the position after a legal move, updated from `key`, the key of
+/// the position before it. `after` is the position the move leads to.
+pub fn updated_key(key: u64, position: &Board, m: ChessMove, after: &Board) -> u64 {
+    let side = position.side_to_move();
+    let (source, dest) = (m.get_source(), m.get_dest());
+    let Some(piece) = position.piece_on(source) else {
+        return full_key(after);
+    };
+
+    let mut key = key ^ piece_key(side, piece, source);
+    key ^= piece_key(side, m.get_promotion().unwrap_or(piece), dest);
+    if let Some(captured) = position.piece_on(dest) {
+        key ^= piece_key(!side, captured, dest);
+    } else if piece == Piece::Pawn && source.get_file() != dest.get_file() {
+        // En passant takes the pawn beside the capturing one.
+        let taken = Square::make_square(source.get_rank(), dest.get_file());
+        key ^= piece_key(!side, Piece::Pawn, taken);
+    }
+
+    // Castling moves the rook from the corner to the king's other side.
+    let distance = source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index());
+    if piece == Piece::King && distance == 2 {
+        let (from, to) = if dest.get_file() == File::G {
+            (File::H, File::F)
+        } else {
+            (File::A, File::D)
+        };
+        let rank = source.get_rank();
+        key ^= piece_key(side, Piece::Rook, Square::make_square(rank, from));
+        key ^= piece_key(side, Piece::Rook, Square::make_square(rank, to));
+    }
+
+    key ^= castling_key(position) ^ castling_key(after);
+    key ^= en_passant_key(position) ^ en_passant_key(after);
+    key ^ BLACK_TO_MOVE
+}
+
+// Along a random playout, with castling, en passant and promotions all
+// played, the key updated move by move always matches the key hashed from
+// scratch.
+#[test]
+fn updated_key_matches_full_key() {
+    use std::str::FromStr;
+
+    let starts = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPpP/R3K2R w KQkq - 0 1",
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+    ];
+    let mut z = 0;
+    for fen in starts {
+        let mut position = Board::from_str(fen).unwrap();
+        let mut key = full_key(&position);
+        for _ in 0..200 {
+            let moves: Vec<ChessMove> = MoveGen::new_legal(&position).collect();
+            if moves.is_empty() {
+                break;
+            }
+            z = splitmix64(z);
+            let m = moves[(z % moves.len() as u64) as usize];
+            let after = position.make_move_new(m);
+            key = updated_key(key, &position, m, &after);
+            assert_eq!(key, full_key(&after), "{} after {}", position, m);
+            position = after;
+        }
+    }
+}